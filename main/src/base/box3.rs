@@ -3,6 +3,7 @@ use glm::{DVec3};
 use std::fmt::*;
 
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Box3 {
     pub min: DVec3,
     pub max: DVec3,
@@ -34,6 +35,74 @@ impl Box3
             max: DVec3::new(f64::MIN, f64::MIN, f64::MIN)
         }
     }
+
+    /// Smallest box containing all the given points. Returns an unfitted box if there are no points.
+    pub fn from_points<'a, I: IntoIterator<Item = &'a DVec3>>(points: I) -> Self {
+        let mut bounds = Box3::unfitted();
+        for point in points {
+            bounds.encapsulate(point);
+        }
+        bounds
+    }
+
+    /// Grows the box so that it contains the given point
+    pub fn encapsulate(&mut self, point: &DVec3) {
+        self.min = glm::min2(&self.min, point);
+        self.max = glm::max2(&self.max, point);
+    }
+
+    /// False for unfitted boxes (min greater than max on any axis)
+    pub fn is_valid(&self) -> bool {
+        self.min.x <= self.max.x && self.min.y <= self.max.y && self.min.z <= self.max.z
+    }
+
+    pub fn center(&self) -> DVec3 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn size(&self) -> DVec3 {
+        self.max - self.min
+    }
+
+    pub fn volume(&self) -> f64 {
+        let size = self.size();
+        size.x * size.y * size.z
+    }
+
+    /// Smallest box containing both boxes
+    pub fn union(&self, other: &Box3) -> Box3 {
+        Box3 {
+            min: glm::min2(&self.min, &other.min),
+            max: glm::max2(&self.max, &other.max),
+        }
+    }
+
+    /// Overlapping region of both boxes, if any
+    pub fn intersection(&self, other: &Box3) -> Option<Box3> {
+        let intersection = Box3 {
+            min: glm::max2(&self.min, &other.min),
+            max: glm::min2(&self.max, &other.max),
+        };
+        if intersection.is_valid() {
+            Some(intersection)
+        } else {
+            None
+        }
+    }
+
+    pub fn intersects(&self, other: &Box3) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    /// True if the point is inside the box or on its boundary
+    pub fn contains(&self, point: &DVec3) -> bool {
+        point.x >= self.min.x && point.y >= self.min.y && point.z >= self.min.z
+        && point.x <= self.max.x && point.y <= self.max.y && point.z <= self.max.z
+    }
+
+    pub fn contains_box(&self, other: &Box3) -> bool {
+        self.contains(&other.min) && self.contains(&other.max)
+    }
 }
 
 impl Default for Box3 {
@@ -8,4 +8,16 @@ pub mod box3;
 pub use box3::Box3 as Box3; 
 
 pub mod symmetric_matrix;
-pub use symmetric_matrix::SymmetricMatrix as SymmetricMatrix;
\ No newline at end of file
+pub use symmetric_matrix::SymmetricMatrix as SymmetricMatrix;
+
+pub mod oriented_box3;
+pub use oriented_box3::OrientedBox3 as OrientedBox3;
+
+pub mod ray;
+pub use ray::Ray as Ray;
+
+pub mod sphere;
+pub use sphere::Sphere as Sphere;
+
+pub mod plane;
+pub use plane::Plane as Plane;
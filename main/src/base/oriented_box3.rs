@@ -0,0 +1,98 @@
+use nalgebra_glm as glm;
+use glm::{DVec3, DMat3};
+use super::Box3;
+use std::fmt::*;
+
+/// Box with arbitrary orientation. Columns of `axes` are the orthonormal local axes.
+#[derive(Debug, Copy, Clone)]
+pub struct OrientedBox3 {
+    pub center: DVec3,
+    pub axes: DMat3,
+    pub half_extents: DVec3,
+}
+
+impl OrientedBox3 {
+    pub fn new(center: DVec3, axes: DMat3, half_extents: DVec3) -> Self {
+        OrientedBox3 { center, axes, half_extents }
+    }
+
+    /// Tightest box around the points for the given orthonormal axes
+    pub fn fit(points: &[DVec3], axes: &DMat3) -> Self {
+        let local = Box3::from_points(points.iter().map(|p| axes.transpose() * p).collect::<Vec<_>>().iter());
+        if !local.is_valid() {
+            return OrientedBox3::new(DVec3::zeros(), *axes, DVec3::zeros());
+        }
+        OrientedBox3 {
+            center: axes * local.center(),
+            axes: *axes,
+            half_extents: local.size() / 2.0,
+        }
+    }
+
+    /// Box aligned with the principal axes of the point covariance.
+    /// The axis aligned box is returned instead when it happens to be smaller, so the result
+    /// is never worse than `Box3::from_points`.
+    pub fn from_points_pca(points: &[DVec3]) -> Self {
+        let aligned = OrientedBox3::fit(points, &DMat3::identity());
+        if points.len() < 3 {
+            return aligned;
+        }
+
+        let mean = points.iter().fold(DVec3::zeros(), |acc, p| acc + p) / points.len() as f64;
+        let mut covariance = DMat3::zeros();
+        for p in points {
+            let d = p - mean;
+            covariance += d * d.transpose();
+        }
+
+        let axes = orthonormalize(&covariance.symmetric_eigen().eigenvectors);
+        let pca = OrientedBox3::fit(points, &axes);
+
+        if pca.volume() < aligned.volume() {
+            pca
+        } else {
+            aligned
+        }
+    }
+
+    pub fn volume(&self) -> f64 {
+        8.0 * self.half_extents.x * self.half_extents.y * self.half_extents.z
+    }
+
+    pub fn contains(&self, point: &DVec3) -> bool {
+        let local = self.axes.transpose() * (point - self.center);
+        local.x.abs() <= self.half_extents.x && local.y.abs() <= self.half_extents.y && local.z.abs() <= self.half_extents.z
+    }
+
+    pub fn corners(&self) -> [DVec3; 8] {
+        let mut corners = [DVec3::zeros(); 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let sign = DVec3::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { -1.0 } else { 1.0 });
+            *corner = self.center + self.axes * sign.component_mul(&self.half_extents);
+        }
+        corners
+    }
+
+    /// Axis aligned box enclosing this oriented box
+    pub fn bounds(&self) -> Box3 {
+        Box3::from_points(self.corners().iter())
+    }
+}
+
+// Makes a right-handed orthonormal basis out of eigenvectors
+fn orthonormalize(m: &DMat3) -> DMat3 {
+    let x: DVec3 = m.column(0).normalize();
+    let mut y: DVec3 = m.column(1).into_owned();
+    y = (y - x * x.dot(&y)).normalize();
+    let z = x.cross(&y);
+    DMat3::from_columns(&[x, y, z])
+}
+
+impl Display for OrientedBox3 {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "<center:{} half extents:{}>", self.center, self.half_extents)
+    }
+}
@@ -0,0 +1,40 @@
+use nalgebra_glm as glm;
+use glm::{DVec3};
+use std::fmt::*;
+
+/// Plane of points p such that dot(normal, p) = distance
+#[derive(Debug, Copy, Clone)]
+pub struct Plane {
+    pub normal: DVec3,
+    pub distance: f64,
+}
+
+impl Plane {
+    pub fn new(point: &DVec3, normal: &DVec3) -> Self {
+        let normal = normal.normalize();
+        Plane { normal, distance: normal.dot(point) }
+    }
+
+    /// Positive on the side the normal points to
+    pub fn signed_distance(&self, point: &DVec3) -> f64 {
+        self.normal.dot(point) - self.distance
+    }
+
+    pub fn project(&self, point: &DVec3) -> DVec3 {
+        point - self.normal * self.signed_distance(point)
+    }
+
+    /// Orthonormal vectors (u, v) spanning the plane, such that u × v = normal
+    pub fn basis(&self) -> (DVec3, DVec3) {
+        let helper = if self.normal.x.abs() < 0.9 { DVec3::x() } else { DVec3::y() };
+        let u = helper.cross(&self.normal).normalize();
+        let v = self.normal.cross(&u);
+        (u, v)
+    }
+}
+
+impl Display for Plane {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "<normal:{} distance:{}>", self.normal, self.distance)
+    }
+}
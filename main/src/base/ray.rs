@@ -0,0 +1,26 @@
+use nalgebra_glm as glm;
+use glm::{DVec3};
+use std::fmt::*;
+
+#[derive(Debug, Copy, Clone)]
+pub struct Ray {
+    pub origin: DVec3,
+    /// Expected to be normalized, so that hit distances are in world units
+    pub direction: DVec3,
+}
+
+impl Ray {
+    pub fn new(origin: DVec3, direction: DVec3) -> Self {
+        Ray { origin, direction: direction.normalize() }
+    }
+
+    pub fn at(&self, distance: f64) -> DVec3 {
+        self.origin + self.direction * distance
+    }
+}
+
+impl Display for Ray {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "<origin:{} direction:{}>", self.origin, self.direction)
+    }
+}
@@ -0,0 +1,31 @@
+use nalgebra_glm as glm;
+use glm::{DVec3};
+use super::Box3;
+use std::fmt::*;
+
+#[derive(Debug, Copy, Clone)]
+pub struct Sphere {
+    pub center: DVec3,
+    pub radius: f64,
+}
+
+impl Sphere {
+    pub fn new(center: DVec3, radius: f64) -> Self {
+        Sphere { center, radius }
+    }
+
+    pub fn contains(&self, point: &DVec3) -> bool {
+        (point - self.center).magnitude_squared() <= self.radius * self.radius
+    }
+
+    pub fn bounds(&self) -> Box3 {
+        let extent = DVec3::new(self.radius, self.radius, self.radius);
+        Box3::new(self.center - extent, self.center + extent)
+    }
+}
+
+impl Display for Sphere {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "<center:{} radius:{}>", self.center, self.radius)
+    }
+}
@@ -98,6 +98,15 @@ impl Add<&SymmetricMatrix> for &SymmetricMatrix {
     }
 }
 
+impl Mul<f64> for SymmetricMatrix {
+    type Output = SymmetricMatrix;
+    fn mul(self, weight: f64) -> SymmetricMatrix {
+        SymmetricMatrix {
+            m: self.m.map(|x| x * weight)
+        }
+    }
+}
+
 impl Display for SymmetricMatrix {
     fn fmt(&self, f: &mut Formatter) -> Result {
         write!(f, "<{} {} {} {} | {} {} {} | {} {} | {}>", self.m[0], self.m[1], self.m[2], self.m[3], self.m[4], self.m[5], self.m[6], self.m[7], self.m[8], self.m[9])
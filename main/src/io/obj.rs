@@ -1,10 +1,12 @@
 use nalgebra_glm as glm;
 use glm::{DVec3, U32Vec3};
 use super::super::mesh::SharedMesh;
+use super::super::progress::{Cancelled, Progress};
 
 use std::io::BufWriter;
 use std::io::BufReader;
 use std::io::prelude::*;
+use std::io::SeekFrom;
 
 pub fn read<T: Read>(reader: &mut BufReader<T>) -> SharedMesh {
 
@@ -13,29 +15,67 @@ pub fn read<T: Read>(reader: &mut BufReader<T>) -> SharedMesh {
 
     for line in reader.lines() {
         if let Ok(l) = line {
-            let split = l.split(" ").collect::<Vec<&str>>();
-            match split[0] {
-                "v" => {
-                    let position = DVec3::new(split[1].parse::<f64>().unwrap(), split[2].parse::<f64>().unwrap(), split[3].parse::<f64>().unwrap());
-                    positions.push(position);
-                },
-                "f" => {
-                    triangles.push(U32Vec3::new(
-                        split[1].parse::<u32>().unwrap() - 1,
-                        split[2].parse::<u32>().unwrap() - 1,
-                        split[3].parse::<u32>().unwrap() - 1));
-                },
-                _ => ()
-            }
+            read_line(&l, &mut positions, &mut triangles);
+        }
+    }
+
+    shared_mesh(positions, triangles)
+}
+
+/// Reads the mesh as `read` does, reporting the fraction of the stream read so far
+pub fn read_with_progress<T: Read + Seek>(reader: &mut BufReader<T>, progress: &mut dyn Progress) -> std::result::Result<SharedMesh, Cancelled> {
+
+    // The length is only needed to report progress, so it is left unknown when seeking fails
+    let length = reader.stream_position().and_then(|start| {
+        let end = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(start))?;
+        Ok(end.saturating_sub(start))
+    }).unwrap_or(0);
+
+    let mut positions = Vec::<DVec3>::new();
+    let mut triangles = Vec::<U32Vec3>::new();
+    let mut read_bytes = 0u64;
+
+    for (i, line) in reader.lines().enumerate() {
+        if let Ok(l) = line {
+            read_bytes += l.len() as u64 + 1;
+            read_line(&l, &mut positions, &mut triangles);
+        }
+        if i % 4096 == 0 && !progress.report((read_bytes as f32 / length.max(1) as f32).min(1.0)) {
+            return Err(Cancelled);
         }
     }
 
+    // Reading is done, so cancelling no longer has any effect
+    progress.report(1.0);
+    Ok(shared_mesh(positions, triangles))
+}
+
+fn read_line(l: &str, positions: &mut Vec<DVec3>, triangles: &mut Vec<U32Vec3>) {
+    let split = l.split(" ").collect::<Vec<&str>>();
+    match split[0] {
+        "v" => {
+            let position = DVec3::new(split[1].parse::<f64>().unwrap(), split[2].parse::<f64>().unwrap(), split[3].parse::<f64>().unwrap());
+            positions.push(position);
+        },
+        "f" => {
+            triangles.push(U32Vec3::new(
+                split[1].parse::<u32>().unwrap() - 1,
+                split[2].parse::<u32>().unwrap() - 1,
+                split[3].parse::<u32>().unwrap() - 1));
+        },
+        _ => ()
+    }
+}
+
+fn shared_mesh(positions: Vec<DVec3>, triangles: Vec<U32Vec3>) -> SharedMesh {
     SharedMesh {
         groups: Vec::new(),
         triangles: triangles,
         positions: positions,
         normals: None,
         colors: None,
+        uvs: None,
     }
 }
 
@@ -63,4 +103,44 @@ pub fn write<T: Write>(shared_mesh: &SharedMesh, writer: &mut BufWriter<T>) {
         let triangle = shared_mesh.triangles[i];
         write!("f {} {} {}", triangle[0] + 1, triangle[1] + 1, triangle[2] + 1);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod obj_tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn read_with_progress() {
+        let text = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n".repeat(5000);
+
+        let mut reported = Vec::new();
+        let mesh = super::read_with_progress(&mut BufReader::new(Cursor::new(text.as_bytes())), &mut |p: f32| { reported.push(p); true }).unwrap();
+        assert_eq!(mesh.triangles.len(), 5000);
+        assert_eq!(mesh.positions.len(), 15000);
+        assert!(reported.len() > 2 && reported.windows(2).all(|w| w[0] <= w[1]) && reported.iter().all(|p| *p <= 1.0));
+
+        let cancelled = super::read_with_progress(&mut BufReader::new(Cursor::new(text.as_bytes())), &mut |p: f32| p < 0.5);
+        assert_eq!(cancelled.err(), Some(Cancelled));
+
+        let done = super::read_with_progress(&mut BufReader::new(Cursor::new(text.as_bytes())), &mut |p: f32| p < 1.0);
+        assert_eq!(done.unwrap().triangles.len(), 5000);
+
+        // Streams that can't seek are read without knowing their length
+        struct Unseekable<'a>(&'a [u8]);
+        impl Read for Unseekable<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.0.read(buf)
+            }
+        }
+        impl Seek for Unseekable<'_> {
+            fn seek(&mut self, _: SeekFrom) -> std::io::Result<u64> {
+                Err(std::io::ErrorKind::Unsupported.into())
+            }
+        }
+        let mut reported = Vec::new();
+        let mesh = super::read_with_progress(&mut BufReader::new(Unseekable(text.as_bytes())), &mut |p: f32| { reported.push(p); true }).unwrap();
+        assert_eq!(mesh.triangles.len(), 5000);
+        assert!(reported.iter().all(|p| *p <= 1.0));
+    }
+}
@@ -3,5 +3,7 @@ include!("utils/macros.rs");
 pub mod base;
 pub mod utils;
 pub mod mesh;
+pub mod spatial;
 pub mod io;
-pub mod scene;
\ No newline at end of file
+pub mod scene;
+pub use nanomesh_progress as progress;
\ No newline at end of file
@@ -0,0 +1,53 @@
+use nalgebra_glm as glm;
+use glm::{DVec3, U32Vec3};
+use super::super::utils::SimpleHasher;
+
+use std::hash::BuildHasherDefault;
+use hashbrown::HashMap;
+
+pub(crate) type EdgeMap<T> = HashMap<u64, T, BuildHasherDefault<SimpleHasher>>;
+
+// Packs an undirected edge into a single u64 so that it can be hashed with SimpleHasher
+#[inline]
+pub(crate) fn edge_key(a: u32, b: u32) -> u64 {
+    if a < b {
+        (a as u64) << 32 | b as u64
+    } else {
+        (b as u64) << 32 | a as u64
+    }
+}
+
+/// Maps each vertex to the first vertex sharing the exact same position.
+/// SharedMesh vertices are split by attributes (normals, colors), so this is required
+/// to recover the topology of the underlying surface.
+pub(crate) fn weld_positions(positions: &[DVec3]) -> Vec<u32> {
+    let mut first_at_position = HashMap::<[u64; 3], u32>::with_capacity(positions.len());
+    let mut welded = Vec::with_capacity(positions.len());
+    for (i, p) in positions.iter().enumerate() {
+        let key = [p.x.to_bits(), p.y.to_bits(), p.z.to_bits()];
+        welded.push(*first_at_position.entry(key).or_insert(i as u32));
+    }
+    welded
+}
+
+/// For each undirected edge (on welded vertices), the list of triangles using it
+pub(crate) fn edge_faces(triangles: &[U32Vec3], welded: &[u32]) -> EdgeMap<Vec<u32>> {
+    let mut edges = EdgeMap::<Vec<u32>>::with_capacity_and_hasher(triangles.len() * 3 / 2, Default::default());
+    for (f, t) in triangles.iter().enumerate() {
+        for k in 0..3 {
+            let a = welded[t[k] as usize];
+            let b = welded[t[(k + 1) % 3] as usize];
+            if a == b {
+                continue;
+            }
+            edges.entry(edge_key(a, b)).or_insert_with(Vec::new).push(f as u32);
+        }
+    }
+    edges
+}
+
+/// Returns true if the triangle walks the edge from a to b (on welded vertices)
+#[inline]
+pub(crate) fn walks_edge(triangle: &U32Vec3, welded: &[u32], a: u32, b: u32) -> bool {
+    (0..3).any(|k| welded[triangle[k] as usize] == a && welded[triangle[(k + 1) % 3] as usize] == b)
+}
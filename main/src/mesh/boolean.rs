@@ -0,0 +1,748 @@
+// Boolean operations between closed meshes: union, difference and intersection.
+// Triangles of both meshes are intersected pairwise with exact orientation predicates. Intersection points are
+// identified by the features producing them (an edge crossing a face, two crossing edges, or a vertex), so that
+// triangles sharing an edge split it at the very same points and the result stays watertight.
+// Cut triangles are re-triangulated with their intersection segments as constraints, then every region of pieces
+// delimited by intersection curves is kept or dropped depending on where it lies relatively to the other mesh.
+
+use nalgebra_glm as glm;
+use glm::{DVec2, DVec3, U32Vec3};
+use hashbrown::{HashMap, HashSet};
+use geometry_predicates::{orient2d, orient3d};
+use super::{SharedMesh, Group, adjacency};
+use super::super::base::{Box3, Ray};
+use super::super::spatial::Bvh;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BooleanOperation {
+    /// Volume covered by any of the meshes
+    Union,
+    /// Volume of the first mesh that is not covered by the second one
+    Difference,
+    /// Volume covered by both meshes
+    Intersection,
+}
+
+impl SharedMesh {
+    /// Boolean operation between two closed and outward oriented meshes.
+    /// Groups of `self` come first in the result, followed by groups of `other`.
+    /// Normals, colors and texture coordinates are interpolated on cut triangles. Where a mesh has no normals, face
+    /// normals are used, where it has no colors while the other one has, white is used, and where it has no texture
+    /// coordinates while the other one has, null ones are used.
+    pub fn boolean(&self, other: &SharedMesh, operation: BooleanOperation) -> Result<SharedMesh, cdt::Error> {
+        let meshes = [self, other];
+        let mut arrangement = Arrangement::new(self, other);
+        arrangement.intersect();
+        let (pieces, cuts) = arrangement.pieces()?;
+        let locations = arrangement.locate_pieces(&pieces, &cuts, meshes);
+        Ok(arrangement.build(&pieces, &locations, operation, meshes))
+    }
+
+    pub fn union(&self, other: &SharedMesh) -> Result<SharedMesh, cdt::Error> {
+        self.boolean(other, BooleanOperation::Union)
+    }
+
+    pub fn difference(&self, other: &SharedMesh) -> Result<SharedMesh, cdt::Error> {
+        self.boolean(other, BooleanOperation::Difference)
+    }
+
+    pub fn intersection(&self, other: &SharedMesh) -> Result<SharedMesh, cdt::Error> {
+        self.boolean(other, BooleanOperation::Intersection)
+    }
+}
+
+// Intersection points that are not vertices, named after the features they come from
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum PointKey {
+    // Edge crossing the plane of a face
+    EdgeFace(u64, u32),
+    // Two crossing edges, smallest key first
+    EdgeEdge(u64, u64),
+}
+
+// Where a piece lies relatively to the other mesh
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Location {
+    Outside,
+    Inside,
+    // On the surface of the other mesh, facing the same direction
+    SameSurface,
+    // On the surface of the other mesh, facing the opposite direction
+    OppositeSurface,
+}
+
+// Where a point lies relatively to a triangle, in 2D
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum TrianglePoint {
+    Inside,
+    Edge(usize),
+    Vertex,
+}
+
+// Triangle of the re-triangulated meshes, on points
+struct Piece {
+    corners: [u32; 3],
+    // Source triangle, in the arrangement
+    triangle: u32,
+}
+
+struct Arrangement {
+    // Triangles of both meshes, as points. Triangles of the second mesh come after those of the first one.
+    triangles: Vec<[u32; 3]>,
+    first_count: usize,
+    // Vertices at the same position share a point, intersection points come after them
+    points: Vec<DVec3>,
+    point_at: HashMap<[u64; 3], u32>,
+    point_keys: HashMap<PointKey, u32>,
+    // Intersection points lying inside of each edge
+    edge_points: adjacency::EdgeMap<Vec<u32>>,
+    triangle_points: Vec<Vec<u32>>,
+    triangle_segments: Vec<Vec<(u32, u32)>>,
+}
+
+impl Arrangement {
+    fn new(first: &SharedMesh, second: &SharedMesh) -> Self {
+        let mut arrangement = Arrangement {
+            triangles: Vec::with_capacity(first.triangles.len() + second.triangles.len()),
+            first_count: first.triangles.len(),
+            points: Vec::new(),
+            point_at: HashMap::new(),
+            point_keys: HashMap::new(),
+            edge_points: adjacency::EdgeMap::default(),
+            triangle_points: vec![Vec::new(); first.triangles.len() + second.triangles.len()],
+            triangle_segments: vec![Vec::new(); first.triangles.len() + second.triangles.len()],
+        };
+        for mesh in [first, second] {
+            let vertex_points: Vec<u32> = mesh.positions.iter().map(|p| arrangement.add_point(p)).collect();
+            arrangement.triangles.extend(mesh.triangles.iter().map(|t| [0, 1, 2].map(|k| vertex_points[t[k] as usize])));
+        }
+        arrangement
+    }
+
+    fn add_point(&mut self, position: &DVec3) -> u32 {
+        let points = &mut self.points;
+        *self.point_at.entry([position.x.to_bits(), position.y.to_bits(), position.z.to_bits()]).or_insert_with(|| {
+            points.push(*position);
+            points.len() as u32 - 1
+        })
+    }
+
+    fn coordinates(&self, point: u32) -> [f64; 3] {
+        let p = &self.points[point as usize];
+        [p.x, p.y, p.z]
+    }
+
+    fn normal(&self, triangle: usize) -> DVec3 {
+        let [a, b, c] = self.triangles[triangle].map(|p| self.points[p as usize]);
+        (b - a).cross(&(c - a))
+    }
+
+    fn is_degenerate(&self, triangle: usize) -> bool {
+        let [a, b, c] = self.triangles[triangle];
+        a == b || b == c || c == a || self.normal(triangle).magnitude_squared() == 0.0
+    }
+
+    fn add_edge_point(&mut self, a: u32, b: u32, point: u32) {
+        if point != a && point != b {
+            self.edge_points.entry(adjacency::edge_key(a, b)).or_default().push(point);
+        }
+    }
+
+    // Point where the edge crosses the plane of the face, computed from the edge in a canonical direction
+    fn edge_face_point(&mut self, a: u32, b: u32, face: usize) -> u32 {
+        let key = PointKey::EdgeFace(adjacency::edge_key(a, b), face as u32);
+        if let Some(point) = self.point_keys.get(&key) {
+            return *point;
+        }
+        let (a, b) = (a.min(b), a.max(b));
+        let (pa, pb) = (self.points[a as usize], self.points[b as usize]);
+        let normal = self.normal(face);
+        let origin = self.points[self.triangles[face][0] as usize];
+        let t = normal.dot(&(origin - pa)) / normal.dot(&(pb - pa));
+        let point = self.add_point(&pa.lerp(&pb, t.clamp(0.0, 1.0)));
+        self.point_keys.insert(key, point);
+        self.add_edge_point(a, b, point);
+        point
+    }
+
+    // Point where two edges cross, computed on the edge of smallest key
+    fn edge_edge_point(&mut self, a: u32, b: u32, c: u32, d: u32) -> u32 {
+        let (first, second) = (adjacency::edge_key(a, b), adjacency::edge_key(c, d));
+        let key = PointKey::EdgeEdge(first.min(second), first.max(second));
+        if let Some(point) = self.point_keys.get(&key) {
+            return *point;
+        }
+        let ((a, b), (c, d)) = if first < second { ((a.min(b), a.max(b)), (c, d)) } else { ((c.min(d), c.max(d)), (a, b)) };
+        let (pa, pb) = (self.points[a as usize], self.points[b as usize]);
+        let (pc, pd) = (self.points[c as usize], self.points[d as usize]);
+        // Closest point of the first line to the second one
+        let (u, v, w) = (pb - pa, pd - pc, pa - pc);
+        let (uu, uv, vv, uw, vw) = (u.dot(&u), u.dot(&v), v.dot(&v), u.dot(&w), v.dot(&w));
+        let denominator = uu * vv - uv * uv;
+        let t = if denominator != 0.0 { (uv * vw - vv * uw) / denominator } else { 0.5 };
+        let point = self.add_point(&pa.lerp(&pb, t.clamp(0.0, 1.0)));
+        self.point_keys.insert(key, point);
+        self.add_edge_point(a, b, point);
+        self.add_edge_point(c, d, point);
+        point
+    }
+
+    fn add_segment(&mut self, triangles: &[usize], found: &mut Vec<u32>, direction: &DVec3) {
+        found.sort_unstable();
+        found.dedup();
+        if found.is_empty() {
+            return;
+        }
+        // Points are exact but for their coordinates: more than two of them only happen with rounding, keep the extremes
+        let along = |p: &u32| direction.dot(&self.points[*p as usize]);
+        let first = *found.iter().min_by(|a, b| along(a).partial_cmp(&along(b)).unwrap()).unwrap();
+        let last = *found.iter().max_by(|a, b| along(a).partial_cmp(&along(b)).unwrap()).unwrap();
+        for t in triangles {
+            self.triangle_points[*t].push(first);
+            if first != last {
+                self.triangle_points[*t].push(last);
+                self.triangle_segments[*t].push((first, last));
+            }
+        }
+    }
+
+    // Finds the intersection points and segments of all pairs of overlapping triangles
+    fn intersect(&mut self) {
+        let second: Vec<[DVec3; 3]> = self.triangles[self.first_count..].iter()
+            .map(|t| t.map(|p| self.points[p as usize]))
+            .collect();
+        let bvh = Bvh::from_triangles(second);
+        let margin = Box3::from_points(self.points.iter()).diagonal() * 1e-9;
+        for a in 0..self.first_count {
+            let mut bounds = Box3::from_points(self.triangles[a].iter().map(|p| &self.points[*p as usize]));
+            bounds.min.add_scalar_mut(-margin);
+            bounds.max.add_scalar_mut(margin);
+            for b in bvh.overlap_box(&bounds) {
+                self.intersect_pair(a, self.first_count + b as usize);
+            }
+        }
+    }
+
+    fn intersect_pair(&mut self, a: usize, b: usize) {
+        if self.is_degenerate(a) || self.is_degenerate(b) {
+            return;
+        }
+        let [pa, pb] = [a, b].map(|t| self.triangles[t].map(|p| self.coordinates(p)));
+        let sides_a = pa.map(|p| sign(orient3d(pb[0], pb[1], pb[2], p)));
+        let sides_b = pb.map(|p| sign(orient3d(pa[0], pa[1], pa[2], p)));
+
+        if sides_a.iter().all(|s| *s == 0) {
+            self.insert_coplanar(a, b);
+            self.insert_coplanar(b, a);
+            return;
+        }
+        let is_separated = |sides: &[i32; 3]| sides.iter().all(|s| *s > 0) || sides.iter().all(|s| *s < 0);
+        if is_separated(&sides_a) || is_separated(&sides_b) {
+            return;
+        }
+
+        let mut found = Vec::new();
+        self.crossings(a, b, &sides_a, &mut found);
+        self.crossings(b, a, &sides_b, &mut found);
+        let direction = self.normal(a).cross(&self.normal(b));
+        self.add_segment(&[a, b], &mut found, &direction);
+    }
+
+    // Points where triangle x meets the plane of triangle y, inside of triangle y
+    fn crossings(&mut self, x: usize, y: usize, sides: &[i32; 3], found: &mut Vec<u32>) {
+        let (xc, yc) = (self.triangles[x], self.triangles[y]);
+        let yp = yc.map(|p| self.coordinates(p));
+        let axis = dominant_axis(&self.normal(y));
+        let y2 = yc.map(|p| project(&self.points[p as usize], axis));
+
+        for k in 0..3 {
+            if sides[k] == 0 {
+                match locate(&y2, &project(&self.points[xc[k] as usize], axis)) {
+                    Some(TrianglePoint::Edge(i)) => {
+                        self.add_edge_point(yc[i], yc[(i + 1) % 3], xc[k]);
+                        found.push(xc[k]);
+                    }
+                    Some(_) => found.push(xc[k]),
+                    None => {}
+                }
+            }
+
+            let (p, q) = (xc[k], xc[(k + 1) % 3]);
+            if sides[k] * sides[(k + 1) % 3] >= 0 {
+                continue;
+            }
+            // The edge crosses the plane: side of the edge line relatively to each edge of y
+            let (pp, pq) = (self.coordinates(p), self.coordinates(q));
+            let o = [0, 1, 2].map(|i| sign(orient3d(pp, pq, yp[i], yp[(i + 1) % 3])));
+            if o.iter().any(|s| *s > 0) && o.iter().any(|s| *s < 0) {
+                continue;
+            }
+            let point = match o.iter().filter(|s| **s == 0).count() {
+                0 => self.edge_face_point(p, q, y),
+                1 => {
+                    let i = o.iter().position(|s| *s == 0).unwrap();
+                    self.edge_edge_point(p, q, yc[i], yc[(i + 1) % 3])
+                }
+                _ => {
+                    // Through the vertex that is not on the only edge with a non zero orientation
+                    let i = o.iter().position(|s| *s != 0).unwrap();
+                    let vertex = yc[(i + 2) % 3];
+                    self.add_edge_point(p, q, vertex);
+                    vertex
+                }
+            };
+            found.push(point);
+        }
+    }
+
+    // Inserts the parts of the edges of triangle y lying inside of the coplanar triangle x
+    fn insert_coplanar(&mut self, x: usize, y: usize) {
+        let (xc, yc) = (self.triangles[x], self.triangles[y]);
+        let axis = dominant_axis(&self.normal(x));
+        let x2 = xc.map(|p| project(&self.points[p as usize], axis));
+
+        for j in 0..3 {
+            let (u, v) = (yc[j], yc[(j + 1) % 3]);
+            let (u2, v2) = (project(&self.points[u as usize], axis), project(&self.points[v as usize], axis));
+            let mut found = Vec::new();
+            for w in [u, v] {
+                match locate(&x2, &project(&self.points[w as usize], axis)) {
+                    Some(TrianglePoint::Edge(i)) => {
+                        self.add_edge_point(xc[i], xc[(i + 1) % 3], w);
+                        found.push(w);
+                    }
+                    Some(_) => found.push(w),
+                    None => {}
+                }
+            }
+            for i in 0..3 {
+                let (a, b) = (xc[i], xc[(i + 1) % 3]);
+                let (a2, b2) = (x2[i], x2[(i + 1) % 3]);
+                if sign(orient2d(u2, v2, a2)) == 0 && is_between(&u2, &v2, &a2) {
+                    self.add_edge_point(u, v, a);
+                    found.push(a);
+                }
+                let crosses = sign(orient2d(a2, b2, u2)) * sign(orient2d(a2, b2, v2)) < 0
+                    && sign(orient2d(u2, v2, a2)) * sign(orient2d(u2, v2, b2)) < 0;
+                if crosses {
+                    found.push(self.edge_edge_point(a, b, u, v));
+                }
+            }
+            let direction = self.points[v as usize] - self.points[u as usize];
+            self.add_segment(&[x], &mut found, &direction);
+        }
+    }
+
+    // Re-triangulates cut triangles. Also returns the edges of pieces lying on intersection curves.
+    fn pieces(&self) -> Result<(Vec<Piece>, HashSet<u64>), cdt::Error> {
+        let mut pieces = Vec::with_capacity(self.triangles.len());
+        let mut cuts = HashSet::new();
+
+        for t in 0..self.triangles.len() {
+            if self.is_degenerate(t) {
+                continue;
+            }
+            let corners = self.triangles[t];
+            let mut points: Vec<u32> = self.triangle_points[t].clone();
+            for k in 0..3 {
+                if let Some(edge_points) = self.edge_points.get(&adjacency::edge_key(corners[k], corners[(k + 1) % 3])) {
+                    points.extend_from_slice(edge_points);
+                }
+            }
+            points.sort_unstable();
+            points.dedup();
+            points.retain(|p| !corners.contains(p));
+            if points.is_empty() && self.triangle_segments[t].is_empty() {
+                pieces.push(Piece { corners, triangle: t as u32 });
+                continue;
+            }
+
+            let local: Vec<u32> = corners.iter().chain(points.iter()).copied().collect();
+            let index: HashMap<u32, usize> = local.iter().enumerate().map(|(i, p)| (*p, i)).collect();
+            let axis = dominant_axis(&self.normal(t));
+            let points_2d: Vec<[f64; 2]> = local.iter().map(|p| project(&self.points[*p as usize], axis)).collect();
+
+            // Boundary of the triangle, through the points inserted on its edges
+            let mut boundary = Vec::new();
+            for k in 0..3 {
+                let (a, b) = (corners[k], corners[(k + 1) % 3]);
+                let mut on_edge: Vec<usize> = self.edge_points.get(&adjacency::edge_key(a, b))
+                    .map(|edge_points| edge_points.iter().map(|p| index[p]).collect())
+                    .unwrap_or_default();
+                on_edge.extend(points_on_segment(&points_2d, k, (k + 1) % 3));
+                boundary.extend(chain(&points_2d, k, (k + 1) % 3, on_edge));
+            }
+            let boundary_keys: HashSet<(usize, usize)> = boundary.iter().map(|(a, b)| ((*a).min(*b), (*a).max(*b))).collect();
+
+            // Constraints are toggled each time they are given: interior ones are given twice so that the
+            // triangulation doesn't consider them as boundaries
+            let mut edges = boundary.clone();
+            let mut interior = HashSet::new();
+            for (a, b) in &self.triangle_segments[t] {
+                let (a, b) = (index[a], index[b]);
+                for (c, d) in chain(&points_2d, a, b, points_on_segment(&points_2d, a, b)) {
+                    cuts.insert(adjacency::edge_key(local[c], local[d]));
+                    let key = (c.min(d), c.max(d));
+                    if !boundary_keys.contains(&key) && interior.insert(key) {
+                        edges.push(key);
+                        edges.push(key);
+                    }
+                }
+            }
+
+            let points_2d_tuples: Vec<(f64, f64)> = points_2d.iter().map(|p| (p[0], p[1])).collect();
+            let orientation = sign(orient2d(points_2d[0], points_2d[1], points_2d[2]));
+            for (a, b, c) in cdt::triangulate_with_edges(&points_2d_tuples, &edges)? {
+                match sign(orient2d(points_2d[a], points_2d[b], points_2d[c])) * orientation {
+                    1 => pieces.push(Piece { corners: [local[a], local[b], local[c]], triangle: t as u32 }),
+                    -1 => pieces.push(Piece { corners: [local[a], local[c], local[b]], triangle: t as u32 }),
+                    _ => {}
+                }
+            }
+        }
+
+        Ok((pieces, cuts))
+    }
+
+    // Locates each region of pieces delimited by intersection curves relatively to the other mesh
+    fn locate_pieces(&self, pieces: &[Piece], cuts: &HashSet<u64>, meshes: [&SharedMesh; 2]) -> Vec<Location> {
+        let mesh_of = |piece: &Piece| (piece.triangle as usize >= self.first_count) as usize;
+        let mut edge_pieces = adjacency::EdgeMap::<Vec<u32>>::default();
+        for (i, piece) in pieces.iter().enumerate() {
+            for k in 0..3 {
+                let key = adjacency::edge_key(piece.corners[k], piece.corners[(k + 1) % 3]);
+                if !cuts.contains(&key) {
+                    edge_pieces.entry(key).or_default().push(i as u32);
+                }
+            }
+        }
+
+        let bvhs = [Bvh::new(meshes[0]), Bvh::new(meshes[1])];
+        let epsilon = Box3::from_points(self.points.iter()).diagonal() * 1e-9;
+        let mut locations = vec![Location::Outside; pieces.len()];
+        let mut is_visited = vec![false; pieces.len()];
+        for start in 0..pieces.len() {
+            if is_visited[start] {
+                continue;
+            }
+            let mesh = mesh_of(&pieces[start]);
+            let mut region = vec![start];
+            let mut stack = vec![start];
+            is_visited[start] = true;
+            while let Some(i) = stack.pop() {
+                let corners = &pieces[i].corners;
+                for k in 0..3 {
+                    let key = adjacency::edge_key(corners[k], corners[(k + 1) % 3]);
+                    for neighbor in edge_pieces.get(&key).into_iter().flatten() {
+                        let neighbor = *neighbor as usize;
+                        if !is_visited[neighbor] && mesh_of(&pieces[neighbor]) == mesh {
+                            is_visited[neighbor] = true;
+                            region.push(neighbor);
+                            stack.push(neighbor);
+                        }
+                    }
+                }
+            }
+
+            // The largest piece is the least sensitive to rounding
+            let area = |i: &usize| self.piece_normal(&pieces[*i]).magnitude();
+            let representative = *region.iter().max_by(|a, b| area(a).partial_cmp(&area(b)).unwrap()).unwrap();
+            let location = locate_point(
+                &bvhs[1 - mesh],
+                meshes[1 - mesh],
+                &self.piece_centroid(&pieces[representative]),
+                &self.piece_normal(&pieces[representative]),
+                epsilon);
+            for i in region {
+                locations[i] = location;
+            }
+        }
+        locations
+    }
+
+    fn piece_normal(&self, piece: &Piece) -> DVec3 {
+        let [a, b, c] = piece.corners.map(|p| self.points[p as usize]);
+        (b - a).cross(&(c - a))
+    }
+
+    fn piece_centroid(&self, piece: &Piece) -> DVec3 {
+        piece.corners.iter().map(|p| self.points[*p as usize]).sum::<DVec3>() / 3.0
+    }
+
+    fn build(&self, pieces: &[Piece], locations: &[Location], operation: BooleanOperation, meshes: [&SharedMesh; 2]) -> SharedMesh {
+        let normals = meshes.map(|mesh| mesh.normals.as_ref().filter(|normals| normals.len() == mesh.positions.len()));
+        let colors = meshes.map(|mesh| mesh.colors.as_ref().filter(|colors| colors.len() == mesh.positions.len()));
+        let has_normals = normals.iter().any(|n| n.is_some());
+        let uvs = meshes.map(|mesh| mesh.uvs.as_ref().filter(|uvs| uvs.len() == mesh.positions.len()));
+        let has_colors = colors.iter().any(|c| c.is_some());
+        let has_uvs = uvs.iter().any(|u| u.is_some());
+        let triangle_groups = meshes.map(|mesh| mesh.triangle_groups());
+
+        let mut result = SharedMesh {
+            groups: Vec::new(),
+            triangles: Vec::new(),
+            positions: Vec::new(),
+            normals: if has_normals { Some(Vec::new()) } else { None },
+            colors: if has_colors { Some(Vec::new()) } else { None },
+            uvs: if has_uvs { Some(Vec::new()) } else { None },
+        };
+        let mut vertices = HashMap::<[u64; 11], u32>::new();
+        let mut last_group = u32::MAX;
+
+        for (piece, location) in pieces.iter().zip(locations) {
+            let mesh = (piece.triangle as usize >= self.first_count) as usize;
+            // Whether the piece is kept, and flipped
+            let flip = match (operation, mesh, location) {
+                (BooleanOperation::Union, 0, Location::Outside | Location::SameSurface) => false,
+                (BooleanOperation::Union, 1, Location::Outside) => false,
+                (BooleanOperation::Intersection, 0, Location::Inside | Location::SameSurface) => false,
+                (BooleanOperation::Intersection, 1, Location::Inside) => false,
+                (BooleanOperation::Difference, 0, Location::Outside | Location::OppositeSurface) => false,
+                (BooleanOperation::Difference, 1, Location::Inside) => true,
+                _ => continue,
+            };
+
+            let source = piece.triangle as usize - mesh * self.first_count;
+            let triangle = meshes[mesh].triangles[source];
+            let [a, b, c] = [0, 1, 2].map(|k| meshes[mesh].positions[triangle[k] as usize]);
+            let face_normal = (b - a).cross(&(c - a)).normalize();
+            let direction = if flip { -1.0 } else { 1.0 };
+
+            let mut corners = U32Vec3::default();
+            for k in 0..3 {
+                let position = self.points[piece.corners[k] as usize];
+                let weights = barycentric(&position, &a, &b, &c);
+                let interpolate = |values: &Vec<DVec3>| (0..3).map(|i| values[triangle[i] as usize] * weights[i]).sum::<DVec3>();
+                let normal = normals[mesh].map_or(face_normal, |normals| {
+                    let normal = interpolate(normals);
+                    if normal.magnitude_squared() > 0.0 { normal.normalize() } else { face_normal }
+                }) * direction;
+                let color = colors[mesh].map_or(DVec3::new(1., 1., 1.), interpolate);
+                let uv = uvs[mesh].map_or(DVec2::zeros(), |uvs| (0..3).map(|i| uvs[triangle[i] as usize] * weights[i]).sum::<DVec2>());
+
+                let normal = if has_normals { normal } else { DVec3::zeros() };
+                let color = if has_colors { color } else { DVec3::zeros() };
+                let key = [
+                    position.x.to_bits(), position.y.to_bits(), position.z.to_bits(),
+                    normal.x.to_bits(), normal.y.to_bits(), normal.z.to_bits(),
+                    color.x.to_bits(), color.y.to_bits(), color.z.to_bits(),
+                    uv.x.to_bits(), uv.y.to_bits(),
+                ];
+                let next = result.positions.len() as u32;
+                corners[k] = *vertices.entry(key).or_insert_with(|| {
+                    result.positions.push(position);
+                    if let Some(normals) = result.normals.as_mut() {
+                        normals.push(normal);
+                    }
+                    if let Some(colors) = result.colors.as_mut() {
+                        colors.push(color);
+                    }
+                    if let Some(uvs) = result.uvs.as_mut() {
+                        uvs.push(uv);
+                    }
+                    next
+                });
+            }
+            if flip {
+                corners.swap_rows(1, 2);
+            }
+
+            let group = match triangle_groups[mesh][source] {
+                u32::MAX => u32::MAX,
+                group => group + mesh as u32 * meshes[0].groups.len() as u32,
+            };
+            if group != u32::MAX {
+                if group != last_group {
+                    result.groups.push(Group::new(result.triangles.len() as u32 * 3, 0));
+                    last_group = group;
+                }
+                result.groups.last_mut().unwrap().index_count += 3;
+            } else {
+                last_group = u32::MAX;
+            }
+            result.triangles.push(corners);
+        }
+
+        result
+    }
+}
+
+fn sign(value: f64) -> i32 {
+    if value > 0.0 { 1 } else if value < 0.0 { -1 } else { 0 }
+}
+
+fn dominant_axis(normal: &DVec3) -> usize {
+    normal.abs().imax()
+}
+
+// Drops the given axis. Projections are exact, so 2D predicates on coplanar points are exact too.
+fn project(point: &DVec3, axis: usize) -> [f64; 2] {
+    [point[(axis + 1) % 3], point[(axis + 2) % 3]]
+}
+
+fn locate(triangle: &[[f64; 2]; 3], point: &[f64; 2]) -> Option<TrianglePoint> {
+    let orientation = sign(orient2d(triangle[0], triangle[1], triangle[2]));
+    if orientation == 0 {
+        return None;
+    }
+    let sides = [0, 1, 2].map(|i| sign(orient2d(triangle[i], triangle[(i + 1) % 3], *point)) * orientation);
+    if sides.iter().any(|s| *s < 0) {
+        return None;
+    }
+    match sides.iter().filter(|s| **s == 0).count() {
+        0 => Some(TrianglePoint::Inside),
+        1 => Some(TrianglePoint::Edge(sides.iter().position(|s| *s == 0).unwrap())),
+        _ => Some(TrianglePoint::Vertex),
+    }
+}
+
+// Whether a point known to be on the line through a and b lies strictly between them
+fn is_between(a: &[f64; 2], b: &[f64; 2], point: &[f64; 2]) -> bool {
+    let along = |from: &[f64; 2], to: &[f64; 2]| (point[0] - from[0]) * (to[0] - from[0]) + (point[1] - from[1]) * (to[1] - from[1]);
+    along(a, b) > 0.0 && along(b, a) > 0.0
+}
+
+// Points lying exactly inside of the segment from a to b
+fn points_on_segment(points: &[[f64; 2]], a: usize, b: usize) -> Vec<usize> {
+    (0..points.len())
+        .filter(|i| *i != a && *i != b)
+        .filter(|i| orient2d(points[a], points[b], points[*i]) == 0.0 && is_between(&points[a], &points[b], &points[*i]))
+        .collect()
+}
+
+// Edges from a to b through the given points, sorted along the way
+fn chain(points: &[[f64; 2]], a: usize, b: usize, mut through: Vec<usize>) -> Vec<(usize, usize)> {
+    let direction = [points[b][0] - points[a][0], points[b][1] - points[a][1]];
+    let along = |i: &usize| (points[*i][0] - points[a][0]) * direction[0] + (points[*i][1] - points[a][1]) * direction[1];
+    through.sort_by(|i, j| along(i).partial_cmp(&along(j)).unwrap());
+    through.dedup();
+    let mut path = vec![a];
+    path.extend(through);
+    path.push(b);
+    path.windows(2).map(|w| (w[0], w[1])).collect()
+}
+
+fn barycentric(p: &DVec3, a: &DVec3, b: &DVec3, c: &DVec3) -> [f64; 3] {
+    let (ab, ac, ap) = (b - a, c - a, p - a);
+    let (d00, d01, d11) = (ab.dot(&ab), ab.dot(&ac), ac.dot(&ac));
+    let (d20, d21) = (ap.dot(&ab), ap.dot(&ac));
+    let denominator = d00 * d11 - d01 * d01;
+    if denominator == 0.0 {
+        return [1.0 / 3.0; 3];
+    }
+    let v = (d11 * d20 - d01 * d21) / denominator;
+    let w = (d00 * d21 - d01 * d20) / denominator;
+    [1.0 - v - w, v, w]
+}
+
+fn locate_point(bvh: &Bvh, mesh: &SharedMesh, point: &DVec3, normal: &DVec3, epsilon: f64) -> Location {
+    if let Some(closest) = bvh.closest_point(point, epsilon) {
+        let t = mesh.triangles[closest.triangle as usize];
+        let [a, b, c] = [0, 1, 2].map(|k| mesh.positions[t[k] as usize]);
+        return if (b - a).cross(&(c - a)).dot(normal) > 0.0 { Location::SameSurface } else { Location::OppositeSurface };
+    }
+
+    // Parity of the surface crossings along a few unaligned rays, by majority
+    let directions = [DVec3::new(0.8251, 0.3761, 0.4215), DVec3::new(-0.2947, 0.8813, 0.3693), DVec3::new(0.1832, -0.4356, 0.8813)];
+    let inside_votes = directions.iter()
+        .filter(|direction| bvh.raycast_all(&Ray::new(*point, **direction), f64::MAX).len() % 2 == 1)
+        .count();
+    if inside_votes >= 2 { Location::Inside } else { Location::Outside }
+}
+
+#[cfg(test)]
+mod boolean_tests {
+    use super::*;
+    use super::super::test_meshes::*;
+
+    // Every edge is shared by exactly two triangles walking it in opposite directions
+    fn assert_closed(mesh: &SharedMesh) {
+        let welded = adjacency::weld_positions(&mesh.positions);
+        let edges = adjacency::edge_faces(&mesh.triangles, &welded);
+        for (key, faces) in edges.iter() {
+            let (a, b) = ((key >> 32) as u32, *key as u32);
+            assert_eq!(faces.len(), 2);
+            assert!(adjacency::walks_edge(&mesh.triangles[faces[0] as usize], &welded, a, b)
+                != adjacency::walks_edge(&mesh.triangles[faces[1] as usize], &welded, a, b));
+        }
+    }
+
+    #[test]
+    fn overlapping_cubes() {
+        let a = cube(DVec3::zeros(), 2.0);
+        let b = cube(DVec3::new(0.5, 0.3, 0.2), 2.0);
+        // Overlap is 1.5 x 1.7 x 1.8
+        let overlap = 1.5 * 1.7 * 1.8;
+
+        let union = a.union(&b).unwrap();
+        let difference = a.difference(&b).unwrap();
+        let intersection = a.intersection(&b).unwrap();
+        assert!((union.volume() - (16.0 - overlap)).abs() < 1e-9);
+        assert!((difference.volume() - (8.0 - overlap)).abs() < 1e-9);
+        assert!((intersection.volume() - overlap).abs() < 1e-9);
+        for mesh in [&union, &difference, &intersection] {
+            assert_closed(mesh);
+        }
+    }
+
+    #[test]
+    fn coplanar_faces() {
+        // Both cubes share the planes of their y faces, and the second one is flush with the first one on x
+        let a = cube(DVec3::zeros(), 2.0);
+        let b = cube(DVec3::new(1.5, 0., 0.25), 2.0);
+        let overlap = 0.5 * 2.0 * 1.75;
+
+        let union = a.union(&b).unwrap();
+        let difference = a.difference(&b).unwrap();
+        let intersection = a.intersection(&b).unwrap();
+        assert!((union.volume() - (16.0 - overlap)).abs() < 1e-9);
+        assert!((difference.volume() - (8.0 - overlap)).abs() < 1e-9);
+        assert!((intersection.volume() - overlap).abs() < 1e-9);
+        for mesh in [&union, &difference, &intersection] {
+            assert_closed(mesh);
+        }
+
+        // Identical meshes
+        assert!((a.union(&a).unwrap().volume() - 8.0).abs() < 1e-9);
+        assert!(a.difference(&a).unwrap().triangles.is_empty());
+    }
+
+    #[test]
+    fn attributes_and_groups() {
+        let mut a = sphere(DVec3::zeros(), 1.0, 3);
+        a.groups = vec![Group::new(0, a.triangles.len() as u32 * 3)];
+        a.colors = Some(vec![DVec3::new(1., 0., 0.); a.positions.len()]);
+        a.uvs = Some(a.positions.iter().map(|p| DVec2::new(p.x, p.y)).collect());
+        let mut b = cube(DVec3::new(0.7, 0.1, 0.05), 1.0);
+        b.groups = vec![Group::new(0, 18), Group::new(18, 18)];
+
+        let difference = a.difference(&b).unwrap();
+        assert_closed(&difference);
+        assert!(difference.volume() > 0.0 && difference.volume() < a.volume());
+
+        // Sphere triangles come first, then the carved faces of the cube, inverted and white
+        assert!(difference.groups.len() >= 2 && difference.groups.len() <= 3);
+        let triangle_groups = difference.triangle_groups();
+        let colors = difference.colors.as_ref().unwrap();
+        let normals = difference.normals.as_ref().unwrap();
+        let uvs = difference.uvs.as_ref().unwrap();
+        for (i, t) in difference.triangles.iter().enumerate() {
+            let color = colors[t[0] as usize];
+            if triangle_groups[i] == 0 {
+                assert!((color - DVec3::new(1., 0., 0.)).magnitude() < 1e-9);
+                assert!((normals[t[0] as usize].magnitude() - 1.0).abs() < 1e-9);
+                // Texture coordinates are linear on each sphere triangle, so cut vertices keep them exact
+                for k in 0..3 {
+                    let p = difference.positions[t[k] as usize];
+                    assert!((uvs[t[k] as usize] - DVec2::new(p.x, p.y)).magnitude() < 1e-9);
+                }
+            } else {
+                assert_eq!(color, DVec3::new(1., 1., 1.));
+                assert_eq!(uvs[t[0] as usize], DVec2::zeros());
+                // Normals of the carved faces point toward the inside of the cube
+                let centroid = (0..3).map(|k| difference.positions[t[k] as usize]).sum::<DVec3>() / 3.0;
+                assert!(normals[t[0] as usize].dot(&(DVec3::new(0.7, 0.1, 0.05) - centroid)) > 0.0);
+            }
+        }
+    }
+}
@@ -2,68 +2,75 @@ impl From<&SharedMesh> for ConnectedMesh {
     fn from(shared_mesh: &SharedMesh) -> Self {
         let triangles = &shared_mesh.triangles;
         let mut nodes = vec![Node::default(); triangles.len() * 3];
-        let mut vertex_to_nodes = HashMap::<u32, Vec<u32>, _>::with_hasher(
-            BuildHasherDefault::<SimpleHasher>::default()
-        );
         let mut face_count = 0;
-        for i in 0..triangles.len() {
-            let triangle = triangles[i];
-            {
-                let mut a = &mut nodes[i];
-                a.position = triangle[0];
-                a.normal = triangle[0];
-                a.relative = (i as u32) + 1; // B
-                if !vertex_to_nodes.contains_key(&a.position) {
-                    vertex_to_nodes.insert(a.position, Vec::new());
-                }
-                vertex_to_nodes.get_mut(&a.position).unwrap().push(i as u32);
-            }
-            {
-                let mut b = &mut nodes[i + 1];
-                b.position = triangle[1];
-                b.normal = triangle[1];
-                b.relative = (i as u32) + 2; // C
-                if !vertex_to_nodes.contains_key(&b.position) {
-                    vertex_to_nodes.insert(b.position, Vec::new());
-                }  
-                vertex_to_nodes.get_mut(&b.position).unwrap().push((i as u32) + 1);
-            }
-            {
-                let mut c = &mut nodes[i + 2];
-                c.position = triangle[2];
-                c.normal = triangle[2];
-                c.relative = i as u32; // A
-                if !vertex_to_nodes.contains_key(&c.position) {
-                    vertex_to_nodes.insert(c.position, Vec::new());
-                }
-                vertex_to_nodes.get_mut(&c.position).unwrap().push((i as u32) + 2);
+
+        // Vertices split by attributes in the shared mesh share a single position here,
+        // attributes remain indexed by the original vertex index
+        let welded = adjacency::weld_positions(&shared_mesh.positions);
+        let mut vertex_to_position = vec![u32::MAX; shared_mesh.positions.len()];
+        let mut positions = Vec::new();
+        for (vertex, first) in welded.iter().enumerate() {
+            if vertex_to_position[*first as usize] == u32::MAX {
+                vertex_to_position[*first as usize] = positions.len() as u32;
+                positions.push(shared_mesh.positions[vertex]);
             }
-            face_count = face_count + 1;
+            vertex_to_position[vertex] = vertex_to_position[*first as usize];
         }
 
-        for x in vertex_to_nodes.values() {
-            let mut previous_sibling: u32 = u32::MAX;
-            let mut first_sibling: u32 = u32::MAX;
-            for node in x.iter() {
-                if first_sibling != u32::MAX {
-                    nodes[*node as usize].sibling = previous_sibling;
-                }
-                else {
-                    first_sibling = *node;
-                }
-                previous_sibling = *node;
+        for (i, triangle) in triangles.iter().enumerate() {
+            let first = i * 3;
+            for k in 0..3 {
+                let node = &mut nodes[first + k];
+                node.position = vertex_to_position[triangle[k] as usize];
+                node.normal = triangle[k];
+                node.color = triangle[k];
+                node.uv = triangle[k];
+                node.relative = (first + (k + 1) % 3) as u32; // A -> B -> C -> A
             }
-            nodes[first_sibling as usize].sibling = previous_sibling;
+            face_count = face_count + 1;
         }
 
+        link_siblings(&mut nodes);
+
         return ConnectedMesh { 
-            positions: shared_mesh.positions.clone(),
-            normals: shared_mesh.normals.clone(),
+            positions,
+            normals: shared_mesh.normals.clone().filter(|normals| normals.len() == shared_mesh.positions.len()),
+            colors: shared_mesh.colors.clone().filter(|colors| colors.len() == shared_mesh.positions.len()),
+            uvs: shared_mesh.uvs.clone().filter(|uvs| uvs.len() == shared_mesh.positions.len()),
+            groups: shared_mesh.groups.clone(),
             nodes: nodes,
             face_count: face_count };
     }
 }
 
+// Links all nodes sharing the same position in sibling loops
+fn link_siblings(nodes: &mut [Node]) {
+    let mut vertex_to_nodes = HashMap::<u32, Vec<u32>, _>::with_hasher(
+        BuildHasherDefault::<SimpleHasher>::default()
+    );
+    for (i, node) in nodes.iter().enumerate() {
+        if node.is_removed {
+            continue;
+        }
+        vertex_to_nodes.entry(node.position).or_insert_with(Vec::new).push(i as u32);
+    }
+
+    for x in vertex_to_nodes.values() {
+        let mut previous_sibling: u32 = u32::MAX;
+        let mut first_sibling: u32 = u32::MAX;
+        for node in x.iter() {
+            if first_sibling != u32::MAX {
+                nodes[*node as usize].sibling = previous_sibling;
+            }
+            else {
+                first_sibling = *node;
+            }
+            previous_sibling = *node;
+        }
+        nodes[first_sibling as usize].sibling = previous_sibling;
+    }
+}
+
 impl Into<ConnectedMesh> for SharedMesh {
     fn into(self) -> ConnectedMesh {
         return ConnectedMesh::from(&self);
@@ -73,11 +80,14 @@ impl Into<ConnectedMesh> for SharedMesh {
 impl From<&ConnectedMesh> for SharedMesh {
     fn from(connected_mesh: &ConnectedMesh) -> Self {
 
-        let mut per_vertex_map = HashMap::<[u32; 2], u32>::new();
+        let mut per_vertex_map = HashMap::<[u32; 4], u32>::new();
         let mut browsed_nodes = HashSet::new();
-        let mut triangles = Vec::<U32Vec3>::with_capacity((connected_mesh.face_count / 3) as usize);
+        let mut triangles = Vec::<U32Vec3>::with_capacity(connected_mesh.face_count as usize);
+        let mut groups = Vec::<Group>::new();
+        let node_groups = connected_mesh.node_groups();
+        let mut last_group = u32::MAX;
 
-        for i in 0..connected_mesh.nodes.len() {
+        for (i, group) in node_groups.iter().enumerate() {
             if connected_mesh.nodes[i].is_removed {
                 continue;
             }
@@ -90,7 +100,8 @@ impl From<&ConnectedMesh> for SharedMesh {
 
             let mut x = 0;
             loop_relatives!(i as u32, connected_mesh.nodes, relative, {
-                let key = [connected_mesh.nodes[relative as usize].position, connected_mesh.nodes[relative as usize].normal];
+                let node = &connected_mesh.nodes[relative as usize];
+                let key = [node.position, node.normal, node.color, node.uv];
                 if !per_vertex_map.contains_key(&key) {
                     per_vertex_map.insert(key, per_vertex_map.len() as u32);
                 }
@@ -103,6 +114,18 @@ impl From<&ConnectedMesh> for SharedMesh {
                 x += 1;
             });
 
+            // Faces are visited in node order, so faces of a group remain contiguous
+            let group = *group;
+            if group != u32::MAX {
+                if group != last_group {
+                    groups.push(Group::new(triangles.len() as u32 * 3, 0));
+                    last_group = group;
+                }
+                groups.last_mut().unwrap().index_count += 3;
+            } else {
+                last_group = u32::MAX;
+            }
+
             triangles.push(triangle);
         }
 
@@ -122,12 +145,32 @@ impl From<&ConnectedMesh> for SharedMesh {
             None => None,
         };
 
+        let colors = match &connected_mesh.colors {
+            Some(cm_colors) => {
+                let mut scolors = vec![DVec3::default(); per_vertex_map.len()];
+                for (key, value) in &per_vertex_map {
+                    scolors[*value as usize] = cm_colors[key[2] as usize];
+                }
+                Some(scolors)
+            },
+            None => None,
+        };
+
+        let uvs = connected_mesh.uvs.as_ref().map(|cm_uvs| {
+            let mut suvs = vec![DVec2::default(); per_vertex_map.len()];
+            for (key, value) in &per_vertex_map {
+                suvs[*value as usize] = cm_uvs[key[3] as usize];
+            }
+            suvs
+        });
+
         return SharedMesh {
-            groups: Vec::new(),
+            groups,
             triangles: triangles,
             positions: positions,
             normals: normals,
-            colors: None,
+            colors,
+            uvs,
         };
     }
 }
@@ -146,8 +189,6 @@ mod builder_tests {
 
     #[test]
     fn shared_mesh_to_connected_mesh() {
-        
-        return; // todo: Fix this 
 
         let mut positions = Vec::new();
         // Build a square
@@ -166,6 +207,7 @@ mod builder_tests {
             groups: Vec::new(),
             triangles: triangles,
             colors: None,
+            uvs: None,
             positions: positions,
             normals: None,
         };
@@ -218,6 +260,9 @@ mod builder_tests {
         let connected_mesh = ConnectedMesh {
             positions: positions,
             normals: None,
+            colors: None,
+            uvs: None,
+            groups: Vec::new(),
             nodes: nodes,
             face_count: 2,
         };
@@ -229,4 +274,36 @@ mod builder_tests {
         assert_eq!(shared_mesh.triangles[0], U32Vec3::new(0, 1, 2));
         assert_eq!(shared_mesh.triangles[1], U32Vec3::new(0, 2, 3));
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let mut shared_mesh = test_meshes::sphere(DVec3::zeros(), 1.0, 2);
+        shared_mesh.groups = vec![Group::new(0, 24)];
+
+        let json = serde_json::to_string(&shared_mesh).unwrap();
+        let shared_mesh_back: SharedMesh = serde_json::from_str(&json).unwrap();
+        assert_eq!(shared_mesh_back.triangles, shared_mesh.triangles);
+        assert_eq!(shared_mesh_back.positions, shared_mesh.positions);
+        assert_eq!(shared_mesh_back.normals, shared_mesh.normals);
+        assert_eq!(shared_mesh_back.groups, shared_mesh.groups);
+
+        let mut connected_mesh = ConnectedMesh::from(&shared_mesh);
+        connected_mesh.decimate(40);
+        let json = serde_json::to_string(&connected_mesh).unwrap();
+        let mut connected_mesh_back: ConnectedMesh = serde_json::from_str(&json).unwrap();
+        assert_eq!(connected_mesh_back.face_count, connected_mesh.face_count);
+
+        // Decimation resumes from the snapshot
+        connected_mesh.decimate(20);
+        connected_mesh_back.decimate(20);
+        let (a, b) = (SharedMesh::from(&connected_mesh), SharedMesh::from(&connected_mesh_back));
+        assert_eq!(a.triangles, b.triangles);
+        assert_eq!(a.positions, b.positions);
+
+        let bounding_box = shared_mesh.bounding_box();
+        let bounding_box_back: Box3 = serde_json::from_str(&serde_json::to_string(&bounding_box).unwrap()).unwrap();
+        assert_eq!(bounding_box_back.min, bounding_box.min);
+        assert_eq!(bounding_box_back.max, bounding_box.max);
+    }
 }
\ No newline at end of file
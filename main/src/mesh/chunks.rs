@@ -0,0 +1,248 @@
+// Splitting meshes into chunks with a limited number of vertices, for runtimes that only support 16-bit indices.
+// Chunks are grown breadth-first over triangles sharing vertices, so that few vertices are duplicated across chunks.
+
+use nalgebra_glm as glm;
+use glm::{DVec2, DVec3, U32Vec3};
+use std::collections::VecDeque;
+use std::ops::Range;
+use super::{SharedMesh, Group};
+
+/// Maximum number of vertices addressable with 16-bit indices, 0xFFFF being kept for primitive restart
+pub const MAX_U16_VERTICES: usize = 65535;
+
+/// A chunk of a mesh with 16-bit indices
+#[derive(Debug, Clone, Default)]
+pub struct U16Mesh {
+    pub triangles: Vec<[u16; 3]>,
+    pub positions: Vec<DVec3>,
+    pub normals: Option<Vec<DVec3>>,
+    pub colors: Option<Vec<DVec3>>,
+    pub uvs: Option<Vec<DVec2>>,
+    /// Index of each vertex in the source mesh
+    pub source_vertices: Vec<u32>,
+    /// Group of the source mesh this chunk comes from, if any
+    pub source_group: Option<usize>,
+}
+
+impl SharedMesh {
+    /// Reorders triangles into chunks of at most `max_vertices` vertices, and replaces groups by these chunks.
+    /// Chunks never span several source groups. Returns the source group of each new group, if any.
+    pub fn split_into_chunks(&mut self, max_vertices: usize) -> Vec<Option<usize>> {
+        let chunks = self.chunks(max_vertices);
+        let mut triangles = Vec::with_capacity(self.triangles.len());
+        let mut groups = Vec::with_capacity(chunks.len());
+        let mut source_groups = Vec::with_capacity(chunks.len());
+        for (chunk, source_group) in chunks {
+            groups.push(Group::new(triangles.len() as u32 * 3, chunk.len() as u32 * 3));
+            triangles.extend(chunk.iter().map(|t| self.triangles[*t as usize]));
+            source_groups.push(source_group);
+        }
+        self.triangles = triangles;
+        self.groups = groups;
+        source_groups
+    }
+
+    /// Splits the mesh into separate meshes of at most `max_vertices` vertices, with 16-bit indices
+    pub fn to_u16_chunks(&self, max_vertices: usize) -> Vec<U16Mesh> {
+        assert!(max_vertices <= MAX_U16_VERTICES, "16-bit indices cannot address more than {} vertices", MAX_U16_VERTICES);
+
+        let mut remap = vec![u16::MAX; self.positions.len()];
+        self.chunks(max_vertices).into_iter().map(|(chunk, source_group)| {
+            let mut vertices = Vec::new();
+            let triangles = chunk.iter().map(|t| {
+                let t = self.triangles[*t as usize];
+                [0, 1, 2].map(|k| {
+                    let v = t[k] as usize;
+                    if remap[v] == u16::MAX {
+                        remap[v] = vertices.len() as u16;
+                        vertices.push(v as u32);
+                    }
+                    remap[v]
+                })
+            }).collect();
+            for v in &vertices {
+                remap[*v as usize] = u16::MAX;
+            }
+
+            let count = self.positions.len();
+            U16Mesh {
+                triangles,
+                positions: vertices.iter().map(|v| self.positions[*v as usize]).collect(),
+                normals: self.normals.as_ref()
+                    .filter(|normals| normals.len() == count)
+                    .map(|normals| vertices.iter().map(|v| normals[*v as usize]).collect()),
+                colors: self.colors.as_ref()
+                    .filter(|colors| colors.len() == count)
+                    .map(|colors| vertices.iter().map(|v| colors[*v as usize]).collect()),
+                uvs: self.uvs.as_ref()
+                    .filter(|uvs| uvs.len() == count)
+                    .map(|uvs| vertices.iter().map(|v| uvs[*v as usize]).collect()),
+                source_vertices: vertices,
+                source_group,
+            }
+        }).collect()
+    }
+
+    // Triangles of each chunk, and the group it belongs to
+    fn chunks(&self, max_vertices: usize) -> Vec<(Vec<u32>, Option<usize>)> {
+        let max_vertices = max_vertices.max(3);
+
+        let mut vertex_triangles = vec![Vec::new(); self.positions.len()];
+        for (i, t) in self.triangles.iter().enumerate() {
+            for k in 0..3 {
+                vertex_triangles[t[k] as usize].push(i as u32);
+            }
+        }
+
+        let mut chunks = Vec::new();
+        let mut is_assigned = vec![false; self.triangles.len()];
+        let mut is_in_chunk = vec![false; self.positions.len()];
+        for range in self.triangle_ranges() {
+            let source_group = self.groups.iter().position(|g| g.triangles() == range);
+            for chunk in grow_chunks(&self.triangles, range, &vertex_triangles, max_vertices, &mut is_assigned, &mut is_in_chunk) {
+                chunks.push((chunk, source_group));
+            }
+        }
+        chunks
+    }
+}
+
+fn grow_chunks(
+    triangles: &[U32Vec3],
+    range: Range<usize>,
+    vertex_triangles: &[Vec<u32>],
+    max_vertices: usize,
+    is_assigned: &mut [bool],
+    is_in_chunk: &mut [bool],
+) -> Vec<Vec<u32>> {
+    let mut chunks = Vec::new();
+    let mut seed = range.start;
+    let mut chunk = Vec::new();
+    let mut vertices = Vec::new();
+    let mut queue = VecDeque::new();
+    let mut is_queued = vec![false; range.len()];
+    // Triangles queued for the current chunk, whose flags are reset when the next one starts
+    let mut queued = Vec::new();
+
+    loop {
+        if queue.is_empty() {
+            while seed < range.end && is_assigned[seed] {
+                seed += 1;
+            }
+            if seed == range.end {
+                break;
+            }
+            // A new region starts in the current chunk if there is room for it
+            let seed_vertices = (0..3).filter(|k| !is_in_chunk[triangles[seed][*k] as usize]).count();
+            if vertices.len() + seed_vertices > max_vertices {
+                chunks.push(std::mem::take(&mut chunk));
+                for v in vertices.drain(..) {
+                    is_in_chunk[v as usize] = false;
+                }
+                for t in queued.drain(..) {
+                    is_queued[t as usize - range.start] = false;
+                }
+            }
+            is_queued[seed - range.start] = true;
+            queued.push(seed as u32);
+            queue.push_back(seed as u32);
+        }
+
+        let t = queue.pop_front().unwrap();
+        let triangle = triangles[t as usize];
+        let new_vertices = (0..3).filter(|k| !is_in_chunk[triangle[*k] as usize]).count();
+        if vertices.len() + new_vertices > max_vertices {
+            // Left for the next chunk
+            continue;
+        }
+
+        is_assigned[t as usize] = true;
+        chunk.push(t);
+        for k in 0..3 {
+            let v = triangle[k];
+            if !is_in_chunk[v as usize] {
+                is_in_chunk[v as usize] = true;
+                vertices.push(v);
+            }
+            for n in &vertex_triangles[v as usize] {
+                let n = *n as usize;
+                if range.contains(&n) && !is_assigned[n] && !is_queued[n - range.start] {
+                    is_queued[n - range.start] = true;
+                    queued.push(n as u32);
+                    queue.push_back(n as u32);
+                }
+            }
+        }
+    }
+
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    for v in vertices {
+        is_in_chunk[v as usize] = false;
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod chunks_tests {
+    use super::*;
+    use super::super::test_meshes::*;
+
+    fn vertex_count(mesh: &SharedMesh, group: &Group) -> usize {
+        let mut vertices: Vec<u32> = mesh.triangles[group.triangles()].iter().flat_map(|t| [t[0], t[1], t[2]]).collect();
+        vertices.sort_unstable();
+        vertices.dedup();
+        vertices.len()
+    }
+
+    #[test]
+    fn large_grid() {
+        let mut mesh = grid(300, 1.0);
+        let mut original = mesh.triangles.clone();
+        let source_groups = mesh.split_into_chunks(MAX_U16_VERTICES);
+
+        assert_eq!(mesh.groups.len(), 2);
+        assert_eq!(source_groups, vec![None, None]);
+        let counts: Vec<usize> = mesh.groups.iter().map(|g| vertex_count(&mesh, g)).collect();
+        assert!(counts.iter().all(|c| *c <= MAX_U16_VERTICES));
+        // Duplicated vertices along the border between both chunks
+        assert!(counts.iter().sum::<usize>() < mesh.positions.len() + 1000, "{:?}", counts);
+
+        let mut triangles = mesh.triangles.clone();
+        original.sort_unstable_by_key(|t| (t[0], t[1], t[2]));
+        triangles.sort_unstable_by_key(|t| (t[0], t[1], t[2]));
+        assert_eq!(original, triangles);
+    }
+
+    #[test]
+    fn small_chunks_in_groups() {
+        let mut mesh = grid(40, 1.0);
+        mesh.groups = vec![Group::new(0, 1200), Group::new(1200, 1800)];
+        let source_groups = mesh.split_into_chunks(100);
+
+        assert!(mesh.groups.iter().all(|g| vertex_count(&mesh, g) <= 100));
+        let total: usize = mesh.groups.iter().map(|g| vertex_count(&mesh, g)).sum();
+        assert!(total < mesh.positions.len() * 3 / 2, "{} {}", total, mesh.positions.len());
+        // Triangles outside of groups are chunked too
+        assert_eq!(mesh.groups.iter().map(|g| g.index_count as usize).sum::<usize>(), mesh.triangles.len() * 3);
+        assert_eq!(source_groups.iter().filter(|g| **g == Some(0)).count(), 3);
+        assert!(source_groups.windows(2).all(|w| w[0].unwrap_or(2) <= w[1].unwrap_or(2)));
+    }
+
+    #[test]
+    fn u16_chunks() {
+        let mesh = sphere(DVec3::zeros(), 1.0, 4);
+        let chunks = mesh.to_u16_chunks(500);
+        assert!(chunks.len() >= mesh.positions.len() / 500);
+        assert_eq!(chunks.iter().map(|c| c.triangles.len()).sum::<usize>(), mesh.triangles.len());
+        for chunk in &chunks {
+            assert!(chunk.positions.len() <= 500);
+            assert_eq!(chunk.normals.as_ref().unwrap().len(), chunk.positions.len());
+            for (p, v) in chunk.positions.iter().zip(&chunk.source_vertices) {
+                assert_eq!(*p, mesh.positions[*v as usize]);
+            }
+            assert!(chunk.triangles.iter().flatten().all(|i| (*i as usize) < chunk.positions.len()));
+        }
+    }
+}
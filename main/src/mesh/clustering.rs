@@ -0,0 +1,332 @@
+// Vertex clustering simplification ("Out-of-Core Simplification of Large Polygonal Models", Lindstrom, 2000).
+// Vertices are merged per cell of a regular grid, at the position minimizing the quadric error of the cell.
+// Much faster than edge collapses, at the cost of quality and topology preservation.
+
+use nalgebra_glm as glm;
+use glm::{DVec3, DMat3, U32Vec3};
+use hashbrown::{HashMap, HashSet};
+use std::hash::BuildHasherDefault;
+use super::{SharedMesh, Group};
+use super::super::base::SymmetricMatrix;
+use super::super::utils::SimpleHasher;
+
+#[derive(Debug, Clone)]
+pub struct ClusteringOptions {
+    /// Size of the grid cells, in world units. When zero, it is derived from `resolution`.
+    pub cell_size: f64,
+    /// Number of cells along the largest side of the bounding box, when `cell_size` is zero
+    pub resolution: u32,
+    /// Upper bound of the memory used by the cell maps while accumulating triangles, in bytes. The grid is coarsened
+    /// until it fits. Allocations proportional to the input or to the output (vertex remapping, triangle groups and
+    /// deduplication, the simplified mesh itself) are not counted.
+    pub memory_budget: usize,
+    /// Accumulate triangles on several threads. Requires the `parallel` feature, ignored otherwise.
+    pub parallel: bool,
+}
+
+impl Default for ClusteringOptions {
+    fn default() -> Self {
+        ClusteringOptions {
+            cell_size: 0.0,
+            resolution: 64,
+            memory_budget: 256 * 1024 * 1024,
+            parallel: false,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Cell {
+    quadric: SymmetricMatrix,
+    position_sum: DVec3,
+    normal_sum: DVec3,
+    color_sum: DVec3,
+    count: u32,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell {
+            quadric: SymmetricMatrix::default_zeroes(),
+            position_sum: DVec3::zeros(),
+            normal_sum: DVec3::zeros(),
+            color_sum: DVec3::zeros(),
+            count: 0,
+        }
+    }
+}
+
+impl Cell {
+    #[cfg(feature = "parallel")]
+    fn merge(&mut self, other: &Cell) {
+        self.quadric += other.quadric;
+        self.position_sum += other.position_sum;
+        self.normal_sum += other.normal_sum;
+        self.color_sum += other.color_sum;
+        self.count += other.count;
+    }
+
+    // Minimizer of the quadric, found with a truncated pseudo-inverse around the mean position
+    // so that flat and sharp-edged cells stay well defined. Falls back to the mean when outside of the cell.
+    fn representative(&self, min: &DVec3, max: &DVec3) -> DVec3 {
+        let mean = self.position_sum / self.count as f64;
+        let m = &self.quadric.m;
+        let a = DMat3::new(
+            m[0], m[1], m[2],
+            m[1], m[4], m[5],
+            m[2], m[5], m[7]);
+        let b = -DVec3::new(m[3], m[6], m[8]);
+
+        let eigen = a.symmetric_eigen();
+        let max_eigenvalue = eigen.eigenvalues.iter().fold(0.0f64, |a, b| a.max(b.abs()));
+        let residual = b - a * mean;
+        let mut position = mean;
+        for i in 0..3 {
+            let eigenvalue = eigen.eigenvalues[i];
+            if eigenvalue.abs() > max_eigenvalue * 1e-3 {
+                let vector = eigen.eigenvectors.column(i);
+                position += vector * (vector.dot(&residual) / eigenvalue);
+            }
+        }
+
+        let margin = (max - min) * 0.01;
+        let is_inside = (0..3).all(|k| position[k] >= min[k] - margin[k] && position[k] <= max[k] + margin[k]);
+        if is_inside { position } else { mean }
+    }
+}
+
+type CellMap = HashMap<u64, Cell, BuildHasherDefault<SimpleHasher>>;
+
+struct Grid {
+    origin: DVec3,
+    cell_size: f64,
+}
+
+impl Grid {
+    // Cell coordinates packed on 21 bits each
+    fn key(&self, position: &DVec3) -> u64 {
+        let cell = (position - self.origin) / self.cell_size;
+        let coordinate = |x: f64| (x.max(0.0) as u64).min((1 << 21) - 1);
+        coordinate(cell.x) | coordinate(cell.y) << 21 | coordinate(cell.z) << 42
+    }
+
+    fn bounds(&self, key: u64) -> (DVec3, DVec3) {
+        let mask = (1 << 21) - 1;
+        let cell = DVec3::new((key & mask) as f64, (key >> 21 & mask) as f64, (key >> 42 & mask) as f64);
+        let min = self.origin + cell * self.cell_size;
+        (min, min + DVec3::repeat(self.cell_size))
+    }
+}
+
+impl SharedMesh {
+    /// Simplifies the mesh by merging all vertices falling in the same grid cell, in a single pass over the triangles.
+    /// Triangles that become degenerate or duplicated are removed. Normals and colors are averaged per cell.
+    pub fn simplify_clustering(&self, options: &ClusteringOptions) -> SharedMesh {
+        let bounds = self.bounding_box();
+        let size = bounds.size();
+        let largest_side = size.x.max(size.y).max(size.z);
+        let mut cell_size = if options.cell_size > 0.0 { options.cell_size } else { largest_side / options.resolution.max(1) as f64 };
+        if !bounds.is_valid() || cell_size <= 0.0 {
+            return self.submesh(&vec![true; self.triangles.len()]);
+        }
+
+        // Number of cells that may be occupied, bounded by the grid size and the number of vertices
+        let threads = if options.parallel { thread_count() } else { 1 };
+        let cell_bytes = std::mem::size_of::<(u64, Cell)>() * 2;
+        let max_cells = (options.memory_budget / cell_bytes / threads).max(1) as f64;
+        loop {
+            let cells = size / cell_size;
+            let grid_cells = (cells.x.floor() + 1.0) * (cells.y.floor() + 1.0) * (cells.z.floor() + 1.0);
+            let fits_keys = largest_side / cell_size < ((1 << 21) - 1) as f64;
+            if fits_keys && grid_cells.min(self.positions.len() as f64) <= max_cells {
+                break;
+            }
+            cell_size *= 1.25;
+        }
+
+        let grid = Grid { origin: bounds.min, cell_size };
+        let normals = self.normals.as_ref().filter(|normals| normals.len() == self.positions.len());
+        let colors = self.colors.as_ref().filter(|colors| colors.len() == self.positions.len());
+
+        let accumulate = |triangles: &[U32Vec3]| -> CellMap {
+            let mut cells = CellMap::default();
+            for t in triangles {
+                let [a, b, c] = [0, 1, 2].map(|k| self.positions[t[k] as usize]);
+                let cross = (b - a).cross(&(c - a));
+                let length = cross.magnitude();
+                let quadric = if length > 0.0 {
+                    let normal = cross / length;
+                    let mut quadric = SymmetricMatrix::from_normal(&normal, &-normal.dot(&a));
+                    // Weight by the area, so that large triangles dominate
+                    for m in quadric.m.iter_mut() {
+                        *m *= length / 2.0;
+                    }
+                    quadric
+                } else {
+                    SymmetricMatrix::default_zeroes()
+                };
+                for k in 0..3 {
+                    let v = t[k] as usize;
+                    let cell = cells.entry(grid.key(&self.positions[v])).or_default();
+                    cell.quadric += quadric;
+                    cell.position_sum += self.positions[v];
+                    if let Some(normals) = normals {
+                        cell.normal_sum += normals[v];
+                    }
+                    if let Some(colors) = colors {
+                        cell.color_sum += colors[v];
+                    }
+                    cell.count += 1;
+                }
+            }
+            cells
+        };
+
+        let cells = accumulate_cells(&self.triangles, options.parallel, &accumulate);
+
+        // Sorted for a deterministic vertex order
+        let mut keys: Vec<u64> = cells.keys().copied().collect();
+        keys.sort_unstable();
+        let mut cell_to_vertex = HashMap::<u64, u32, BuildHasherDefault<SimpleHasher>>::default();
+        let mut positions = Vec::with_capacity(keys.len());
+        let mut out_normals = normals.map(|_| Vec::with_capacity(keys.len()));
+        let mut out_colors = colors.map(|_| Vec::with_capacity(keys.len()));
+        for key in &keys {
+            let cell = &cells[key];
+            let (min, max) = grid.bounds(*key);
+            cell_to_vertex.insert(*key, positions.len() as u32);
+            positions.push(cell.representative(&min, &max));
+            if let Some(out_normals) = out_normals.as_mut() {
+                let normal = cell.normal_sum;
+                out_normals.push(if normal.magnitude_squared() > 0.0 { normal.normalize() } else { normal });
+            }
+            if let Some(out_colors) = out_colors.as_mut() {
+                out_colors.push(cell.color_sum / cell.count as f64);
+            }
+        }
+
+        let triangle_groups = self.triangle_groups();
+        let mut triangles = Vec::new();
+        let mut groups = Vec::<Group>::new();
+        let mut last_group = u32::MAX;
+        let mut unique = HashSet::new();
+        for (i, t) in self.triangles.iter().enumerate() {
+            let [a, b, c] = [0, 1, 2].map(|k| cell_to_vertex[&grid.key(&self.positions[t[k] as usize])]);
+            if a == b || b == c || c == a {
+                continue;
+            }
+            let mut sorted = [a, b, c];
+            sorted.sort_unstable();
+            if !unique.insert(sorted) {
+                continue;
+            }
+            if triangle_groups[i] != u32::MAX {
+                if triangle_groups[i] != last_group {
+                    groups.push(Group::new(triangles.len() as u32 * 3, 0));
+                    last_group = triangle_groups[i];
+                }
+                groups.last_mut().unwrap().index_count += 3;
+            } else {
+                last_group = u32::MAX;
+            }
+            triangles.push(U32Vec3::new(a, b, c));
+        }
+
+        SharedMesh {
+            groups,
+            triangles,
+            positions,
+            normals: out_normals,
+            colors: out_colors,
+            uvs: None,
+        }
+    }
+}
+
+#[cfg(feature = "parallel")]
+fn thread_count() -> usize {
+    rayon::current_num_threads()
+}
+
+#[cfg(not(feature = "parallel"))]
+fn thread_count() -> usize {
+    1
+}
+
+#[cfg(feature = "parallel")]
+fn accumulate_cells(triangles: &[U32Vec3], parallel: bool, accumulate: &(dyn Fn(&[U32Vec3]) -> CellMap + Sync)) -> CellMap {
+    use rayon::prelude::*;
+    if !parallel {
+        return accumulate(triangles);
+    }
+    triangles.par_chunks(1 << 16)
+        .map(accumulate)
+        .reduce(CellMap::default, |mut a, b| {
+            for (key, cell) in b {
+                a.entry(key).or_default().merge(&cell);
+            }
+            a
+        })
+}
+
+#[cfg(not(feature = "parallel"))]
+fn accumulate_cells(triangles: &[U32Vec3], _parallel: bool, accumulate: &dyn Fn(&[U32Vec3]) -> CellMap) -> CellMap {
+    accumulate(triangles)
+}
+
+#[cfg(test)]
+mod clustering_tests {
+    use super::*;
+    use super::super::test_meshes::*;
+    use super::super::{ConnectedMesh, RemeshOptions};
+
+    #[test]
+    fn cluster_sphere() {
+        let mesh = sphere(DVec3::zeros(), 1.0, 5);
+        let simplified = mesh.simplify_clustering(&ClusteringOptions { resolution: 8, ..Default::default() });
+
+        assert!(simplified.triangles.len() < mesh.triangles.len() / 10);
+        assert!(simplified.triangles.len() > 50);
+        // Quadric representatives stay close to the surface
+        for p in &simplified.positions {
+            assert!((p.magnitude() - 1.0).abs() < 0.05);
+        }
+        assert!((simplified.volume() - mesh.volume()).abs() < 0.1 * mesh.volume());
+        assert_eq!(simplified.normals.as_ref().unwrap().len(), simplified.positions.len());
+    }
+
+    #[test]
+    fn cluster_keeps_sharp_corners() {
+        // A finely remeshed cube: quadric representatives snap to corners and edges
+        let mut connected_mesh = ConnectedMesh::from(&cube(DVec3::zeros(), 2.0));
+        connected_mesh.remesh(&RemeshOptions { target_edge_length: 0.1, iterations: 2, ..Default::default() });
+        let mesh = SharedMesh::from(&connected_mesh);
+
+        let simplified = mesh.simplify_clustering(&ClusteringOptions { cell_size: 0.5, ..Default::default() });
+        assert!(simplified.triangles.len() < mesh.triangles.len() / 10);
+        assert!(simplified.positions.iter().all(|p| (p.abs().max() - 1.0).abs() < 1e-6));
+        for corner in &cube(DVec3::zeros(), 2.0).positions {
+            assert!(simplified.positions.iter().any(|p| (p - corner).magnitude() < 1e-6));
+        }
+    }
+
+    #[test]
+    fn memory_budget_coarsens_grid() {
+        let mesh = sphere(DVec3::zeros(), 1.0, 4);
+        let budget = std::mem::size_of::<(u64, Cell)>() * 2 * 100;
+        let simplified = mesh.simplify_clustering(&ClusteringOptions { resolution: 1000, memory_budget: budget, ..Default::default() });
+        assert!(simplified.positions.len() <= 100);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_matches_serial() {
+        let mesh = sphere(DVec3::zeros(), 1.0, 6);
+        let serial = mesh.simplify_clustering(&ClusteringOptions { resolution: 16, ..Default::default() });
+        let parallel = mesh.simplify_clustering(&ClusteringOptions { resolution: 16, parallel: true, ..Default::default() });
+        assert_eq!(serial.triangles, parallel.triangles);
+        for (a, b) in serial.positions.iter().zip(&parallel.positions) {
+            assert!((a - b).magnitude() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use super::{SharedMesh, Group};
+use super::adjacency::*;
+use super::super::utils::DisjointSet;
+
+/// How triangles are considered connected when labeling components
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Connectivity {
+    /// Triangles sharing at least one vertex position
+    Vertex,
+    /// Triangles sharing at least one edge
+    Edge,
+}
+
+/// Per-triangle component labels
+#[derive(Debug, Clone)]
+pub struct Components {
+    /// Component id of each triangle, in `0..count`
+    pub triangle_components: Vec<u32>,
+    pub count: u32,
+}
+
+impl Components {
+    pub fn triangle_counts(&self) -> Vec<u32> {
+        let mut counts = vec![0; self.count as usize];
+        for c in &self.triangle_components {
+            counts[*c as usize] += 1;
+        }
+        counts
+    }
+
+    pub fn areas(&self, shared_mesh: &SharedMesh) -> Vec<f64> {
+        let mut areas = vec![0.0; self.count as usize];
+        for (t, c) in self.triangle_components.iter().enumerate() {
+            areas[*c as usize] += shared_mesh.triangle_area(t);
+        }
+        areas
+    }
+}
+
+impl SharedMesh {
+    /// Labels connected components ("islands"). Vertices are matched by position,
+    /// so attribute seams do not split components.
+    pub fn label_components(&self, connectivity: Connectivity) -> Components {
+        let welded = weld_positions(&self.positions);
+        let mut set = DisjointSet::new(self.triangles.len());
+
+        match connectivity {
+            Connectivity::Vertex => {
+                let mut first_triangle = vec![u32::MAX; self.positions.len()];
+                for (f, t) in self.triangles.iter().enumerate() {
+                    for k in 0..3 {
+                        let v = welded[t[k] as usize] as usize;
+                        if first_triangle[v] == u32::MAX {
+                            first_triangle[v] = f as u32;
+                        } else {
+                            set.union(first_triangle[v], f as u32);
+                        }
+                    }
+                }
+            },
+            Connectivity::Edge => {
+                for faces in edge_faces(&self.triangles, &welded).values() {
+                    for f in &faces[1..] {
+                        set.union(faces[0], *f);
+                    }
+                }
+            },
+        }
+
+        let (triangle_components, count) = set.labels();
+        Components { triangle_components, count }
+    }
+
+    /// Extracts a single component as its own mesh
+    pub fn extract_component(&self, components: &Components, component: u32) -> SharedMesh {
+        let keep: Vec<bool> = components.triangle_components.iter().map(|c| *c == component).collect();
+        self.submesh(&keep)
+    }
+
+    /// Splits the mesh into one mesh per component
+    pub fn split_components(&self, connectivity: Connectivity) -> Vec<SharedMesh> {
+        let components = self.label_components(connectivity);
+        (0..components.count)
+            .map(|c| self.extract_component(&components, c))
+            .collect()
+    }
+
+    /// Reorders triangles so that each component is contiguous and replaces groups with one group per component.
+    /// Returns the component labels, in the new triangle order.
+    pub fn group_components(&mut self, connectivity: Connectivity) -> Components {
+        let components = self.label_components(connectivity);
+        let counts = components.triangle_counts();
+
+        let mut offsets = Vec::with_capacity(counts.len());
+        let mut offset = 0;
+        for count in &counts {
+            offsets.push(offset);
+            offset += count;
+        }
+
+        self.groups = offsets.iter().zip(&counts)
+            .map(|(offset, count)| Group::new(offset * 3, count * 3))
+            .collect();
+
+        let mut triangles = self.triangles.clone();
+        let mut triangle_components = components.triangle_components.clone();
+        for (t, c) in components.triangle_components.iter().enumerate() {
+            let target = offsets[*c as usize] as usize;
+            triangles[target] = self.triangles[t];
+            triangle_components[target] = *c;
+            offsets[*c as usize] += 1;
+        }
+        self.triangles = triangles;
+
+        Components { triangle_components, count: components.count }
+    }
+
+    /// Removes components whose area is below `min_area` or whose triangle count is below `min_triangle_count`
+    /// (small floating debris, scanning noise...). Returns the number of components removed.
+    pub fn remove_small_components(&mut self, connectivity: Connectivity, min_area: f64, min_triangle_count: u32) -> u32 {
+        let components = self.label_components(connectivity);
+        let areas = components.areas(self);
+        let counts = components.triangle_counts();
+
+        let removed: Vec<bool> = (0..components.count as usize)
+            .map(|c| areas[c] < min_area || counts[c] < min_triangle_count)
+            .collect();
+
+        let removed_count = removed.iter().filter(|x| **x).count() as u32;
+        if removed_count > 0 {
+            let keep: Vec<bool> = components.triangle_components.iter().map(|c| !removed[*c as usize]).collect();
+            *self = self.submesh(&keep);
+        }
+        removed_count
+    }
+}
+
+#[cfg(test)]
+mod components_tests {
+    use super::*;
+    use super::super::test_meshes::*;
+    use nalgebra_glm as glm;
+    use glm::{DVec3, U32Vec3};
+
+    fn two_cubes_and_debris() -> SharedMesh {
+        let mesh = SharedMesh::combine(cube(DVec3::zeros(), 1.0), cube(DVec3::new(5., 0., 0.), 2.0));
+        SharedMesh::combine(mesh, cube(DVec3::new(-5., 0., 0.), 0.01))
+    }
+
+    #[test]
+    fn label_cubes() {
+        let mesh = two_cubes_and_debris();
+        let components = mesh.label_components(Connectivity::Edge);
+        assert_eq!(components.count, 3);
+        assert_eq!(components.triangle_counts(), vec![12, 12, 12]);
+    }
+
+    #[test]
+    fn vertex_and_edge_connectivity_differ() {
+        // Two triangles only touching by a vertex
+        let mesh = SharedMesh {
+            groups: Vec::new(),
+            triangles: vec![U32Vec3::new(0, 1, 2), U32Vec3::new(2, 3, 4)],
+            positions: vec![DVec3::new(0., 0., 0.), DVec3::new(1., 0., 0.), DVec3::new(1., 1., 0.), DVec3::new(2., 1., 0.), DVec3::new(2., 2., 0.)],
+            normals: None,
+            colors: None,
+            uvs: None,
+        };
+        assert_eq!(mesh.label_components(Connectivity::Vertex).count, 1);
+        assert_eq!(mesh.label_components(Connectivity::Edge).count, 2);
+    }
+
+    #[test]
+    fn split_and_group() {
+        let mut mesh = two_cubes_and_debris();
+        let parts = mesh.split_components(Connectivity::Edge);
+        assert_eq!(parts.len(), 3);
+        assert!(parts.iter().all(|p| p.positions.len() == 8 && p.triangles.len() == 12));
+
+        mesh.group_components(Connectivity::Vertex);
+        assert_eq!(mesh.groups.len(), 3);
+        assert_eq!(mesh.groups[1], Group::new(36, 36));
+    }
+
+    #[test]
+    fn remove_debris() {
+        let mut mesh = two_cubes_and_debris();
+        assert_eq!(mesh.remove_small_components(Connectivity::Edge, 0.1, 0), 1);
+        assert_eq!(mesh.triangles.len(), 24);
+        assert_eq!(mesh.positions.len(), 16);
+    }
+}
@@ -3,14 +3,18 @@ use std::fmt::*;
 type U32Map = HashMap::<u32, u32>;
 type U32Set = HashSet::<u32>;
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ConnectedMesh {
     nodes: Vec<Node>,
     face_count: u32,
 
     positions: Vec<DVec3>,
     normals: Option<Vec<DVec3>>,
-    // uv0: Vec<Vector3>,
-    // colors: Vec<Vector3>,
+    colors: Option<Vec<DVec3>>,
+    uvs: Option<Vec<DVec2>>,
+
+    // Ranges of node indices. Nodes are never reordered, so groups stay valid through collapses.
+    groups: Vec<Group>,
 }
 
 impl Default for ConnectedMesh {
@@ -18,6 +22,9 @@ impl Default for ConnectedMesh {
         ConnectedMesh { 
             positions: Vec::new(),
             normals: None,
+            colors: None,
+            uvs: None,
+            groups: Vec::new(),
             nodes: Vec::new(),
             face_count: 0
         }
@@ -238,46 +245,16 @@ impl ConnectedMesh {
         return Some(first_valid);
     }
 
-    fn get_edge_topo(&self, node_index_a: u32, node_index_b: u32) -> f64 {
-        let pos_b = self.nodes[node_index_b as usize].position;
-        let mut faces_attached = 0;
-        let mut attribute_at_a: u32 = u32::MAX;
-        let mut attribute_at_b: u32 = u32::MAX;
-        let mut edge_weight = 0.0;
-        
-        loop_siblings!(node_index_a, self.nodes, sibling_of_a, {
-            if !self.nodes[sibling_of_a as usize].is_removed {
-                // Maybe we should begin directly with relative?
-                loop_relatives!(sibling_of_a, self.nodes, relative_of_a, {
-                    let pos_c = self.nodes[relative_of_a as usize].position;
-                    if pos_c == pos_b {
-                        faces_attached = faces_attached + 1;
-    
-                        match &self.normals {
-                            Some(normals) => {
-                                if attribute_at_b != u32::MAX && normals[attribute_at_b as usize] == normals[self.nodes[relative_of_a as usize].normal as usize] {
-                                    edge_weight = edge_weight + 10.0
-                                }
-                                if attribute_at_a != u32::MAX && normals[attribute_at_a as usize] == normals[self.nodes[sibling_of_a as usize].normal as usize] {
-                                    edge_weight = edge_weight + 10.0
-                                }
-                            },
-                            None => ()
-                        }
-    
-                        attribute_at_b = self.nodes[relative_of_a as usize].normal;
-                        attribute_at_a = self.nodes[sibling_of_a as usize].normal;
-                    }
-                });
+    // Group index of each node, or u32::MAX for nodes outside of any group
+    fn node_groups(&self) -> Vec<u32> {
+        let mut node_groups = vec![u32::MAX; self.nodes.len()];
+        for (i, group) in self.groups.iter().enumerate() {
+            let end = ((group.first_index + group.index_count) as usize).min(self.nodes.len());
+            for node_group in &mut node_groups[(group.first_index as usize).min(end)..end] {
+                *node_group = i as u32;
             }
-        });
-
-        // Check if border
-        if faces_attached < 2 {
-            edge_weight = edge_weight + 100.0;
         }
-
-        return edge_weight;
+        node_groups
     }
 
     fn get_face_normal(&mut self, node_index: u32) -> DVec3 {
@@ -292,29 +269,34 @@ impl ConnectedMesh {
 }
 
 include!("decimate/decimate.rs");
+include!("subdivide/subdivide.rs");
+include!("remesh/remesh.rs");
+include!("smooth/smooth.rs");
+include!("topology/topology.rs");
 
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Node {
     sibling: u32,
     relative: u32,
 
     position: u32,
     normal: u32,
-    // uv0: u32,
-    // color: u32,
+    color: u32,
+    uv: u32,
 
     is_removed: bool,
 }
 
 impl Node {
     fn from_layout(position: u32, sibling: u32, relative: u32) -> Self {
-        Node { position: position, sibling: sibling, relative: relative,  normal: 0, is_removed: false }
+        Node { position: position, sibling: sibling, relative: relative,  normal: 0, color: 0, uv: 0, is_removed: false }
     }
 }
 
 impl Default for Node {
     fn default() -> Self {
-        Node { position: 0, sibling: 0, relative: 0,  normal: 0, is_removed: false }
+        Node { position: 0, sibling: 0, relative: 0,  normal: 0, color: 0, uv: 0, is_removed: false }
     }
 }
 
@@ -377,6 +359,9 @@ mod connected_mesh_tests {
             positions: positions,
             nodes: nodes,
             normals: None,
+            colors: None,
+            uvs: None,
+            groups: Vec::new(),
             face_count: 6 };
 
         // Verify connectivity
@@ -2,7 +2,10 @@
 struct CollapseContext {
     collapse_to: DVec3,
     error: f64, // TODO: f32 ?
-    weight: f64, // TODO: f32 ?
+    // Times this collapse was rejected since the edge last changed
+    rejections: u32,
+    // Distance to the original surface, and number of collapses performed when it was measured
+    measured: Option<(f64, u32)>,
 }
 
 impl Default for CollapseContext {
@@ -10,7 +13,8 @@ impl Default for CollapseContext {
         Self {
             collapse_to: DVec3::default(),
             error: 0.,
-            weight: 0.,
+            rejections: 0,
+            measured: None,
         }
     }
 }
@@ -1,12 +1,105 @@
-use super::base::SymmetricMatrix;
+use super::base::{Box3, SymmetricMatrix};
+use super::progress::{Cancelled, NoProgress, Progress};
+use super::spatial::triangle;
 
 use std::hash::Hash;
+use std::ops::AddAssign;
+use std::time::{Duration, Instant};
 use priority_queue::PriorityQueue;
 use hashbrown::HashSet;
 use pool::Pool;
 
 include!("edge.rs");
 include!("collapse_context.rs");
+include!("lod.rs");
+include!("quadric.rs");
+include!("deviation.rs");
+
+// Rejected collapses are queued again with their error multiplied by this factor, a few times at most
+const REJECTION_PENALTY: f64 = 4.0;
+const MAX_REJECTIONS: u32 = 3;
+
+/// How edges of a kind are preserved while decimating
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EdgeConstraint {
+    Free,
+    /// Planes going through these edges, perpendicular to their faces, are added to quadrics with this weight
+    Weighted(f64),
+    /// Vertices on these edges are never moved nor removed
+    Locked,
+}
+
+#[derive(Debug, Clone)]
+pub struct DecimationOptions {
+    /// Weight of normals in the collapse error, zero for geometry only.
+    /// Attributes are scaled by the diagonal of the bounding box: with a weight of 1, a full-range attribute
+    /// difference costs as much as a distance of that diagonal.
+    pub normal_weight: f64,
+    /// Weight of colors in the collapse error, zero for geometry only
+    pub color_weight: f64,
+    /// Weight of texture coordinates in the collapse error, zero for geometry only
+    pub uv_weight: f64,
+    /// Weight faces by their area, so that small faces don't hold large ones in place. Off by default.
+    pub area_weighted: bool,
+    /// How open borders are preserved. Free by default, use `EdgeConstraint::Weighted(1.0)` to keep their shape.
+    pub boundaries: EdgeConstraint,
+    /// How edges with a dihedral angle above `feature_angle` are preserved
+    pub features: EdgeConstraint,
+    /// Dihedral angle above which edges are features, in radians
+    pub feature_angle: f64,
+    /// How borders between groups are preserved
+    pub group_borders: EdgeConstraint,
+    /// Reject collapses that would flip faces or make them degenerate. Off by default.
+    pub prevent_flips: bool,
+    /// Reject collapses producing faces with a larger aspect ratio (longest edge over shortest height, 1 for equilateral
+    /// faces), unless they improve it. Infinity to allow any shape.
+    pub max_aspect_ratio: f64,
+    /// Vertices (as `VertexId`) that must never be moved nor removed. Missing vertices are not locked.
+    pub locked_vertices: Vec<bool>,
+    /// Importance of each vertex (as `VertexId`), multiplying the error of collapses involving it.
+    /// Missing vertices have an importance of 1.
+    pub vertex_weights: Vec<f64>,
+}
+
+impl Default for DecimationOptions {
+    fn default() -> Self {
+        DecimationOptions {
+            normal_weight: 0.0,
+            color_weight: 0.0,
+            uv_weight: 0.0,
+            area_weighted: false,
+            boundaries: EdgeConstraint::Free,
+            features: EdgeConstraint::Free,
+            feature_angle: std::f64::consts::FRAC_PI_4,
+            group_borders: EdgeConstraint::Free,
+            prevent_flips: false,
+            max_aspect_ratio: f64::INFINITY,
+            locked_vertices: Vec::new(),
+            vertex_weights: Vec::new(),
+        }
+    }
+}
+
+/// When to stop decimating: as soon as any of the set conditions is met
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct DecimationTarget {
+    pub triangle_count: Option<u32>,
+    /// Maximum distance between the decimated and the original surfaces, in world units.
+    /// Collapses going further are skipped. Distances are measured at samples of both surfaces, which is slower than
+    /// decimating to a triangle count.
+    pub max_error: Option<f64>,
+    /// Time after which decimation stops. Not supported on wasm32-unknown-unknown, which has no clock.
+    pub time_budget: Option<Duration>,
+}
+
+/// What a decimation achieved
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DecimationResult {
+    pub triangle_count: u32,
+    /// Largest geometric error of the collapses performed, in world units: the distance to the original surface with
+    /// a `max_error` target, an estimate from quadrics otherwise
+    pub error: f64,
+}
 
 impl ConnectedMesh {    
     pub fn decimate_to_ratio(&mut self, target_triangle_ratio: f32) {
@@ -14,6 +107,71 @@ impl ConnectedMesh {
     }
 
     pub fn decimate(&mut self, target_triangle_count: u32) {
+        self.decimate_with_options(target_triangle_count, &DecimationOptions::default());
+    }
+
+    /// Decimates the mesh down to `target_triangle_count` triangles, taking attributes into account as set in `options`.
+    /// Normals, colors and texture coordinates are interpolated at the position each edge collapses to.
+    pub fn decimate_with_options(&mut self, target_triangle_count: u32, options: &DecimationOptions) {
+        self.decimate_while(options, None, |connected_mesh, _| connected_mesh.face_count > target_triangle_count);
+    }
+
+    /// Decimates the mesh for as long as it can stay within `max_error` of the original surface, in world units
+    pub fn decimate_to_error(&mut self, max_error: f64) -> DecimationResult {
+        self.decimate_to_target(&DecimationTarget { max_error: Some(max_error), ..Default::default() }, &DecimationOptions::default())
+    }
+
+    /// Decimates the mesh until one of the conditions of `target` is met, or until it can't be decimated further
+    pub fn decimate_to_target(&mut self, target: &DecimationTarget, options: &DecimationOptions) -> DecimationResult {
+        self.decimate_with_progress(target, options, &mut NoProgress).expect("decimation without progress can't be cancelled")
+    }
+
+    /// Decimates the mesh as `decimate_to_target` does, reporting progress towards the closest target.
+    /// When cancelled, the mesh is left valid, with the collapses performed so far.
+    pub fn decimate_with_progress(&mut self, target: &DecimationTarget, options: &DecimationOptions, progress: &mut dyn Progress) -> std::result::Result<DecimationResult, Cancelled> {
+        let start = target.time_budget.map(|_| Instant::now());
+        let initial_count = self.face_count;
+        let mut error = 0.0f64;
+        let mut collapses = 0u32;
+        // Reported progress never goes back, though errors of the next collapses go up and down
+        let mut reported = 0.0f32;
+        let mut is_cancelled = false;
+        self.decimate_while(options, target.max_error, |connected_mesh, next_error| {
+            let proceed = target.triangle_count.map_or(true, |count| connected_mesh.face_count > count)
+                && target.time_budget.zip(start).map_or(true, |(budget, start)| start.elapsed() < budget);
+            if proceed {
+                error = error.max(next_error);
+            }
+
+            collapses += 1;
+            if proceed && collapses % 256 == 0 {
+                let fractions = [
+                    target.triangle_count.map(|count| (initial_count - connected_mesh.face_count) as f32 / (initial_count.saturating_sub(count)).max(1) as f32),
+                    target.max_error.map(|max_error| (error / max_error) as f32),
+                    target.time_budget.zip(start).map(|(budget, start)| start.elapsed().as_secs_f32() / budget.as_secs_f32()),
+                    // Fraction of faces removed, which also gives progress when decimating without any target
+                    Some((initial_count - connected_mesh.face_count) as f32 / initial_count.max(1) as f32),
+                ];
+                reported = fractions.iter().flatten().fold(reported, |a, b| a.max(*b)).min(1.0);
+                is_cancelled = !progress.report(reported);
+                return !is_cancelled;
+            }
+            proceed
+        });
+
+        if is_cancelled {
+            return Err(Cancelled);
+        }
+        // Decimation is done, so cancelling no longer has any effect
+        progress.report(1.0);
+        Ok(DecimationResult { triangle_count: self.face_count, error })
+    }
+
+    // Collapses edges by increasing error for as long as `proceed` returns true.
+    // `proceed` is given the mesh and the geometric error of the next collapse, in world units. With `max_error`, this
+    // is the distance to the original surface, and collapses going further are skipped. Otherwise, it is estimated
+    // from quadrics.
+    fn decimate_while(&mut self, options: &DecimationOptions, max_error: Option<f64>, mut proceed: impl FnMut(&ConnectedMesh, f64) -> bool) {
 
         macro_rules! loop_edges {
             ($node_index:expr, $edge_buffer:expr,$nodes:expr, $relative:ident, $exec:expr) => {{
@@ -35,7 +193,8 @@ impl ConnectedMesh {
 
         let mut queue = PriorityQueue::<Edge, CollapseContext, _>::with_hasher(BuildHasherDefault::<SimpleHasher>::default());
         let mut position_to_node = U32Map::new();
-        let mut quadrics = vec![SymmetricMatrix::default_uninitalized(); self.positions.len()];
+        let mut quadrics = Quadrics::new(self, options);
+        let mut deviation = max_error.map(|_| SurfaceDeviation::new(self));
 
         let mut pool = Pool::with_capacity(20, 0, || U32Set::new() /* SetU32::new() */);
 
@@ -51,12 +210,7 @@ impl ConnectedMesh {
 
         // Initialize quadrics
         for pos_to_node in &position_to_node {
-            calculate_quadric(self, &mut quadrics, *pos_to_node.1);
-        }
-
-        // Initialize weights
-        for x in &mut queue {
-            calculate_weight(self, &position_to_node, x.0, x.1);
+            calculate_quadric(self, &mut quadrics, options, *pos_to_node.1);
         }
 
         // Initialize errors
@@ -65,7 +219,7 @@ impl ConnectedMesh {
         
             for x in &queue {
                 let mut collapse_context = CollapseContext::default();
-                calculate_error(self, &mut quadrics, &queue, &position_to_node, &mut pool.checkout().unwrap(), x.0, &mut collapse_context);
+                calculate_error(self, &quadrics, x.0, &mut collapse_context);
                 collapse_contexts.push(collapse_context);
             }
     
@@ -78,9 +232,8 @@ impl ConnectedMesh {
         }
 
         // Iterate
-        while self.face_count > target_triangle_count {
+        while let Some(pair_to_collapse) = queue.pop() {
 
-            let pair_to_collapse = queue.pop().unwrap();
             let edge_to_collapse = pair_to_collapse.0;
             let collapse_context = pair_to_collapse.1;
 
@@ -93,9 +246,64 @@ impl ConnectedMesh {
                 Some(_) => (),
                 None => continue
             };
+
+            if quadrics.locked[edge_to_collapse.pos_a as usize] && quadrics.locked[edge_to_collapse.pos_b as usize] {
+                continue;
+            }
+
+            let node_index_a = *position_to_node.get(&edge_to_collapse.pos_a).unwrap();
+            let half_edge = match self.find_half_edge(node_index_a, edge_to_collapse.pos_b) {
+                Some(half_edge) => half_edge,
+                None => continue,
+            };
+            if !self.satisfies_link_condition_unchecked(half_edge) || !self.is_collapse_safe(node_index_a, *position_to_node.get(&edge_to_collapse.pos_b).unwrap(), &collapse_context.collapse_to, options) {
+                // Tried again later, as collapses around may make it valid
+                if collapse_context.rejections < MAX_REJECTIONS {
+                    let mut collapse_context = collapse_context;
+                    collapse_context.rejections += 1;
+                    collapse_context.error = collapse_context.error.min(-f64::MIN_POSITIVE) * REJECTION_PENALTY;
+                    queue.push(edge_to_collapse, collapse_context);
+                }
+                continue;
+            }
+
+            // Quadric errors are sums of squared distances
+            let mut error = (-collapse_context.error / REJECTION_PENALTY.powi(collapse_context.rejections as i32)).max(0.0).sqrt();
+            let node_index_b = *position_to_node.get(&edge_to_collapse.pos_b).unwrap();
+            let mut faces = Vec::new();
+            if let (Some(deviation), Some(max_error)) = (&deviation, max_error) {
+                faces = self.faces_around_edge(node_index_a, node_index_b);
+                error = match collapse_context.measured {
+                    Some((measured, collapses)) if deviation.is_unchanged(&edge_to_collapse, collapses) => measured,
+                    _ => {
+                        let measured = deviation.collapse_error(self, &faces, node_index_a, node_index_b, &collapse_context.collapse_to, max_error);
+                        // Collapses going further from the original surface than estimated wait for their turn
+                        if measured * measured > -collapse_context.error {
+                            let mut collapse_context = collapse_context;
+                            collapse_context.error = -measured * measured;
+                            collapse_context.measured = Some((measured, deviation.collapses));
+                            queue.push(edge_to_collapse, collapse_context);
+                            continue;
+                        }
+                        measured
+                    }
+                };
+                // Skipped until collapses around refresh the edge
+                if error > max_error {
+                    continue;
+                }
+            }
+            if !proceed(self, error) {
+                break;
+            }
         
+            let attribute_pairs = self.edge_attributes(node_index_a, edge_to_collapse.pos_b);
+            let pos_a = self.positions[edge_to_collapse.pos_a as usize];
+            let ab = self.positions[edge_to_collapse.pos_b as usize] - pos_a;
+            let t = if ab.magnitude_squared() > 0.0 { ((collapse_context.collapse_to - pos_a).dot(&ab) / ab.magnitude_squared()).clamp(0.0, 1.0) } else { 0.5 };
+
             // Collapse edge
-            let valid_node_index_o = self.collapse_edge_to_a(*position_to_node.get(&edge_to_collapse.pos_a).unwrap(), *position_to_node.get(&edge_to_collapse.pos_b).unwrap(), &mut Some(&mut position_to_node));
+            let valid_node_index_o = self.collapse_edge_to_a(node_index_a, node_index_b, &mut Some(&mut position_to_node));
 
             if valid_node_index_o.is_none() {
                 continue;
@@ -103,11 +311,18 @@ impl ConnectedMesh {
 
             let valid_node_index = valid_node_index_o.unwrap();
 
+            self.interpolate_attributes(valid_node_index, &attribute_pairs, t);
+            quadrics.locked[edge_to_collapse.pos_a as usize] |= quadrics.locked[edge_to_collapse.pos_b as usize];
+            quadrics.importance[edge_to_collapse.pos_a as usize] = quadrics.importance[edge_to_collapse.pos_a as usize].max(quadrics.importance[edge_to_collapse.pos_b as usize]);
+
             // Use optimal position
             self.positions[self.nodes[valid_node_index as usize].position as usize] = collapse_context.collapse_to;
+            if let Some(deviation) = &mut deviation {
+                deviation.attach(self, &faces, valid_node_index);
+            }
 
             // Recalculate quadric at A
-            calculate_quadric(self, &mut quadrics, valid_node_index);
+            calculate_quadric(self, &mut quadrics, options, valid_node_index);
 
             let node_a = self.nodes[valid_node_index as usize];
 
@@ -117,11 +332,9 @@ impl ConnectedMesh {
                 let node_c = self.nodes[relative as usize];
                 let edge = &Edge::new(node_a.position, node_c.position);
                 // Recompute quadric
-                calculate_quadric(self, &mut quadrics, node_c.sibling);
+                calculate_quadric(self, &mut quadrics, options, node_c.sibling);
                 // Refresh edge in queue (new collapse target position)
-                let mut collapse_context = CollapseContext::default();
-                queue.push(*edge, collapse_context);
-                calculate_weight(self, &position_to_node, edge, &mut collapse_context);
+                queue.push(*edge, CollapseContext::default());
             });
 
             for position in positions.iter() {
@@ -129,45 +342,80 @@ impl ConnectedMesh {
                 let edge = &Edge::new(node_a.position, *position);
                 // Refresh edge in queue (new collapse target position)
                 let mut collapse_context = *queue.get(&edge).unwrap().1;
-                calculate_error(self, &mut quadrics, &queue, &position_to_node, &mut pool.checkout().unwrap(), edge, &mut collapse_context);
+                calculate_error(self, &quadrics, edge, &mut collapse_context);
                 queue.change_priority(edge, collapse_context);
             }
         }
 
-        fn calculate_quadric(connected_mesh: &mut ConnectedMesh, quadrics: &mut Vec<SymmetricMatrix>, node_index: u32)
+        fn calculate_quadric(connected_mesh: &mut ConnectedMesh, quadrics: &mut Quadrics, options: &DecimationOptions, node_index: u32)
         {
+            let position = connected_mesh.nodes[node_index as usize].position as usize;
+            // Faces are weighted by their area relative to the mean area of the faces around, so that errors remain
+            // sums of squared distances
+            let mut mean_area = 0.0;
+            if options.area_weighted {
+                let mut count = 0;
+                loop_siblings!(node_index, connected_mesh.nodes, sibling, {
+                    mean_area += connected_mesh.face_area(sibling);
+                    count += 1;
+                });
+                mean_area /= count as f64;
+            }
+            let area_weight = |connected_mesh: &ConnectedMesh, sibling: u32| {
+                if mean_area > 0.0 { connected_mesh.face_area(sibling) / mean_area } else { 1.0 }
+            };
+            let planes = connected_mesh.constraint_planes(quadrics, options, node_index);
+
+            if !quadrics.attributes.is_empty() {
+                let mut quadric = AttributeQuadric::zeroes(quadrics.attributes[position].dimension);
+                loop_siblings!(node_index, connected_mesh.nodes, sibling, {
+                    let node_a = connected_mesh.nodes[sibling as usize];
+                    let node_b = connected_mesh.nodes[node_a.relative as usize];
+                    let node_c = connected_mesh.nodes[node_b.relative as usize];
+                    let points = [node_a, node_b, node_c].map(|node| connected_mesh.attribute_vector(&node, &quadrics.attribute_weights));
+                    let mut face_quadric = AttributeQuadric::from_face([&points[0], &points[1], &points[2]]);
+                    face_quadric.scale(area_weight(connected_mesh, sibling));
+                    quadric += &face_quadric;
+                });
+                for (normal, dot, weight) in &planes {
+                    quadric.add_plane(normal, *dot, *weight);
+                }
+                quadric.scale(quadrics.importance[position]);
+                quadrics.attributes[position] = quadric;
+                return;
+            }
+
             let mut matrix = SymmetricMatrix::default_zeroes();
 
             loop_siblings!(node_index, connected_mesh.nodes, sibling, {
                 let face_normal = &connected_mesh.get_face_normal(sibling);
-                let position = &connected_mesh.positions[connected_mesh.nodes[sibling as usize].position as usize];
-                let dot = &-face_normal.dot(position);
-                matrix += SymmetricMatrix::from_normal(face_normal, &dot);
+                // Degenerate faces have no plane
+                if face_normal.iter().all(|x| x.is_finite()) {
+                    let position = &connected_mesh.positions[connected_mesh.nodes[sibling as usize].position as usize];
+                    let dot = &-face_normal.dot(position);
+                    matrix += SymmetricMatrix::from_normal(face_normal, &dot) * area_weight(connected_mesh, sibling);
+                }
             });
-            quadrics[connected_mesh.nodes[node_index as usize].position as usize] = matrix;
-
-            // TODO: Take surface area into consideration
-            // TODO: "For each face adjacent to a given boundary edge, we compute a plane through the edge that is perpendicular to the face"
-        }
-
-        fn calculate_weight(connected_mesh: &ConnectedMesh, position_to_node: &U32Map, edge: &Edge, collapse_context: &mut CollapseContext)
-        {
-            let node_a = *position_to_node.get(&edge.pos_a).unwrap();
-            let node_b = *position_to_node.get(&edge.pos_b).unwrap();
-
-            collapse_context.weight = connected_mesh.get_edge_topo(node_a, node_b);
+            // "For each face adjacent to a given boundary edge, we compute a plane through the edge that is perpendicular to the face"
+            for (normal, dot, weight) in &planes {
+                matrix += SymmetricMatrix::from_normal(normal, dot) * *weight;
+            }
+            quadrics.geometric[position] = matrix * quadrics.importance[position];
         }
 
-        fn calculate_error(connected_mesh: &mut ConnectedMesh, quadrics: &mut Vec<SymmetricMatrix>, queue: &PriorityQueue::<Edge, CollapseContext, BuildHasherDefault<SimpleHasher>>, position_to_node: &U32Map, edge_buffer: &mut U32Set, edge: &Edge, collapse_context: &mut CollapseContext)
+        fn calculate_error(connected_mesh: &mut ConnectedMesh, quadrics: &Quadrics, edge: &Edge, collapse_context: &mut CollapseContext)
         {
             let pos_a = &connected_mesh.positions[edge.pos_a as usize];
             let pos_b = &connected_mesh.positions[edge.pos_b as usize];
             let pos_c = &(&(pos_a + pos_b) / 2.0);
 
-            //let node_a = *position_to_node.get(&edge.pos_a).unwrap();
-            //let node_b = *position_to_node.get(&edge.pos_b).unwrap();
-
-            let matrix = &quadrics[edge.pos_a as usize] + &quadrics[edge.pos_b as usize];
+            let matrix = &if quadrics.attributes.is_empty() {
+                &quadrics.geometric[edge.pos_a as usize] + &quadrics.geometric[edge.pos_b as usize]
+            } else {
+                let mut quadric = quadrics.attributes[edge.pos_a as usize].clone();
+                quadric += &quadrics.attributes[edge.pos_b as usize];
+                quadric.to_symmetric_matrix()
+            };
 
             let det = matrix.get_det_xyz();
 
@@ -182,36 +430,388 @@ impl ConnectedMesh {
                 (f64::MAX, DVec3::default())
             };
 
-            let mut error_a = matrix.quadric_distance_to_vertex(&pos_a);
-            let mut error_b = matrix.quadric_distance_to_vertex(&pos_b);
+            let error_a = matrix.quadric_distance_to_vertex(&pos_a);
+            let error_b = matrix.quadric_distance_to_vertex(&pos_b);
             let mut error_c = matrix.quadric_distance_to_vertex(&pos_c);
 
-            // We multiply by edge length to be agnotics with quadrics error.
-            // Otherwise it becomes too scale dependent
-            //let length = (pos_b - pos_a).magnitude();
-
-            // loop_edges!(node_a, edge_buffer, &connected_mesh.nodes, relative, {
-            //     let pos_d_index = connected_mesh.nodes[relative as usize].position;
-            //     let pos_d = &connected_mesh.positions[pos_d_index as usize];
-            //     let weight = queue.get(&Edge::new(edge.pos_a, pos_d_index)).unwrap().1.weight;
-            //     error_b += weight * length * pos_a.distance_to_line(pos_b, pos_d);
-            //     error_c += weight * length * pos_a.distance_to_line(pos_c, pos_d);
-            // });
-
-            // loop_edges!(node_b, edge_buffer, &connected_mesh.nodes, relative, {
-            //     let pos_d_index = connected_mesh.nodes[relative as usize].position;
-            //     let pos_d = &connected_mesh.positions[pos_d_index as usize];
-            //     let weight = queue.get(&Edge::new(edge.pos_b, pos_d_index)).unwrap().1.weight;
-            //     error_a += weight * length * pos_b.distance_to_line(pos_a, pos_d);
-            //     error_c += weight * length * pos_b.distance_to_line(pos_c, pos_d);
-            // });
-
             error_c *= 0.4716252;
 
-            let (xerror, xpos) = min!(*error_o, pos_o, error_a, pos_a, error_b, pos_b, error_c, pos_c);
+            // Locked positions stay in place
+            let (xerror, xpos) = match (quadrics.locked[edge.pos_a as usize], quadrics.locked[edge.pos_b as usize]) {
+                (true, true) => (f64::MAX, pos_a),
+                (true, false) => (error_a, pos_a),
+                (false, true) => (error_b, pos_b),
+                (false, false) => min!(*error_o, pos_o, error_a, pos_a, error_b, pos_b, error_c, pos_c),
+            };
 
+            collapse_context.measured = None;
             collapse_context.error = -xerror; // Negative is a small hack because PriorityQueue is max based, but we want min
             collapse_context.collapse_to = *xpos;
         }
     }
-}
\ No newline at end of file
+}
+impl ConnectedMesh {
+    // Half-edge between the position of the node and `position_b`, in either direction
+    fn find_half_edge(&self, node_index: u32, position_b: u32) -> Option<HalfEdgeId> {
+        let mut half_edge = None;
+        loop_siblings!(node_index, self.nodes, sibling, {
+            let next = self.nodes[sibling as usize].relative;
+            let previous = self.nodes[next as usize].relative;
+            if self.nodes[next as usize].position == position_b {
+                half_edge = Some(HalfEdgeId(sibling));
+            } else if self.nodes[previous as usize].position == position_b {
+                half_edge = half_edge.or(Some(HalfEdgeId(previous)));
+            }
+        });
+        half_edge
+    }
+
+    // Faces around both ends of the edge between the positions of the nodes
+    fn faces_around_edge(&self, node_index_a: u32, node_index_b: u32) -> Vec<u32> {
+        let mut faces = Vec::new();
+        for node_index in [node_index_a, node_index_b] {
+            loop_siblings!(node_index, self.nodes, sibling, {
+                if !faces.contains(&(sibling / 3)) {
+                    faces.push(sibling / 3);
+                }
+            });
+        }
+        faces
+    }
+
+    // Whether the faces left around both ends of the edge keep their orientation and shape once moved to `target`
+    fn is_collapse_safe(&self, node_index_a: u32, node_index_b: u32, target: &DVec3, options: &DecimationOptions) -> bool {
+        let ends = [self.nodes[node_index_a as usize].position, self.nodes[node_index_b as usize].position];
+        let aspect_ratio = |corners: &[DVec3; 3], normal: &DVec3| {
+            let longest = (0..3).map(|k| (corners[(k + 1) % 3] - corners[k]).magnitude_squared()).fold(0.0, f64::max);
+            // Longest edge over shortest height, with the area being half the magnitude of the normal
+            3f64.sqrt() / 2.0 * longest / normal.magnitude()
+        };
+
+        let mut is_safe = true;
+        for node_index in [node_index_a, node_index_b] {
+            loop_siblings!(node_index, self.nodes, sibling, {
+                let next = self.nodes[sibling as usize].relative;
+                let previous = self.nodes[next as usize].relative;
+                let positions = [sibling, next, previous].map(|n| self.nodes[n as usize].position);
+                // Faces along the edge are removed by the collapse
+                if is_safe && !(positions.contains(&ends[0]) && positions.contains(&ends[1])) {
+                    let before = positions.map(|p| self.positions[p as usize]);
+                    let mut after = before;
+                    after[0] = *target;
+                    let normal_before = (before[1] - before[0]).cross(&(before[2] - before[0]));
+                    let normal_after = (after[1] - after[0]).cross(&(after[2] - after[0]));
+                    if options.prevent_flips && (normal_after.magnitude_squared() == 0.0 || normal_before.dot(&normal_after) <= 0.0) {
+                        is_safe = false;
+                    } else if options.max_aspect_ratio.is_finite() {
+                        let ratio = aspect_ratio(&after, &normal_after);
+                        is_safe = ratio <= options.max_aspect_ratio || ratio <= aspect_ratio(&before, &normal_before);
+                    }
+                }
+            });
+        }
+        is_safe
+    }
+
+    // Corners at both ends of the edge from the node at `node_index` to `position_b`, for each face about to be collapsed
+    fn edge_attributes(&self, node_index: u32, position_b: u32) -> Vec<(Node, Node)> {
+        let mut pairs = Vec::new();
+        loop_siblings!(node_index, self.nodes, sibling, {
+            let node = self.nodes[sibling as usize];
+            let next = self.nodes[node.relative as usize];
+            let previous = self.nodes[next.relative as usize];
+            for other in [next, previous] {
+                if other.position == position_b {
+                    pairs.push((node, other));
+                }
+            }
+        });
+        pairs
+    }
+
+    // Gives the corners of a collapsed vertex the attributes of its edge ends interpolated at `t`, on each side of seams
+    fn interpolate_attributes(&mut self, node_index: u32, pairs: &[(Node, Node)], t: f64) {
+        let mut normals = Vec::<(u32, u32, u32)>::new();
+        let mut colors = Vec::<(u32, u32, u32)>::new();
+        let mut uvs = Vec::<(u32, u32, u32)>::new();
+        for (a, b) in pairs {
+            if !normals.iter().any(|(x, y, _)| (*x, *y) == (a.normal, b.normal)) {
+                normals.push((a.normal, b.normal, lerp_attribute(&mut self.normals, a.normal, b.normal, t, true)));
+            }
+            if !colors.iter().any(|(x, y, _)| (*x, *y) == (a.color, b.color)) {
+                colors.push((a.color, b.color, lerp_attribute(&mut self.colors, a.color, b.color, t, false)));
+            }
+            if !uvs.iter().any(|(x, y, _)| (*x, *y) == (a.uv, b.uv)) {
+                let uv = match &mut self.uvs {
+                    Some(values) if a.uv != b.uv => {
+                        values.push(glm::lerp(&values[a.uv as usize], &values[b.uv as usize], t));
+                        values.len() as u32 - 1
+                    }
+                    _ => a.uv,
+                };
+                uvs.push((a.uv, b.uv, uv));
+            }
+        }
+
+        let find = |interpolated: &[(u32, u32, u32)], index: u32| {
+            interpolated.iter().find(|(a, b, _)| *a == index || *b == index).map_or(index, |(_, _, i)| *i)
+        };
+        loop_siblings!(node_index, self.nodes, sibling, {
+            let node = &mut self.nodes[sibling as usize];
+            node.normal = find(&normals, node.normal);
+            node.color = find(&colors, node.color);
+            node.uv = find(&uvs, node.uv);
+        });
+    }
+}
+
+fn lerp_attribute(attributes: &mut Option<Vec<DVec3>>, a: u32, b: u32, t: f64, normalize: bool) -> u32 {
+    match attributes {
+        Some(attributes) if a != b => {
+            let mut value = glm::lerp(&attributes[a as usize], &attributes[b as usize], t);
+            if normalize && value.magnitude_squared() > 0.0 {
+                value = value.normalize();
+            }
+            attributes.push(value);
+            attributes.len() as u32 - 1
+        }
+        _ => a,
+    }
+}
+
+#[cfg(test)]
+mod decimate_tests {
+    use super::*;
+    use super::test_meshes::*;
+
+    #[test]
+    fn attribute_quadric() {
+        let points = [[0., 0., 1., 0.5], [2., 0., 1., 1.5], [0., 1., 1., 0.5]];
+        let quadric = AttributeQuadric::from_face([&points[0], &points[1], &points[2]]);
+        let matrix = quadric.to_symmetric_matrix();
+        // Attribute is linear over the face: only the distance to the plane z = 1 remains
+        assert!(matrix.quadric_distance_to_vertex(&DVec3::new(5., -3., 1.)).abs() < 1e-9);
+        assert!((matrix.quadric_distance_to_vertex(&DVec3::new(1., 2., 3.)) - 4.0).abs() < 1e-9);
+        // Without attributes, this is the plane quadric
+        let geometric = AttributeQuadric::from_face([&points[0][..3], &points[1][..3], &points[2][..3]]).to_symmetric_matrix();
+        let plane = SymmetricMatrix::from_normal(&DVec3::new(0., 0., 1.), &-1.0);
+        assert!(geometric.m.iter().zip(plane.m).all(|(x, y)| (x - y).abs() < 1e-9));
+    }
+
+    #[test]
+    fn uvs_are_interpolated() {
+        let mut mesh = grid(20, 1.0);
+        mesh.uvs = Some(mesh.positions.iter().map(|p| p.xy()).collect());
+        // Collapse targets stay on edges when borders are free, where attributes are interpolated exactly
+        for options in [DecimationOptions::default(), DecimationOptions { uv_weight: 0.1, ..Default::default() }] {
+            let mut connected_mesh = ConnectedMesh::from(&mesh);
+            connected_mesh.decimate_with_options(150, &options);
+            let result = SharedMesh::from(&connected_mesh);
+            assert!(result.triangles.len() <= 150);
+            for (p, uv) in result.positions.iter().zip(result.uvs.as_ref().unwrap()) {
+                assert!((p.xy() - uv).magnitude() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn colors_are_kept_with_weight() {
+        let mut mesh = grid(20, 1.0);
+        let color = |p: &DVec3| DVec3::repeat(((p.x - 0.5) * 10.0 + 0.5).clamp(0.0, 1.0));
+        mesh.colors = Some(mesh.positions.iter().map(color).collect());
+
+        let color_error = |options: &DecimationOptions| {
+            let mut connected_mesh = ConnectedMesh::from(&mesh);
+            connected_mesh.decimate_with_options(100, options);
+            let result = SharedMesh::from(&connected_mesh);
+            let colors = result.colors.as_ref().unwrap();
+            result.triangles.iter().map(|t| {
+                let centroid = t.iter().map(|v| result.positions[*v as usize]).sum::<DVec3>() / 3.0;
+                let interpolated = t.iter().map(|v| colors[*v as usize]).sum::<DVec3>() / 3.0;
+                (interpolated - color(&centroid)).magnitude()
+            }).sum::<f64>()
+        };
+
+        let weighted = color_error(&DecimationOptions { color_weight: 1.0, ..Default::default() });
+        let unweighted = color_error(&DecimationOptions::default());
+        assert!(weighted < unweighted * 0.5, "{} {}", weighted, unweighted);
+    }
+
+    #[test]
+    fn boundaries_are_kept() {
+        let mesh = grid(20, 1.0);
+        let mut connected_mesh = ConnectedMesh::from(&mesh);
+        connected_mesh.decimate_with_options(100, &DecimationOptions { boundaries: EdgeConstraint::Weighted(1.0), area_weighted: true, ..Default::default() });
+        let result = SharedMesh::from(&connected_mesh);
+        assert!(result.triangles.len() <= 100);
+        let area: f64 = result.triangles.iter().map(|t| {
+            let [a, b, c] = [0, 1, 2].map(|k| result.positions[t[k] as usize]);
+            (b - a).cross(&(c - a)).z / 2.0
+        }).sum();
+        assert!((area - 1.0).abs() < 1e-9, "{}", area);
+    }
+
+    #[test]
+    fn features_and_group_borders_are_locked() {
+        // Roof folded along x = 0.5, in two groups split along y = 0.5
+        let mut mesh = grid(20, 1.0);
+        mesh.positions.iter_mut().for_each(|p| p.z = (p.x - 0.5).abs());
+        mesh.groups = vec![Group::new(0, 1200), Group::new(1200, 1200)];
+        let on_crease = |p: &DVec3| (p.x - 0.5).abs() < 1e-9;
+        let on_border = |p: &DVec3| (p.y - 0.5).abs() < 1e-9;
+
+        let decimated = |options: &DecimationOptions| {
+            let mut connected_mesh = ConnectedMesh::from(&mesh);
+            connected_mesh.decimate_with_options(100, options);
+            let result = SharedMesh::from(&connected_mesh);
+            (result.positions.iter().filter(|p| on_crease(p)).count(), result.positions.iter().filter(|p| on_border(p)).count())
+        };
+
+        let locked = decimated(&DecimationOptions { features: EdgeConstraint::Locked, group_borders: EdgeConstraint::Locked, ..Default::default() });
+        assert_eq!(locked, (21, 21));
+        let free = decimated(&DecimationOptions::default());
+        assert!(free.0 < 21 && free.1 < 21, "{:?}", free);
+    }
+
+    #[test]
+    fn error_bounded() {
+        let mesh = sphere(DVec3::zeros(), 1.0, 4);
+        let mut connected_mesh = ConnectedMesh::from(&mesh);
+        let result = connected_mesh.decimate_to_error(0.01);
+        assert!(result.error <= 0.01);
+        assert!((result.triangle_count as usize) < mesh.triangles.len());
+        let decimated = SharedMesh::from(&connected_mesh);
+        assert_eq!(decimated.triangles.len(), result.triangle_count as usize);
+        assert!(metric::hausdorff_distance(&decimated, &mesh, 1000) <= 0.01);
+
+        // Errors are in world units (scaled by a power of two, so that rounding is the same)
+        let scaled = sphere(DVec3::zeros(), 128.0, 4);
+        let scaled_result = ConnectedMesh::from(&scaled).decimate_to_error(0.01 * 128.0);
+        assert_eq!(scaled_result, DecimationResult { triangle_count: result.triangle_count, error: result.error * 128.0 });
+    }
+
+    #[test]
+    fn error_bounded_on_wavy_sheet() {
+        let mut mesh = grid(40, 1.0);
+        mesh.positions.iter_mut().for_each(|p| p.z = 0.05 * (p.x * 12.0).sin() * (p.y * 9.0).cos());
+        for max_error in [0.001, 0.005] {
+            let mut connected_mesh = ConnectedMesh::from(&mesh);
+            let result = connected_mesh.decimate_to_error(max_error);
+            assert!(result.error <= max_error);
+            assert!((result.triangle_count as usize) < mesh.triangles.len() * 2 / 3);
+            let decimated = SharedMesh::from(&connected_mesh);
+            let measured = metric::hausdorff_distance(&decimated, &mesh, 10000);
+            assert!(measured <= max_error, "{} {}", measured, max_error);
+        }
+    }
+
+    #[test]
+    fn first_condition_stops() {
+        let mesh = sphere(DVec3::zeros(), 1.0, 3);
+        let decimate = |target: DecimationTarget| ConnectedMesh::from(&mesh).decimate_to_target(&target, &DecimationOptions::default());
+
+        let by_error = decimate(DecimationTarget { max_error: Some(0.05), ..Default::default() });
+        let by_count = decimate(DecimationTarget { triangle_count: Some(by_error.triangle_count * 2), max_error: Some(0.05), ..Default::default() });
+        assert_eq!(by_count.triangle_count, by_error.triangle_count * 2);
+        assert!(by_count.error <= by_error.error);
+
+        let by_time = decimate(DecimationTarget { triangle_count: Some(0), time_budget: Some(Duration::ZERO), ..Default::default() });
+        assert_eq!(by_time, DecimationResult { triangle_count: mesh.triangles.len() as u32, error: 0.0 });
+    }
+
+    #[test]
+    fn progress_and_cancellation() {
+        let mesh = sphere(DVec3::zeros(), 1.0, 4);
+        let target = DecimationTarget { triangle_count: Some(100), ..Default::default() };
+
+        let mut reported = Vec::new();
+        let mut connected_mesh = ConnectedMesh::from(&mesh);
+        let result = connected_mesh.decimate_with_progress(&target, &DecimationOptions::default(), &mut |p: f32| { reported.push(p); true });
+        assert_eq!(result.unwrap().triangle_count, 100);
+        assert_eq!(reported.last(), Some(&1.0));
+        assert!(reported.len() > 2 && reported.windows(2).all(|w| w[0] <= w[1]));
+
+        // Errors of the next collapses go up and down
+        let mut sheet = grid(50, 1.0);
+        sheet.positions.iter_mut().for_each(|p| p.z = 0.05 * (p.x * 12.0).sin() * (p.y * 9.0).cos());
+        let mut reported = Vec::new();
+        let by_error = DecimationTarget { max_error: Some(0.001), ..Default::default() };
+        ConnectedMesh::from(&sheet).decimate_with_progress(&by_error, &DecimationOptions::default(), &mut |p: f32| { reported.push(p); true }).unwrap();
+        assert_eq!(reported.last(), Some(&1.0));
+        assert!(reported.len() > 2 && reported.windows(2).all(|w| w[0] <= w[1]));
+
+        let mut connected_mesh = ConnectedMesh::from(&mesh);
+        let result = connected_mesh.decimate_with_progress(&target, &DecimationOptions::default(), &mut |p: f32| p < 0.5);
+        assert_eq!(result, Err(Cancelled));
+        assert!(connected_mesh.face_count > 100 && connected_mesh.face_count < mesh.triangles.len() as u32);
+        connected_mesh.check().unwrap();
+
+        // Cancelling once done has no effect
+        let result = ConnectedMesh::from(&mesh).decimate_with_progress(&target, &DecimationOptions::default(), &mut |p: f32| p < 1.0);
+        assert_eq!(result.unwrap().triangle_count, 100);
+    }
+
+    fn aspect_ratios(mesh: &SharedMesh) -> Vec<f64> {
+        mesh.triangles.iter().map(|t| {
+            let [a, b, c] = [0, 1, 2].map(|k| mesh.positions[t[k] as usize]);
+            let longest = [b - a, c - b, a - c].iter().map(|e| e.magnitude_squared()).fold(0.0, f64::max);
+            3f64.sqrt() / 2.0 * longest / (b - a).cross(&(c - a)).magnitude()
+        }).collect()
+    }
+
+    #[test]
+    fn no_flips() {
+        // Wavy sheet with free borders, which flips faces when collapses are not checked
+        let mut mesh = grid(20, 1.0);
+        mesh.positions.iter_mut().for_each(|p| p.z = 0.1 * (p.x * 17.0).sin() * (p.y * 13.0).cos());
+        let options = DecimationOptions { prevent_flips: true, ..Default::default() };
+        for target in [50, 100] {
+            let mut connected_mesh = ConnectedMesh::from(&mesh);
+            connected_mesh.decimate_with_options(target, &options);
+            connected_mesh.check().unwrap();
+            let result = SharedMesh::from(&connected_mesh);
+            assert!(result.triangles.iter().all(|t| {
+                let [a, b, c] = [0, 1, 2].map(|k| result.positions[t[k] as usize]);
+                (b - a).cross(&(c - a)).z > 0.0
+            }));
+        }
+    }
+
+    #[test]
+    fn aspect_ratio_is_bounded() {
+        let mesh = grid(20, 1.0);
+        let mut connected_mesh = ConnectedMesh::from(&mesh);
+        connected_mesh.decimate_with_options(60, &DecimationOptions { max_aspect_ratio: 4.0, ..Default::default() });
+        let result = SharedMesh::from(&connected_mesh);
+        assert!(result.triangles.len() <= 80);
+        assert!(aspect_ratios(&result).iter().all(|r| *r <= 4.0), "{:?}", aspect_ratios(&result));
+    }
+
+    #[test]
+    fn locked_vertices() {
+        let mut mesh = grid(20, 1.0);
+        mesh.positions.iter_mut().for_each(|p| p.z = 0.1 * (p.x * 17.0).sin() * (p.y * 13.0).cos());
+        let mut connected_mesh = ConnectedMesh::from(&mesh);
+        let is_locked = |p: &DVec3| (p.x - 0.25).abs() < 1e-9;
+        let locked_vertices: Vec<bool> = (0..connected_mesh.vertex_count()).map(|v| is_locked(&connected_mesh.position(VertexId(v)).unwrap())).collect();
+
+        connected_mesh.decimate_with_options(50, &DecimationOptions { locked_vertices, ..Default::default() });
+        let result = SharedMesh::from(&connected_mesh);
+        for p in mesh.positions.iter().filter(|p| is_locked(p)) {
+            assert!(result.positions.contains(p));
+        }
+    }
+
+    #[test]
+    fn important_vertices() {
+        let mesh = sphere(DVec3::zeros(), 1.0, 4);
+        let mut connected_mesh = ConnectedMesh::from(&mesh);
+        let vertex_weights = (0..connected_mesh.vertex_count()).map(|v| if connected_mesh.position(VertexId(v)).unwrap().z > 0.0 { 100.0 } else { 1.0 }).collect();
+
+        connected_mesh.decimate_with_options(200, &DecimationOptions { vertex_weights, ..Default::default() });
+        let result = SharedMesh::from(&connected_mesh);
+        let upper = result.positions.iter().filter(|p| p.z > 0.01).count();
+        let lower = result.positions.iter().filter(|p| p.z < -0.01).count();
+        assert!(upper > lower * 2, "{} {}", upper, lower);
+    }
+}
@@ -0,0 +1,226 @@
+// Distance between the decimated surface and the original one (Klein, Liebich & Straßer, 1996), so that errors don't
+// depend on what the quadrics are made of. Samples of the original surface (vertices, edge midpoints and face centroids)
+// are attached to the decimated face they were last found closest to. New edges are sampled at the spacing of the
+// original edges and projected onto the original surface, refining where the distance peaks between samples, and so
+// are face centroids. Being sampled, distances can still be exceeded between samples where the original surface is
+// coarse and strongly curved.
+
+struct SurfaceDeviation {
+    original: Bvh,
+    // Samples of the original surface, by face (node index / 3) of the decimated mesh
+    samples: Vec<Vec<DVec3>>,
+    // Mean edge length of the original mesh
+    spacing: f64,
+    // Distance of each position to the original surface
+    distances: Vec<f64>,
+    // Number of collapses performed, and for each position the number of collapses when faces around it last changed
+    collapses: u32,
+    changed_at: Vec<u32>,
+}
+
+// Times the distance along an edge is halved around peaks between samples
+const PEAK_REFINEMENTS: u32 = 6;
+
+impl SurfaceDeviation {
+    fn new(connected_mesh: &ConnectedMesh) -> Self {
+        let mut triangles = Vec::new();
+        let mut samples = vec![Vec::new(); connected_mesh.nodes.len() / 3];
+        let mut is_sampled = vec![false; connected_mesh.positions.len()];
+        let mut sampled_edges = HashSet::new();
+        let mut length = 0.0;
+        for (face, face_samples) in samples.iter_mut().enumerate() {
+            if connected_mesh.nodes[3 * face].is_removed {
+                continue;
+            }
+            let positions = [0, 1, 2].map(|k| connected_mesh.nodes[3 * face + k].position);
+            let corners = positions.map(|p| connected_mesh.positions[p as usize]);
+            face_samples.push((corners[0] + corners[1] + corners[2]) / 3.0);
+            for k in 0..3 {
+                let next = (k + 1) % 3;
+                if !is_sampled[positions[k] as usize] {
+                    is_sampled[positions[k] as usize] = true;
+                    face_samples.push(corners[k]);
+                }
+                if sampled_edges.insert((positions[k].min(positions[next]), positions[k].max(positions[next]))) {
+                    face_samples.push((corners[k] + corners[next]) / 2.0);
+                }
+                length += (corners[next] - corners[k]).magnitude();
+            }
+            triangles.push(corners);
+        }
+
+        let spacing = if triangles.is_empty() { 0.0 } else { length / (3 * triangles.len()) as f64 };
+        Self {
+            original: Bvh::from_triangles(triangles),
+            samples,
+            spacing,
+            distances: vec![0.0; connected_mesh.positions.len()],
+            collapses: 0,
+            changed_at: vec![0; connected_mesh.positions.len()],
+        }
+    }
+
+    // Whether faces around both ends of the edge are unchanged since `collapses` collapses were performed
+    fn is_unchanged(&self, edge: &Edge, collapses: u32) -> bool {
+        self.changed_at[edge.pos_a as usize] <= collapses && self.changed_at[edge.pos_b as usize] <= collapses
+    }
+
+    // Largest distance between both surfaces over `faces`, once the edge between the positions of the nodes at
+    // `node_index_a` and `node_index_b` is collapsed to `target`. Measuring stops beyond `limit`.
+    fn collapse_error(&self, connected_mesh: &ConnectedMesh, faces: &[u32], node_index_a: u32, node_index_b: u32, target: &DVec3, limit: f64) -> f64 {
+        let ends = [connected_mesh.nodes[node_index_a as usize].position, connected_mesh.nodes[node_index_b as usize].position];
+        let mut collapsed = Vec::new();
+        let mut collapsed_index = Vec::new();
+        let mut linked = Vec::new();
+        for face in faces {
+            let positions = [0, 1, 2].map(|k| connected_mesh.nodes[(3 * face + k) as usize].position);
+            // Faces along the edge are removed by the collapse
+            if positions.contains(&ends[0]) && positions.contains(&ends[1]) {
+                collapsed_index.push(None);
+                continue;
+            }
+            collapsed_index.push(Some(collapsed.len()));
+            collapsed.push(positions.map(|p| if ends.contains(&p) { *target } else { connected_mesh.positions[p as usize] }));
+            for p in positions {
+                if !ends.contains(&p) && !linked.contains(&p) {
+                    linked.push(p);
+                }
+            }
+        }
+
+        let target_distance = self.distance_to_original(target, limit);
+        let mut error = target_distance;
+        if error > limit {
+            return error;
+        }
+        for (face, index) in faces.iter().zip(collapsed_index) {
+            for sample in &self.samples[*face as usize] {
+                error = error.max(distance_to_faces(sample, &collapsed, index, error));
+                if error > limit {
+                    return error;
+                }
+            }
+        }
+        // Other edges are left as they were when measured
+        for p in linked {
+            error = self.edge_distance_to_original([target, &connected_mesh.positions[p as usize]], [target_distance, self.distances[p as usize]], error, limit);
+            if error > limit {
+                return error;
+            }
+        }
+        for [a, b, c] in &collapsed {
+            error = error.max(self.distance_to_original(&((a + b + c) / 3.0), limit));
+        }
+        error
+    }
+
+    // Attaches the samples of `faces`, as they were before the collapse that produced the node at `node_index`, to
+    // the closest face left around it
+    fn attach(&mut self, connected_mesh: &ConnectedMesh, faces: &[u32], node_index: u32) {
+        self.collapses += 1;
+        let position = connected_mesh.nodes[node_index as usize].position;
+        self.distances[position as usize] = self.distance_to_original(&connected_mesh.positions[position as usize], f64::INFINITY);
+        for face in faces {
+            for k in 0..3 {
+                self.changed_at[connected_mesh.nodes[(3 * face + k) as usize].position as usize] = self.collapses;
+            }
+        }
+
+        let mut around = Vec::new();
+        loop_siblings!(node_index, connected_mesh.nodes, sibling, {
+            around.push(sibling / 3);
+        });
+        let corners: Vec<[DVec3; 3]> = around.iter()
+            .map(|face| [0, 1, 2].map(|k| connected_mesh.positions[connected_mesh.nodes[(3 * face + k) as usize].position as usize]))
+            .collect();
+
+        for face in faces {
+            for sample in std::mem::take(&mut self.samples[*face as usize]) {
+                if let Some((_, closest)) = closest_face(&sample, &corners) {
+                    self.samples[around[closest] as usize].push(sample);
+                }
+            }
+        }
+    }
+
+    // Distance from the point to the original surface, infinite beyond `limit`
+    fn distance_to_original(&self, point: &DVec3, limit: f64) -> f64 {
+        self.original.closest_point(point, limit).map_or(f64::INFINITY, |closest| closest.distance)
+    }
+
+    // Largest distance from the edge to the original surface, given the distances of its ends. Sampled at the spacing
+    // of the original edges and refined where it peaks, when the peak could go beyond `limit` (beyond the largest
+    // distance so far without a limit). Measuring stops beyond `limit`.
+    fn edge_distance_to_original(&self, [a, b]: [&DVec3; 2], ends: [f64; 2], known: f64, limit: f64) -> f64 {
+        let point = |t: f64| a + (b - a) * t;
+        let n = if self.spacing > 0.0 { ((b - a).magnitude() / self.spacing).ceil().max(1.0) as usize } else { 1 };
+        let mut distances = Vec::with_capacity(n + 1);
+        distances.push(ends[0]);
+        for i in 1..n {
+            let distance = self.distance_to_original(&point(i as f64 / n as f64), limit);
+            if distance > limit {
+                return distance;
+            }
+            distances.push(distance);
+        }
+        distances.push(ends[1]);
+        let mut error = known;
+        for i in 0..=n {
+            error = error.max(distances[i]);
+            let refined_above = if limit < f64::INFINITY { limit } else { error };
+            // Distances peaking between samples are expected to rise about as much as they change from one sample to the next
+            let left = if i > 0 { distances[i] - distances[i - 1] } else { 0.0 };
+            let right = if i < n { distances[i] - distances[i + 1] } else { 0.0 };
+            if left < 0.0 || right < 0.0 || distances[i] + left.max(right) <= refined_above {
+                continue;
+            }
+            let (mut low, mut high) = (i.saturating_sub(1) as f64 / n as f64, (i + 1).min(n) as f64 / n as f64);
+            let mut peak = (i as f64 / n as f64, distances[i]);
+            for _ in 0..PEAK_REFINEMENTS {
+                let (t_low, t_high) = ((low + peak.0) / 2.0, (peak.0 + high) / 2.0);
+                let (d_low, d_high) = (self.distance_to_original(&point(t_low), limit), self.distance_to_original(&point(t_high), limit));
+                if d_low > limit || d_high > limit {
+                    return d_low.max(d_high);
+                }
+                if d_low > peak.1 && d_low >= d_high {
+                    high = peak.0;
+                    peak = (t_low, d_low);
+                } else if d_high > peak.1 {
+                    low = peak.0;
+                    peak = (t_high, d_high);
+                } else {
+                    low = t_low;
+                    high = t_high;
+                }
+            }
+            error = error.max(peak.1);
+        }
+        error
+    }
+}
+
+// Distance from the point to the closest of the faces, starting with the face at `first` and stopping within `within`.
+// Infinite without faces.
+fn distance_to_faces(point: &DVec3, faces: &[[DVec3; 3]], first: Option<usize>, within: f64) -> f64 {
+    let mut closest = f64::INFINITY;
+    for [a, b, c] in first.map(|i| &faces[i]).into_iter().chain(faces) {
+        closest = closest.min((triangle::closest_point(point, a, b, c).0 - point).magnitude());
+        if closest <= within {
+            break;
+        }
+    }
+    closest
+}
+
+// Distance from the point to the closest of the faces, and the index of that face. None without faces.
+fn closest_face(point: &DVec3, faces: &[[DVec3; 3]]) -> Option<(f64, usize)> {
+    faces.iter()
+        .map(|[a, b, c]| (triangle::closest_point(point, a, b, c).0 - point).magnitude_squared())
+        .enumerate()
+        .fold(None, |closest: Option<(f64, usize)>, (i, distance)| match closest {
+            Some((closest_distance, _)) if closest_distance <= distance => closest,
+            _ => Some((distance, i)),
+        })
+        .map(|(distance, i)| (distance.sqrt(), i))
+}
+
@@ -0,0 +1,123 @@
+/// When to take a level of detail while decimating
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LodTarget {
+    TriangleCount(u32),
+    /// Ratio of the triangle count of the original mesh
+    Ratio(f32),
+    /// Maximum geometric error, in world units
+    Error(f64),
+}
+
+/// A level of detail, as produced by `ConnectedMesh::decimate_lods`
+pub struct Lod {
+    pub mesh: SharedMesh,
+    pub triangle_count: u32,
+    /// Largest distance to the original surface of the collapses that produced this level, in world units
+    pub geometric_error: f64,
+}
+
+impl Lod {
+    /// Geometric error projected on screen, in pixels, for an object at `distance` from a perspective camera
+    /// with a vertical field of view of `fov_y` radians and a viewport of `viewport_height` pixels
+    pub fn screen_space_error(&self, distance: f64, fov_y: f64, viewport_height: f64) -> f64 {
+        if distance <= 0.0 {
+            return f64::MAX;
+        }
+        self.geometric_error * viewport_height / (2.0 * distance * (fov_y / 2.0).tan())
+    }
+}
+
+/// Index of the coarsest level whose screen space error stays within `max_pixel_error`.
+/// Levels must be ordered from the finest to the coarsest, as returned by `ConnectedMesh::decimate_lods`.
+pub fn select_lod(lods: &[Lod], distance: f64, fov_y: f64, viewport_height: f64, max_pixel_error: f64) -> usize {
+    lods.iter()
+        .rposition(|lod| lod.screen_space_error(distance, fov_y, viewport_height) <= max_pixel_error)
+        .unwrap_or(0)
+}
+
+impl ConnectedMesh {
+    /// Decimates the mesh in a single pass, taking a snapshot each time a target is reached.
+    /// Targets must be ordered from the finest to the coarsest. The first level is the undecimated mesh.
+    /// Errors are distances to the original surface, whatever `options` weigh in the choice of collapses.
+    pub fn decimate_lods(&mut self, targets: &[LodTarget], options: &DecimationOptions) -> Vec<Lod> {
+        let original_count = self.face_count;
+        let mut lods = vec![Lod { mesh: SharedMesh::from(&*self), triangle_count: original_count, geometric_error: 0.0 }];
+        let mut next_target = 0;
+        let mut max_error = 0.0f64;
+
+        let is_reached = |target: &LodTarget, connected_mesh: &ConnectedMesh, next_error: f64| match *target {
+            LodTarget::TriangleCount(count) => connected_mesh.face_count <= count,
+            LodTarget::Ratio(ratio) => connected_mesh.face_count <= (ratio * original_count as f32) as u32,
+            LodTarget::Error(error) => next_error > error,
+        };
+
+        self.decimate_while(options, Some(f64::INFINITY), |connected_mesh, next_error| {
+            while next_target < targets.len() && is_reached(&targets[next_target], connected_mesh, next_error) {
+                lods.push(Lod { mesh: SharedMesh::from(connected_mesh), triangle_count: connected_mesh.face_count, geometric_error: max_error });
+                next_target += 1;
+            }
+            max_error = max_error.max(next_error);
+            next_target < targets.len()
+        });
+
+        // Targets that could not be reached (the mesh can't be decimated further)
+        for _ in next_target..targets.len() {
+            lods.push(Lod { mesh: SharedMesh::from(&*self), triangle_count: self.face_count, geometric_error: max_error });
+        }
+
+        lods
+    }
+}
+
+#[cfg(test)]
+mod lod_tests {
+    use super::*;
+    use super::test_meshes::*;
+    use super::metric::hausdorff_distance;
+
+    #[test]
+    fn lod_chain() {
+        let mesh = sphere(DVec3::zeros(), 1.0, 4);
+        let mut connected_mesh = ConnectedMesh::from(&mesh);
+        let lods = connected_mesh.decimate_lods(&[
+            LodTarget::Error(0.005),
+            LodTarget::Ratio(0.5),
+            LodTarget::TriangleCount(200),
+            LodTarget::Error(0.1),
+        ], &DecimationOptions::default());
+
+        assert_eq!(lods.len(), 5);
+        assert_eq!(lods[0].triangle_count as usize, mesh.triangles.len());
+        assert!(lods[2].triangle_count as usize <= mesh.triangles.len() / 2);
+        assert!(lods[3].triangle_count <= 200);
+        for pair in lods.windows(2) {
+            assert!(pair[1].triangle_count <= pair[0].triangle_count);
+            assert!(pair[1].geometric_error >= pair[0].geometric_error);
+        }
+        assert!(lods[1].geometric_error <= 0.005);
+        assert!(lods[4].geometric_error <= 0.1);
+
+        for lod in &lods {
+            assert_eq!(lod.mesh.triangles.len(), lod.triangle_count as usize);
+            let measured = hausdorff_distance(&lod.mesh, &mesh, 1000);
+            assert!(measured <= lod.geometric_error + 1e-9, "{} {}", measured, lod.geometric_error);
+        }
+    }
+
+    #[test]
+    fn screen_space_selection() {
+        let mesh = sphere(DVec3::zeros(), 1.0, 3);
+        let lods = ConnectedMesh::from(&mesh).decimate_lods(&[LodTarget::Ratio(0.5), LodTarget::Ratio(0.25), LodTarget::Ratio(0.1)], &DecimationOptions::default());
+        let fov = std::f64::consts::FRAC_PI_3;
+
+        assert_eq!(select_lod(&lods, 0.0, fov, 1080.0, 1.0), 0);
+        let mut previous = 0;
+        for distance in [1.0, 10.0, 100.0, 1000.0, 10000.0] {
+            let level = select_lod(&lods, distance, fov, 1080.0, 1.0);
+            assert!(level >= previous);
+            assert!(lods[level].screen_space_error(distance, fov, 1080.0) <= 1.0 || level == 0);
+            previous = level;
+        }
+        assert_eq!(previous, lods.len() - 1);
+    }
+}
@@ -0,0 +1,246 @@
+// Quadrics over positions and vertex attributes (Garland & Heckbert, 1998). Each face spans a plane in the space
+// of positions and weighted attributes, and the error of a vertex is its squared distance to the planes of its faces.
+// Attributes are then minimized out, so that collapse targets are found with the same 3D quadrics as for geometry alone.
+
+#[derive(Debug, Clone)]
+struct AttributeQuadric {
+    dimension: usize,
+    // Upper triangle of A, row by row
+    a: Vec<f64>,
+    b: Vec<f64>,
+    c: f64,
+}
+
+impl AttributeQuadric {
+    fn zeroes(dimension: usize) -> Self {
+        Self {
+            dimension,
+            a: vec![0.0; dimension * (dimension + 1) / 2],
+            b: vec![0.0; dimension],
+            c: 0.0,
+        }
+    }
+
+    // Quadric of the plane going through three points, in the space of positions and attributes:
+    // A = I - e1.e1t - e2.e2t, b = (p.e1)e1 + (p.e2)e2 - p, c = p.p - (p.e1)^2 - (p.e2)^2
+    fn from_face(points: [&[f64]; 3]) -> Self {
+        let dimension = points[0].len();
+        let mut quadric = Self::zeroes(dimension);
+
+        let sub = |x: &[f64], y: &[f64]| -> Vec<f64> { x.iter().zip(y).map(|(x, y)| x - y).collect() };
+        let dot = |x: &[f64], y: &[f64]| -> f64 { x.iter().zip(y).map(|(x, y)| x * y).sum() };
+
+        let mut e1 = sub(points[1], points[0]);
+        let length = dot(&e1, &e1).sqrt();
+        if length < 1e-12 {
+            return quadric;
+        }
+        e1.iter_mut().for_each(|x| *x /= length);
+
+        let mut e2 = sub(points[2], points[0]);
+        let projection = dot(&e1, &e2);
+        e2.iter_mut().zip(&e1).for_each(|(x, e)| *x -= projection * e);
+        let length = dot(&e2, &e2).sqrt();
+        if length < 1e-12 {
+            return quadric;
+        }
+        e2.iter_mut().for_each(|x| *x /= length);
+
+        let p = points[0];
+        let (pe1, pe2) = (dot(p, &e1), dot(p, &e2));
+        for i in 0..dimension {
+            for j in i..dimension {
+                let identity = if i == j { 1.0 } else { 0.0 };
+                let index = quadric.index(i, j);
+                quadric.a[index] = identity - e1[i] * e1[j] - e2[i] * e2[j];
+            }
+            quadric.b[i] = pe1 * e1[i] + pe2 * e2[i] - p[i];
+        }
+        quadric.c = dot(p, p) - pe1 * pe1 - pe2 * pe2;
+        quadric
+    }
+
+    // Adds the quadric of a plane of positions, which does not constrain attributes
+    fn add_plane(&mut self, normal: &DVec3, dot: f64, weight: f64) {
+        for i in 0..3 {
+            for j in i..3 {
+                let index = self.index(i, j);
+                self.a[index] += weight * normal[i] * normal[j];
+            }
+            self.b[i] += weight * normal[i] * dot;
+        }
+        self.c += weight * dot * dot;
+    }
+
+    fn scale(&mut self, weight: f64) {
+        self.a.iter_mut().chain(self.b.iter_mut()).for_each(|x| *x *= weight);
+        self.c *= weight;
+    }
+
+    fn index(&self, i: usize, j: usize) -> usize {
+        let (i, j) = if i <= j { (i, j) } else { (j, i) };
+        i * (2 * self.dimension - i + 1) / 2 + j - i
+    }
+
+    fn get(&self, i: usize, j: usize) -> f64 {
+        self.a[self.index(i, j)]
+    }
+
+    // Minimizes attributes out (Schur complement), leaving a quadric over positions only:
+    // A' = App - Aps.Ass^-1.Asp, b' = bp - Aps.Ass^-1.bs, c' = c - bst.Ass^-1.bs
+    fn to_symmetric_matrix(&self) -> SymmetricMatrix {
+        let m = self.dimension - 3;
+
+        // Solves Ass.x = [Asp | bs] by Gauss-Jordan elimination. Directions in which attributes are
+        // unconstrained have null pivots and are ignored.
+        let mut rows: Vec<Vec<f64>> = (0..m).map(|i| {
+            let mut row: Vec<f64> = (0..m).map(|j| self.get(3 + i, 3 + j)).collect();
+            row.extend((0..3).map(|j| self.get(3 + i, j)));
+            row.push(self.b[3 + i]);
+            row
+        }).collect();
+        for k in 0..m {
+            let pivot = rows[k][k];
+            if pivot.abs() < 1e-12 {
+                rows[k].iter_mut().for_each(|x| *x = 0.0);
+                continue;
+            }
+            rows[k].iter_mut().for_each(|x| *x /= pivot);
+            let pivot_row = rows[k].clone();
+            for (i, row) in rows.iter_mut().enumerate() {
+                let factor = row[k];
+                if i != k && factor != 0.0 {
+                    row.iter_mut().zip(&pivot_row).for_each(|(x, p)| *x -= factor * p);
+                }
+            }
+        }
+        let x = |k: usize, j: usize| rows[k][m + j];
+
+        let a = |i: usize, j: usize| self.get(i, j) - (0..m).map(|k| self.get(i, 3 + k) * x(k, j)).sum::<f64>();
+        let b = |i: usize| self.b[i] - (0..m).map(|k| self.get(i, 3 + k) * x(k, 3)).sum::<f64>();
+        let c = self.c - (0..m).map(|k| self.b[3 + k] * x(k, 3)).sum::<f64>();
+
+        SymmetricMatrix {
+            m: [a(0, 0), a(0, 1), a(0, 2), b(0),
+                a(1, 1), a(1, 2), b(1),
+                a(2, 2), b(2),
+                c]
+        }
+    }
+}
+
+impl AddAssign<&AttributeQuadric> for AttributeQuadric {
+    fn add_assign(&mut self, other: &AttributeQuadric) {
+        self.a.iter_mut().zip(&other.a).for_each(|(x, y)| *x += y);
+        self.b.iter_mut().zip(&other.b).for_each(|(x, y)| *x += y);
+        self.c += other.c;
+    }
+}
+
+// Quadrics of each position, along with what is needed to compute them
+struct Quadrics {
+    geometric: Vec<SymmetricMatrix>,
+    // Quadrics including attributes, only when some are weighted
+    attributes: Vec<AttributeQuadric>,
+    attribute_weights: [f64; 3],
+    node_groups: Vec<u32>,
+    // Positions that can't be moved nor removed
+    locked: Vec<bool>,
+    // Factor of the quadric of each position
+    importance: Vec<f64>,
+}
+
+impl Quadrics {
+    fn new(connected_mesh: &ConnectedMesh, options: &DecimationOptions) -> Self {
+        let attribute_weights = connected_mesh.attribute_weights(options);
+        let dimension = 3 + [3, 3, 2].iter().zip(&attribute_weights).filter(|(_, w)| **w != 0.0).map(|(d, _)| d).sum::<usize>();
+        let position_count = connected_mesh.positions.len();
+
+        let mut quadrics = Quadrics {
+            geometric: vec![SymmetricMatrix::default_uninitalized(); position_count],
+            attributes: if dimension > 3 { vec![AttributeQuadric::zeroes(dimension); position_count] } else { Vec::new() },
+            attribute_weights,
+            node_groups: connected_mesh.node_groups(),
+            locked: (0..position_count).map(|i| options.locked_vertices.get(i).copied().unwrap_or(false)).collect(),
+            importance: (0..position_count).map(|i| options.vertex_weights.get(i).copied().unwrap_or(1.0)).collect(),
+        };
+
+        for (i, node) in connected_mesh.nodes.iter().enumerate() {
+            if !node.is_removed && connected_mesh.edge_constraint(&quadrics, options, i as u32) == EdgeConstraint::Locked {
+                quadrics.locked[node.position as usize] = true;
+                quadrics.locked[connected_mesh.nodes[node.relative as usize].position as usize] = true;
+            }
+        }
+        quadrics
+    }
+}
+
+impl ConnectedMesh {
+    // Weights by which normals, colors and texture coordinates are multiplied in quadrics, or zero when not used
+    fn attribute_weights(&self, options: &DecimationOptions) -> [f64; 3] {
+        let scale = Box3::from_points(self.positions.iter()).diagonal();
+        [
+            if self.normals.is_some() { options.normal_weight * scale } else { 0.0 },
+            if self.colors.is_some() { options.color_weight * scale } else { 0.0 },
+            if self.uvs.is_some() { options.uv_weight * scale } else { 0.0 },
+        ]
+    }
+
+    // Position of the node, followed by its weighted attributes
+    fn attribute_vector(&self, node: &Node, weights: &[f64; 3]) -> Vec<f64> {
+        let position = &self.positions[node.position as usize];
+        let mut vector = vec![position.x, position.y, position.z];
+        if weights[0] != 0.0 {
+            vector.extend(self.normals.as_ref().unwrap()[node.normal as usize].iter().map(|x| x * weights[0]));
+        }
+        if weights[1] != 0.0 {
+            vector.extend(self.colors.as_ref().unwrap()[node.color as usize].iter().map(|x| x * weights[1]));
+        }
+        if weights[2] != 0.0 {
+            vector.extend(self.uvs.as_ref().unwrap()[node.uv as usize].iter().map(|x| x * weights[2]));
+        }
+        vector
+    }
+
+    fn face_area(&self, node_index: u32) -> f64 {
+        let node_a = self.nodes[node_index as usize];
+        let node_b = self.nodes[node_a.relative as usize];
+        let node_c = self.nodes[node_b.relative as usize];
+        let [a, b, c] = [node_a, node_b, node_c].map(|node| self.positions[node.position as usize]);
+        (b - a).cross(&(c - a)).magnitude() / 2.0
+    }
+
+    // How the edge going from the node to its relative is preserved
+    fn edge_constraint(&self, quadrics: &Quadrics, options: &DecimationOptions, node_index: u32) -> EdgeConstraint {
+        let half_edge = HalfEdgeId(node_index);
+        match self.opposite_unchecked(half_edge) {
+            None => options.boundaries,
+            Some(opposite) if quadrics.node_groups[node_index as usize] != quadrics.node_groups[opposite.0 as usize] => options.group_borders,
+            Some(opposite) if options.features != EdgeConstraint::Free => {
+                let cos = self.face_normal_unchecked(self.face_unchecked(half_edge)).dot(&self.face_normal_unchecked(self.face_unchecked(opposite)));
+                if cos.clamp(-1.0, 1.0).acos() > options.feature_angle { options.features } else { EdgeConstraint::Free }
+            }
+            Some(_) => EdgeConstraint::Free,
+        }
+    }
+
+    // Planes going through constrained edges around the node, perpendicular to their faces, as (normal, dot, weight)
+    fn constraint_planes(&self, quadrics: &Quadrics, options: &DecimationOptions, node_index: u32) -> Vec<(DVec3, f64, f64)> {
+        let mut planes = Vec::new();
+        loop_siblings!(node_index, self.nodes, sibling, {
+            let previous = self.nodes[self.nodes[sibling as usize].relative as usize].relative;
+            for half_edge in [sibling, previous] {
+                if let EdgeConstraint::Weighted(weight) = self.edge_constraint(quadrics, options, half_edge) {
+                    let from = self.positions[self.nodes[half_edge as usize].position as usize];
+                    let to = self.positions[self.nodes[self.nodes[half_edge as usize].relative as usize].position as usize];
+                    let normal = (to - from).cross(&self.face_normal_unchecked(FaceId(half_edge / 3)));
+                    if normal.magnitude_squared() > 0.0 {
+                        let normal = normal.normalize();
+                        planes.push((normal, -normal.dot(&from), weight));
+                    }
+                }
+            }
+        });
+        planes
+    }
+}
@@ -1,4 +1,18 @@
+/// A contiguous range of the index buffer (3 indices per triangle), used as a submesh / material slot
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Group {
-  first_index: u32,
-  index_count: u32,
+  pub first_index: u32,
+  pub index_count: u32,
+}
+
+impl Group {
+  pub fn new(first_index: u32, index_count: u32) -> Self {
+    Group { first_index, index_count }
+  }
+
+  /// Range of triangles covered by this group
+  pub fn triangles(&self) -> std::ops::Range<usize> {
+    (self.first_index / 3) as usize..((self.first_index + self.index_count) / 3) as usize
+  }
 }
@@ -0,0 +1,381 @@
+// Convex hulls (quickhull) and approximate convex decomposition, for collision proxies.
+// The decomposition recursively cuts the most concave part with the axis aligned plane that minimizes
+// the volume of the hulls of both halves, until parts are convex enough or the hull budget is spent.
+
+use nalgebra_glm as glm;
+use glm::{DVec3, U32Vec3};
+use hashbrown::HashMap;
+use super::SharedMesh;
+use super::super::base::{Box3, Plane};
+
+#[derive(Debug, Clone)]
+pub struct ConvexDecompositionOptions {
+    /// Maximum number of hulls
+    pub max_hulls: u32,
+    /// Maximum number of vertices of each hull, at least 4
+    pub max_vertices_per_hull: u32,
+    /// Parts are not cut further once the volume between them and their hull is below this ratio of the mesh volume
+    pub max_concavity: f64,
+    /// Number of cutting planes tried along each axis
+    pub plane_samples: u32,
+}
+
+impl Default for ConvexDecompositionOptions {
+    fn default() -> Self {
+        ConvexDecompositionOptions {
+            max_hulls: 16,
+            max_vertices_per_hull: 64,
+            max_concavity: 0.01,
+            plane_samples: 16,
+        }
+    }
+}
+
+impl SharedMesh {
+    /// Convex hull of the vertices of the mesh
+    pub fn convex_hull(&self) -> SharedMesh {
+        convex_hull(&self.positions, None)
+    }
+
+    /// Approximates a closed mesh with a set of convex hulls. Hulls only have positions.
+    pub fn convex_decomposition(&self, options: &ConvexDecompositionOptions) -> Vec<SharedMesh> {
+        let total_volume = self.volume().abs();
+        let max_concavity = options.max_concavity * total_volume;
+
+        // Parts with the volume between them and their hull, None once they can't be cut
+        let whole = self.submesh(&vec![true; self.triangles.len()]);
+        let mut parts = vec![(Some(concavity(&whole)), whole)];
+        while parts.len() < options.max_hulls as usize {
+            let worst = parts.iter().enumerate()
+                .filter_map(|(i, (concavity, _))| concavity.map(|c| (i, c)))
+                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+            let (index, worst_concavity) = match worst {
+                Some(worst) => worst,
+                None => break,
+            };
+            if worst_concavity <= max_concavity {
+                break;
+            }
+            match best_cut(&parts[index].1, options.plane_samples) {
+                Some((above, below)) => {
+                    parts.swap_remove(index);
+                    parts.push((Some(concavity(&above)), above));
+                    parts.push((Some(concavity(&below)), below));
+                }
+                None => parts[index].0 = None,
+            }
+        }
+
+        parts.iter()
+            .map(|(_, part)| convex_hull(&part.positions, Some(options.max_vertices_per_hull)))
+            .filter(|hull| !hull.triangles.is_empty())
+            .collect()
+    }
+}
+
+// Volume between the part and its hull
+fn concavity(part: &SharedMesh) -> f64 {
+    (part.convex_hull().volume() - part.volume()).max(0.0)
+}
+
+// Cut with the axis aligned plane minimizing the volume of the hulls of both halves
+fn best_cut(part: &SharedMesh, samples: u32) -> Option<(SharedMesh, SharedMesh)> {
+    let bounds = part.bounding_box();
+    let mut best: Option<(f64, SharedMesh, SharedMesh)> = None;
+    for axis in 0..3 {
+        for i in 1..samples.max(2) {
+            let mut point = bounds.min;
+            point[axis] += bounds.size()[axis] * i as f64 / samples.max(2) as f64;
+            let plane = Plane::new(&point, &DVec3::from_fn(|k, _| if k == axis { 1.0 } else { 0.0 }));
+            let (above, below) = match part.split_by_plane(&plane, true) {
+                Ok(halves) => halves,
+                Err(_) => continue,
+            };
+            if above.triangles.is_empty() || below.triangles.is_empty() {
+                continue;
+            }
+            let cost = above.convex_hull().volume() + below.convex_hull().volume();
+            if best.as_ref().map_or(true, |(best_cost, _, _)| cost < *best_cost) {
+                best = Some((cost, above, below));
+            }
+        }
+    }
+    best.map(|(_, above, below)| (above, below))
+}
+
+struct HullFace {
+    vertices: [u32; 3],
+    normal: DVec3,
+    offset: f64,
+    // Points above the face, not yet in the hull, and the farthest of them with its distance
+    outside: Vec<u32>,
+    farthest: Option<(u32, f64)>,
+    is_alive: bool,
+}
+
+impl HullFace {
+    fn new(points: &[DVec3], vertices: [u32; 3]) -> Self {
+        let [a, b, c] = vertices.map(|v| points[v as usize]);
+        let normal = (b - a).cross(&(c - a)).normalize();
+        HullFace { vertices, normal, offset: normal.dot(&a), outside: Vec::new(), farthest: None, is_alive: true }
+    }
+
+    fn distance(&self, point: &DVec3) -> f64 {
+        self.normal.dot(point) - self.offset
+    }
+}
+
+/// Convex hull of a set of points, with outward facing triangles.
+/// With a vertex limit, the farthest points are added first, so that the hull is the best approximation
+/// found within the limit (it is then contained in the exact hull). Limits below 4 vertices are raised to 4,
+/// the initial tetrahedron.
+/// Sets with less than 4 points or with all points in a plane give an empty mesh.
+pub fn convex_hull(points: &[DVec3], max_vertices: Option<u32>) -> SharedMesh {
+    let mut hull = SharedMesh { groups: Vec::new(), triangles: Vec::new(), positions: Vec::new(), normals: None, colors: None, uvs: None };
+    let epsilon = Box3::from_points(points.iter()).diagonal() * 1e-10;
+    let simplex = match initial_simplex(points, epsilon) {
+        Some(simplex) => simplex,
+        None => return hull,
+    };
+
+    // Faces of the initial tetrahedron, facing away from its centroid
+    let centroid = simplex.iter().map(|v| points[*v as usize]).sum::<DVec3>() / 4.0;
+    let mut faces = Vec::new();
+    for [a, b, c] in [[0, 1, 2], [0, 1, 3], [0, 2, 3], [1, 2, 3]] {
+        let mut face = HullFace::new(points, [simplex[a], simplex[b], simplex[c]]);
+        if face.distance(&centroid) > 0.0 {
+            face = HullFace::new(points, [simplex[a], simplex[c], simplex[b]]);
+        }
+        faces.push(face);
+    }
+    for p in 0..points.len() as u32 {
+        if !simplex.contains(&p) {
+            assign(&mut faces, &[0, 1, 2, 3], p, points, epsilon);
+        }
+    }
+
+    // Directed edge to the face walking it
+    let mut edge_faces = HashMap::<(u32, u32), usize>::new();
+    for (f, face) in faces.iter().enumerate() {
+        for k in 0..3 {
+            edge_faces.insert((face.vertices[k], face.vertices[(k + 1) % 3]), f);
+        }
+    }
+
+    let max_vertices = max_vertices.map(|max| max.max(4));
+    let mut vertex_count = 4;
+    while max_vertices.map_or(true, |max| vertex_count < max) {
+        // Farthest point over all faces
+        let farthest = faces.iter().enumerate()
+            .filter(|(_, face)| face.is_alive)
+            .filter_map(|(f, face)| face.farthest.map(|(p, distance)| (f, p, distance)))
+            .max_by(|a, b| a.2.partial_cmp(&b.2).unwrap());
+        let (start, apex) = match farthest {
+            Some((f, p, _)) => (f, p),
+            None => break,
+        };
+        let apex_position = points[apex as usize];
+
+        // Faces seen from the apex, connected to the first one
+        let mut visible = vec![start];
+        let mut is_visible = HashMap::<usize, bool>::new();
+        is_visible.insert(start, true);
+        let mut i = 0;
+        while i < visible.len() {
+            let face = &faces[visible[i]];
+            for k in 0..3 {
+                let neighbor = edge_faces[&(face.vertices[(k + 1) % 3], face.vertices[k])];
+                if !is_visible.contains_key(&neighbor) {
+                    let is_seen = faces[neighbor].distance(&apex_position) > epsilon;
+                    is_visible.insert(neighbor, is_seen);
+                    if is_seen {
+                        visible.push(neighbor);
+                    }
+                }
+            }
+            i += 1;
+        }
+
+        // Horizon edges are walked by a visible face and by a hidden one
+        let mut horizon = Vec::new();
+        for f in &visible {
+            let vertices = faces[*f].vertices;
+            for k in 0..3 {
+                let (a, b) = (vertices[k], vertices[(k + 1) % 3]);
+                if !is_visible[&edge_faces[&(b, a)]] {
+                    horizon.push((a, b));
+                }
+            }
+        }
+
+        let mut orphans = Vec::new();
+        for f in &visible {
+            faces[*f].is_alive = false;
+            orphans.append(&mut faces[*f].outside);
+            let vertices = faces[*f].vertices;
+            for k in 0..3 {
+                edge_faces.remove(&(vertices[k], vertices[(k + 1) % 3]));
+            }
+        }
+        let mut new_faces = Vec::with_capacity(horizon.len());
+        for (a, b) in horizon {
+            let f = faces.len();
+            faces.push(HullFace::new(points, [a, b, apex]));
+            new_faces.push(f);
+            edge_faces.insert((a, b), f);
+            edge_faces.insert((b, apex), f);
+            edge_faces.insert((apex, a), f);
+        }
+        for p in orphans {
+            if p != apex {
+                assign(&mut faces, &new_faces, p, points, epsilon);
+            }
+        }
+        vertex_count += 1;
+    }
+
+    let mut remap = vec![u32::MAX; points.len()];
+    for face in faces.iter().filter(|face| face.is_alive) {
+        let mut triangle = U32Vec3::default();
+        for k in 0..3 {
+            let v = face.vertices[k] as usize;
+            if remap[v] == u32::MAX {
+                remap[v] = hull.positions.len() as u32;
+                hull.positions.push(points[v]);
+            }
+            triangle[k] = remap[v];
+        }
+        hull.triangles.push(triangle);
+    }
+    hull
+}
+
+// Gives the point to the first face it is above of, if any
+fn assign(faces: &mut [HullFace], candidates: &[usize], point: u32, points: &[DVec3], epsilon: f64) {
+    for f in candidates {
+        let face = &mut faces[*f];
+        let distance = face.distance(&points[point as usize]);
+        if distance > epsilon {
+            face.outside.push(point);
+            if face.farthest.map_or(true, |(_, farthest)| distance > farthest) {
+                face.farthest = Some((point, distance));
+            }
+            return;
+        }
+    }
+}
+
+// Extreme points spanning a tetrahedron of non zero volume
+fn initial_simplex(points: &[DVec3], epsilon: f64) -> Option<[u32; 4]> {
+    if points.len() < 4 {
+        return None;
+    }
+    let farthest = |score: &dyn Fn(&DVec3) -> f64| -> u32 {
+        (0..points.len()).max_by(|a, b| score(&points[*a]).partial_cmp(&score(&points[*b])).unwrap()).unwrap() as u32
+    };
+
+    // Most distant pair among the extremes along each axis
+    let mut extremes = Vec::new();
+    for axis in 0..3 {
+        extremes.push(farthest(&|p| p[axis]));
+        extremes.push(farthest(&|p| -p[axis]));
+    }
+    let mut pair = (extremes[0], extremes[1]);
+    let mut pair_distance = 0.0;
+    for a in &extremes {
+        for b in &extremes {
+            let distance = (points[*a as usize] - points[*b as usize]).magnitude();
+            if distance > pair_distance {
+                pair = (*a, *b);
+                pair_distance = distance;
+            }
+        }
+    }
+    if pair_distance <= epsilon {
+        return None;
+    }
+
+    let (a, b) = (points[pair.0 as usize], points[pair.1 as usize]);
+    let direction = (b - a).normalize();
+    let c = farthest(&|p| (p - a).cross(&direction).magnitude());
+    if (points[c as usize] - a).cross(&direction).magnitude() <= epsilon {
+        return None;
+    }
+
+    let normal = (b - a).cross(&(points[c as usize] - a)).normalize();
+    let d = farthest(&|p| normal.dot(&(p - a)).abs());
+    if normal.dot(&(points[d as usize] - a)).abs() <= epsilon {
+        return None;
+    }
+
+    Some([pair.0, pair.1, c, d])
+}
+
+#[cfg(test)]
+mod hull_tests {
+    use super::*;
+    use super::super::adjacency;
+    use super::super::test_meshes::*;
+
+    fn assert_convex_and_closed(hull: &SharedMesh, points: &[DVec3]) {
+        let welded = adjacency::weld_positions(&hull.positions);
+        let edges = adjacency::edge_faces(&hull.triangles, &welded);
+        assert!(edges.values().all(|faces| faces.len() == 2));
+        for t in &hull.triangles {
+            let [a, b, c] = [0, 1, 2].map(|k| hull.positions[t[k] as usize]);
+            let normal = (b - a).cross(&(c - a)).normalize();
+            assert!(points.iter().all(|p| normal.dot(&(p - a)) < 1e-9));
+        }
+    }
+
+    #[test]
+    fn cube_hull() {
+        let mut points = cube(DVec3::zeros(), 2.0).positions;
+        points.extend(grid(4, 1.0).positions.iter().map(|p| p - DVec3::new(0.5, 0.5, 0.)));
+        let hull = convex_hull(&points, None);
+        assert_convex_and_closed(&hull, &points);
+        assert_eq!(hull.positions.len(), 8);
+        assert_eq!(hull.triangles.len(), 12);
+        assert!((hull.volume() - 8.0).abs() < 1e-9);
+
+        // Degenerate sets
+        assert!(convex_hull(&grid(3, 1.0).positions, None).triangles.is_empty());
+        assert!(convex_hull(&points[..3], None).triangles.is_empty());
+    }
+
+    #[test]
+    fn sphere_hull_with_vertex_limit() {
+        let mesh = sphere(DVec3::zeros(), 1.0, 3);
+        let hull = mesh.convex_hull();
+        assert_convex_and_closed(&hull, &mesh.positions);
+        assert_eq!(hull.positions.len(), mesh.positions.len());
+        assert!((hull.volume() - mesh.volume()).abs() < 1e-9);
+
+        let limited = convex_hull(&mesh.positions, Some(20));
+        assert_eq!(limited.positions.len(), 20);
+        let welded = adjacency::weld_positions(&limited.positions);
+        assert!(adjacency::edge_faces(&limited.triangles, &welded).values().all(|faces| faces.len() == 2));
+        assert!(limited.volume() < hull.volume() && limited.volume() > hull.volume() * 0.6);
+        assert_eq!(convex_hull(&mesh.positions, Some(2)).positions.len(), 4);
+    }
+
+    #[test]
+    fn decompose_l_shape() {
+        // A 2 x 1 x 1 bar with a unit cube on one end
+        let mut bar = cube(DVec3::new(1., 0.5, 0.5), 2.0);
+        bar.positions.iter_mut().for_each(|p| { p.y = p.y.clamp(0.0, 1.0); p.z = p.z.clamp(0.0, 1.0); });
+        let l_shape = bar.union(&cube(DVec3::new(0.5, 1.5, 0.5), 1.0)).unwrap();
+        let volume = l_shape.volume();
+        assert!((volume - 3.0).abs() < 1e-9);
+
+        let options = ConvexDecompositionOptions { max_hulls: 4, max_vertices_per_hull: 16, ..Default::default() };
+        let hulls = l_shape.convex_decomposition(&options);
+        assert!(hulls.len() >= 2 && hulls.len() <= 4);
+        assert!(hulls.iter().all(|hull| hull.positions.len() <= 16));
+        let hulls_volume: f64 = hulls.iter().map(|hull| hull.volume()).sum();
+        assert!((hulls_volume - volume).abs() < volume * 0.05);
+
+        // The whole hull, for comparison
+        assert!(l_shape.convex_hull().volume() > volume * 1.1);
+    }
+}
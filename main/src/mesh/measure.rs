@@ -0,0 +1,155 @@
+use nalgebra_glm as glm;
+use glm::{DVec3, DMat3};
+use super::SharedMesh;
+use super::super::base::{Box3, OrientedBox3};
+
+/// Mass properties of a closed mesh with uniform unit density
+#[derive(Debug, Copy, Clone)]
+pub struct MassProperties {
+    /// Enclosed volume (signed: negative if the mesh is inside-out)
+    pub volume: f64,
+    pub center_of_mass: DVec3,
+    /// Inertia tensor about the center of mass. Multiply by density to get physical units.
+    pub inertia: DMat3,
+}
+
+impl SharedMesh {
+    pub fn surface_area(&self) -> f64 {
+        (0..self.triangles.len()).map(|t| self.triangle_area(t)).sum()
+    }
+
+    /// Signed enclosed volume. Only meaningful for closed, consistently oriented meshes.
+    pub fn volume(&self) -> f64 {
+        self.triangles.iter()
+            .map(|t| {
+                let (a, b, c) = self.triangle_positions(t);
+                a.dot(&b.cross(c))
+            })
+            .sum::<f64>() / 6.0
+    }
+
+    /// Area weighted center of the surface
+    pub fn surface_centroid(&self) -> DVec3 {
+        let mut area = 0.0;
+        let mut centroid = DVec3::zeros();
+        for (i, t) in self.triangles.iter().enumerate() {
+            let (a, b, c) = self.triangle_positions(t);
+            let triangle_area = self.triangle_area(i);
+            centroid += (a + b + c) * (triangle_area / 3.0);
+            area += triangle_area;
+        }
+        if area > 0.0 { centroid / area } else { centroid }
+    }
+
+    /// Center of mass of the enclosed volume. Falls back to the surface centroid for open or flat meshes.
+    pub fn centroid(&self) -> DVec3 {
+        let properties = self.mass_properties();
+        if properties.volume.abs() > f64::EPSILON {
+            properties.center_of_mass
+        } else {
+            self.surface_centroid()
+        }
+    }
+
+    /// Inertia tensor about the center of mass, for a unit density
+    pub fn inertia_tensor(&self) -> DMat3 {
+        self.mass_properties().inertia
+    }
+
+    /// Volume, center of mass and inertia tensor, computed by summing signed tetrahedra
+    /// formed by each triangle and the origin ("How to find the inertia tensor (or other mass properties)
+    /// of a 3D solid body represented by a triangle mesh", Blow & Binstock)
+    pub fn mass_properties(&self) -> MassProperties {
+        // Covariance of the canonical tetrahedron (0, x, y, z)
+        let canonical = DMat3::new(
+            2.0, 1.0, 1.0,
+            1.0, 2.0, 1.0,
+            1.0, 1.0, 2.0) / 120.0;
+
+        let mut volume = 0.0;
+        let mut weighted_center = DVec3::zeros();
+        let mut covariance = DMat3::zeros();
+
+        for t in &self.triangles {
+            let (a, b, c) = self.triangle_positions(t);
+            let basis = DMat3::from_columns(&[*a, *b, *c]);
+            let det = basis.determinant();
+            volume += det / 6.0;
+            weighted_center += (a + b + c) * (det / 24.0);
+            covariance += basis * canonical * basis.transpose() * det;
+        }
+
+        if volume.abs() <= f64::EPSILON {
+            return MassProperties { volume, center_of_mass: DVec3::zeros(), inertia: DMat3::zeros() };
+        }
+
+        let center_of_mass = weighted_center / volume;
+
+        // Parallel axis theorem, moving the covariance to the center of mass
+        covariance -= center_of_mass * center_of_mass.transpose() * volume;
+
+        let inertia = DMat3::identity() * covariance.trace() - covariance;
+
+        MassProperties { volume, center_of_mass, inertia }
+    }
+
+    pub fn bounding_box(&self) -> Box3 {
+        Box3::from_points(self.positions.iter())
+    }
+
+    /// Oriented bounding box along the principal axes of the vertices (PCA)
+    pub fn oriented_bounding_box(&self) -> OrientedBox3 {
+        OrientedBox3::from_points_pca(&self.positions)
+    }
+
+    fn triangle_positions(&self, t: &glm::U32Vec3) -> (&DVec3, &DVec3, &DVec3) {
+        (&self.positions[t[0] as usize], &self.positions[t[1] as usize], &self.positions[t[2] as usize])
+    }
+}
+
+#[cfg(test)]
+mod measure_tests {
+    use super::*;
+    use super::super::test_meshes::*;
+
+    #[test]
+    fn cube_measurements() {
+        let mesh = cube(DVec3::new(1., 2., 3.), 2.0);
+        assert!((mesh.surface_area() - 24.0).abs() < 1e-9);
+        assert!((mesh.volume() - 8.0).abs() < 1e-9);
+        assert!((mesh.centroid() - DVec3::new(1., 2., 3.)).magnitude() < 1e-9);
+
+        // Solid cube: I = m * (a² + a²) / 12 on each axis, with m = 8 and a = 2
+        let inertia = mesh.inertia_tensor();
+        let expected = 8.0 * 8.0 / 12.0;
+        for i in 0..3 {
+            for j in 0..3 {
+                let value = if i == j { expected } else { 0.0 };
+                assert!((inertia[(i, j)] - value).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn bounding_boxes() {
+        let mut mesh = cube(DVec3::zeros(), 2.0);
+        let bounds = mesh.bounding_box();
+        assert_eq!(bounds.min, DVec3::new(-1., -1., -1.));
+        assert_eq!(bounds.max, DVec3::new(1., 1., 1.));
+        assert!(bounds.contains(&DVec3::zeros()));
+        assert!(bounds.intersection(&Box3::new(DVec3::new(2., 2., 2.), DVec3::new(3., 3., 3.))).is_none());
+        assert!((bounds.union(&Box3::new(DVec3::new(2., 2., 2.), DVec3::new(3., 3., 3.))).volume() - 64.0).abs() < 1e-9);
+
+        // Stretch the cube along X and rotate it by 45° around Z: the AABB grows but the OBB should not
+        let rotation = glm::rotation(std::f64::consts::FRAC_PI_4, &DVec3::z());
+        for p in mesh.positions.iter_mut() {
+            *p = (rotation * glm::vec4(p.x * 3.0, p.y, p.z, 0.0)).xyz();
+        }
+        let obb = mesh.oriented_bounding_box();
+        assert!((obb.volume() - 24.0).abs() < 1e-6);
+        assert!(obb.volume() < mesh.bounding_box().volume());
+        for p in &mesh.positions {
+            assert!(obb.contains(&(obb.center + (p - obb.center) * 0.999)));
+        }
+    }
+}
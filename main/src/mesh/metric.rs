@@ -0,0 +1,185 @@
+use nalgebra_glm as glm;
+use glm::{DVec3};
+use super::SharedMesh;
+use super::super::spatial::Bvh;
+
+/// Deviation of a mesh measured against a reference surface
+#[derive(Debug, Clone)]
+pub struct Deviation {
+    /// Maximum distance found (one-sided Hausdorff distance)
+    pub max: f64,
+    pub mean: f64,
+    /// Root mean square distance
+    pub rms: f64,
+    /// Number of surface samples used for the mean and rms
+    pub sample_count: usize,
+    /// Distance of each vertex of the measured mesh to the reference surface
+    pub vertex_deviations: Vec<f64>,
+}
+
+/// Deviation in both directions
+#[derive(Debug, Clone)]
+pub struct SymmetricDeviation {
+    pub a_to_b: Deviation,
+    pub b_to_a: Deviation,
+}
+
+impl SymmetricDeviation {
+    /// Symmetric Hausdorff distance
+    pub fn hausdorff(&self) -> f64 {
+        self.a_to_b.max.max(self.b_to_a.max)
+    }
+
+    pub fn rms(&self) -> f64 {
+        let count = (self.a_to_b.sample_count + self.b_to_a.sample_count) as f64;
+        if count == 0.0 {
+            return 0.0;
+        }
+        ((self.a_to_b.rms.powi(2) * self.a_to_b.sample_count as f64 + self.b_to_a.rms.powi(2) * self.b_to_a.sample_count as f64) / count).sqrt()
+    }
+}
+
+impl Deviation {
+    /// Maps vertex deviations to colors, from blue (no deviation) to green then red (`max_deviation` and above).
+    /// The result can be assigned to `SharedMesh::colors` of the measured mesh.
+    pub fn vertex_colors(&self, max_deviation: f64) -> Vec<DVec3> {
+        self.vertex_deviations.iter()
+            .map(|d| {
+                let x = if max_deviation > 0.0 { (d / max_deviation).min(1.0) } else { 0.0 };
+                if x < 0.5 {
+                    DVec3::new(0.0, x * 2.0, 1.0 - x * 2.0)
+                } else {
+                    DVec3::new(x * 2.0 - 1.0, 2.0 - x * 2.0, 0.0)
+                }
+            })
+            .collect()
+    }
+}
+
+/// Measures how far the surface of `mesh` deviates from `reference`.
+/// The surface is sampled uniformly by area with about `sample_count` points, in addition to every vertex.
+/// Mean and RMS are computed on the area samples, the maximum includes vertices.
+pub fn deviation(mesh: &SharedMesh, reference: &SharedMesh, sample_count: usize) -> Deviation {
+    deviation_from_bvh(mesh, &Bvh::new(reference), sample_count)
+}
+
+/// Same as `deviation`, with a prebuilt BVH of the reference mesh (useful when comparing several meshes to the same reference)
+pub fn deviation_from_bvh(mesh: &SharedMesh, reference: &Bvh, sample_count: usize) -> Deviation {
+    let distance_to = |p: &DVec3| -> f64 {
+        reference.closest_point(p, f64::MAX).map(|c| c.distance).unwrap_or(f64::MAX)
+    };
+
+    let vertex_deviations: Vec<f64> = mesh.positions.iter().map(distance_to).collect();
+
+    let mut max = 0.0f64;
+    for t in &mesh.triangles {
+        for k in 0..3 {
+            max = max.max(vertex_deviations[t[k] as usize]);
+        }
+    }
+
+    let mut sum = 0.0;
+    let mut sum_squared = 0.0;
+    let samples = sample_surface(mesh, sample_count);
+    for sample in &samples {
+        let distance = distance_to(sample);
+        max = max.max(distance);
+        sum += distance;
+        sum_squared += distance * distance;
+    }
+
+    let count = samples.len();
+    Deviation {
+        max,
+        mean: if count > 0 { sum / count as f64 } else { 0.0 },
+        rms: if count > 0 { (sum_squared / count as f64).sqrt() } else { 0.0 },
+        sample_count: count,
+        vertex_deviations,
+    }
+}
+
+/// Measures deviation in both directions, which is required to catch both removed and added features
+pub fn symmetric_deviation(a: &SharedMesh, b: &SharedMesh, sample_count: usize) -> SymmetricDeviation {
+    SymmetricDeviation {
+        a_to_b: deviation(a, b, sample_count),
+        b_to_a: deviation(b, a, sample_count),
+    }
+}
+
+/// Symmetric Hausdorff distance between two meshes
+pub fn hausdorff_distance(a: &SharedMesh, b: &SharedMesh, sample_count: usize) -> f64 {
+    symmetric_deviation(a, b, sample_count).hausdorff()
+}
+
+/// Deterministic, area uniform sampling of the surface.
+/// Samples are distributed among triangles with error diffusion and placed with a Halton sequence.
+pub fn sample_surface(mesh: &SharedMesh, sample_count: usize) -> Vec<DVec3> {
+    let total_area = mesh.surface_area();
+    let mut samples = Vec::with_capacity(sample_count);
+    if total_area <= 0.0 || sample_count == 0 {
+        return samples;
+    }
+
+    let samples_per_area = sample_count as f64 / total_area;
+    let mut carry = 0.0;
+    let mut index = 0u32;
+    for (i, t) in mesh.triangles.iter().enumerate() {
+        let expected = mesh.triangle_area(i) * samples_per_area + carry;
+        let count = expected.floor();
+        carry = expected - count;
+
+        let a = &mesh.positions[t[0] as usize];
+        let b = &mesh.positions[t[1] as usize];
+        let c = &mesh.positions[t[2] as usize];
+        for _ in 0..count as u32 {
+            index += 1;
+            let r1 = halton(index, 2).sqrt();
+            let r2 = halton(index, 3);
+            samples.push(a * (1.0 - r1) + b * (r1 * (1.0 - r2)) + c * (r1 * r2));
+        }
+    }
+    samples
+}
+
+fn halton(mut index: u32, base: u32) -> f64 {
+    let mut f = 1.0;
+    let mut r = 0.0;
+    while index > 0 {
+        f /= base as f64;
+        r += f * (index % base) as f64;
+        index /= base;
+    }
+    r
+}
+
+#[cfg(test)]
+mod metric_tests {
+    use super::*;
+    use super::super::test_meshes::*;
+
+    #[test]
+    fn identical_meshes() {
+        let mesh = sphere(DVec3::zeros(), 1.0, 2);
+        let deviation = symmetric_deviation(&mesh, &mesh, 1000);
+        assert!(deviation.hausdorff() < 1e-9);
+        assert!(deviation.rms() < 1e-9);
+        assert!(deviation.a_to_b.sample_count > 900);
+    }
+
+    #[test]
+    fn offset_cubes() {
+        let a = cube(DVec3::zeros(), 2.0);
+        let b = cube(DVec3::zeros(), 2.2);
+        let deviation = symmetric_deviation(&a, &b, 2000);
+
+        // Every point of the small cube is exactly 0.1 away from the large one
+        assert!((deviation.a_to_b.mean - 0.1).abs() < 1e-9);
+        assert!((deviation.a_to_b.max - 0.1).abs() < 1e-9);
+        assert!(deviation.a_to_b.vertex_deviations.iter().all(|d| (d - 0.1).abs() < 1e-9));
+
+        // Corners of the large cube are the furthest points from the small one
+        assert!((deviation.b_to_a.max - 0.1 * 3f64.sqrt()).abs() < 1e-9);
+        assert!((deviation.hausdorff() - 0.1 * 3f64.sqrt()).abs() < 1e-9);
+        assert_eq!(deviation.b_to_a.vertex_colors(0.1 * 3f64.sqrt())[0], DVec3::new(1., 0., 0.));
+    }
+}
@@ -1,5 +1,5 @@
 use nalgebra_glm as glm;
-use glm::{DVec3, U32Vec3};
+use glm::{DVec2, DVec3, U32Vec3};
 use super::utils::*;
 
 use std::hash::BuildHasherDefault;
@@ -17,5 +17,39 @@ pub use unsafe_mesh::UnsafeMesh as UnsafeMesh;
 pub mod shared_mesh;
 pub use shared_mesh::SharedMesh as SharedMesh; 
 
+pub(crate) mod adjacency;
+
+pub mod orientation;
+
+pub mod components;
+pub use components::{Components, Connectivity};
+
+pub mod measure;
+pub use measure::MassProperties;
+
+pub mod metric;
+
+pub mod clustering;
+
+pub mod visibility;
+
+pub mod slice;
+pub use slice::Polyline;
+
+pub mod boolean;
+pub use boolean::BooleanOperation;
+
+pub mod hull;
+
+pub mod unwrap;
+
+pub mod optimize;
+
+pub mod chunks;
+pub use chunks::U16Mesh;
+
+#[cfg(test)]
+pub(crate) mod test_meshes;
+
 include!("connected_mesh.rs");
 include!("builders.rs");
\ No newline at end of file
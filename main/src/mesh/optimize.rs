@@ -0,0 +1,506 @@
+// Index buffer optimizations for GPU rendering: post-transform vertex cache ordering (Forsyth, Tipsify),
+// overdraw reduction (Sander et al. 2007) and vertex fetch ordering.
+// Triangles are only reordered within their group (or within runs of triangles outside of any group).
+
+use nalgebra_glm as glm;
+use glm::{DVec2, DVec3, U32Vec3};
+use std::ops::Range;
+use super::SharedMesh;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VertexCacheAlgorithm {
+    /// Linear-speed vertex cache optimization (Tom Forsyth), with a LRU cache model
+    Forsyth,
+    /// Tipsify (Sander et al. 2007), faster, with a FIFO cache model
+    Tipsify,
+}
+
+/// Post-transform vertex cache efficiency, simulated with a FIFO cache
+#[derive(Debug, Copy, Clone)]
+pub struct VertexCacheStatistics {
+    pub vertices_transformed: u32,
+    /// Average cache miss ratio: transformed vertices per triangle. 0.5 is the best possible, 3 the worst.
+    pub acmr: f64,
+    /// Average transform to vertex ratio: transformed vertices per used vertex. 1 is the best possible.
+    pub atvr: f64,
+}
+
+impl SharedMesh {
+    /// Reorders triangles for a better post-transform vertex cache hit rate
+    pub fn optimize_vertex_cache(&mut self, algorithm: VertexCacheAlgorithm, cache_size: u32) {
+        let cache_size = cache_size.max(4) as usize;
+        for range in self.triangle_ranges() {
+            let order = match algorithm {
+                VertexCacheAlgorithm::Forsyth => forsyth(&self.triangles[range.clone()], self.positions.len(), cache_size),
+                VertexCacheAlgorithm::Tipsify => tipsify(&self.triangles[range.clone()], self.positions.len(), cache_size),
+            };
+            self.reorder_triangles(range, &order);
+        }
+    }
+
+    /// Reorders clusters of triangles so that outward facing ones are drawn first and occlude the others.
+    /// Expects triangles to be ordered for the vertex cache first. Clusters are split so that the cache miss
+    /// ratio grows by at most `threshold` (1.05 allows 5%).
+    pub fn optimize_overdraw(&mut self, cache_size: u32, threshold: f64) {
+        let cache_size = cache_size.max(4) as usize;
+        let center = self.bounding_box().center();
+        for range in self.triangle_ranges() {
+            let triangles = &self.triangles[range.clone()];
+            let mut clusters = Vec::new();
+            for hard in hard_boundaries(triangles, self.positions.len(), cache_size) {
+                clusters.extend(soft_boundaries(triangles, hard, self.positions.len(), cache_size, threshold));
+            }
+
+            let keys: Vec<f64> = clusters.iter().map(|cluster| {
+                let mut centroid = DVec3::zeros();
+                let mut normal = DVec3::zeros();
+                let mut area = 0.0;
+                for t in &triangles[cluster.clone()] {
+                    let [a, b, c] = [0, 1, 2].map(|k| self.positions[t[k] as usize]);
+                    let n = (b - a).cross(&(c - a));
+                    centroid += (a + b + c) / 3.0 * n.magnitude();
+                    area += n.magnitude();
+                    normal += n;
+                }
+                if area == 0.0 || normal.magnitude_squared() == 0.0 {
+                    return f64::MIN;
+                }
+                (centroid / area - center).dot(&normal.normalize())
+            }).collect();
+
+            let mut cluster_order: Vec<usize> = (0..clusters.len()).collect();
+            cluster_order.sort_by(|a, b| keys[*b].partial_cmp(&keys[*a]).unwrap());
+            let order: Vec<u32> = cluster_order.iter().flat_map(|c| clusters[*c].clone().map(|t| t as u32)).collect();
+            self.reorder_triangles(range, &order);
+        }
+    }
+
+    /// Reorders vertices in the order they are first used by triangles, for better vertex fetch locality.
+    /// Unused vertices are removed.
+    pub fn optimize_vertex_fetch(&mut self) {
+        let mut remap = vec![u32::MAX; self.positions.len()];
+        let mut vertices = Vec::with_capacity(self.positions.len());
+        for t in self.triangles.iter_mut() {
+            for k in 0..3 {
+                let v = t[k] as usize;
+                if remap[v] == u32::MAX {
+                    remap[v] = vertices.len() as u32;
+                    vertices.push(v);
+                }
+                t[k] = remap[v];
+            }
+        }
+
+        let count = self.positions.len();
+        self.positions = vertices.iter().map(|v| self.positions[*v]).collect();
+        if let Some(normals) = self.normals.as_mut().filter(|normals| normals.len() == count) {
+            *normals = vertices.iter().map(|v| normals[*v]).collect::<Vec<DVec3>>();
+        }
+        if let Some(colors) = self.colors.as_mut().filter(|colors| colors.len() == count) {
+            *colors = vertices.iter().map(|v| colors[*v]).collect::<Vec<DVec3>>();
+        }
+        if let Some(uvs) = self.uvs.as_mut().filter(|uvs| uvs.len() == count) {
+            *uvs = vertices.iter().map(|v| uvs[*v]).collect::<Vec<DVec2>>();
+        }
+    }
+
+    /// Simulates a FIFO post-transform vertex cache of the given size over the whole index buffer
+    pub fn vertex_cache_statistics(&self, cache_size: u32) -> VertexCacheStatistics {
+        let mut cache = FifoCache::new(self.positions.len(), cache_size.max(1) as usize);
+        let mut is_used = vec![false; self.positions.len()];
+        let mut vertices_transformed = 0;
+        for t in &self.triangles {
+            for k in 0..3 {
+                is_used[t[k] as usize] = true;
+                if !cache.access(t[k]) {
+                    vertices_transformed += 1;
+                }
+            }
+        }
+        let used = is_used.iter().filter(|u| **u).count();
+        VertexCacheStatistics {
+            vertices_transformed,
+            acmr: if self.triangles.is_empty() { 0.0 } else { vertices_transformed as f64 / self.triangles.len() as f64 },
+            atvr: if used == 0 { 0.0 } else { vertices_transformed as f64 / used as f64 },
+        }
+    }
+
+    // Groups, and runs of triangles between them
+    pub(crate) fn triangle_ranges(&self) -> Vec<Range<usize>> {
+        let mut groups: Vec<Range<usize>> = self.groups.iter().map(|g| g.triangles()).filter(|r| !r.is_empty()).collect();
+        groups.sort_by_key(|r| r.start);
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        for group in groups {
+            if group.start > start {
+                ranges.push(start..group.start);
+            }
+            start = start.max(group.end);
+            ranges.push(group);
+        }
+        if start < self.triangles.len() {
+            ranges.push(start..self.triangles.len());
+        }
+        ranges
+    }
+
+    fn reorder_triangles(&mut self, range: Range<usize>, order: &[u32]) {
+        let reordered: Vec<U32Vec3> = order.iter().map(|t| self.triangles[range.start + *t as usize]).collect();
+        self.triangles[range].copy_from_slice(&reordered);
+    }
+}
+
+// FIFO cache with timestamps: a vertex is in the cache if it was inserted less than `size` insertions ago
+struct FifoCache {
+    timestamps: Vec<u32>,
+    time: u32,
+    size: u32,
+}
+
+impl FifoCache {
+    fn new(vertex_count: usize, size: usize) -> Self {
+        FifoCache { timestamps: vec![0; vertex_count], time: size as u32 + 1, size: size as u32 }
+    }
+
+    fn contains(&self, v: u32) -> bool {
+        self.time - self.timestamps[v as usize] <= self.size
+    }
+
+    // Returns true on hits
+    fn access(&mut self, v: u32) -> bool {
+        if self.contains(v) {
+            return true;
+        }
+        self.timestamps[v as usize] = self.time;
+        self.time += 1;
+        false
+    }
+}
+
+// Triangles of each vertex, as offsets in a flat array
+struct VertexTriangles {
+    offsets: Vec<u32>,
+    triangles: Vec<u32>,
+}
+
+impl VertexTriangles {
+    fn new(triangles: &[U32Vec3], vertex_count: usize) -> Self {
+        let mut counts = vec![0u32; vertex_count + 1];
+        for t in triangles {
+            for k in 0..3 {
+                counts[t[k] as usize + 1] += 1;
+            }
+        }
+        for v in 0..vertex_count {
+            counts[v + 1] += counts[v];
+        }
+        let mut fill = counts.clone();
+        let mut list = vec![0u32; triangles.len() * 3];
+        for (i, t) in triangles.iter().enumerate() {
+            for k in 0..3 {
+                let v = t[k] as usize;
+                list[fill[v] as usize] = i as u32;
+                fill[v] += 1;
+            }
+        }
+        VertexTriangles { offsets: counts, triangles: list }
+    }
+
+    fn of(&self, v: u32) -> &[u32] {
+        &self.triangles[self.offsets[v as usize] as usize..self.offsets[v as usize + 1] as usize]
+    }
+}
+
+// Scores from "Linear-Speed Vertex Cache Optimisation" (Tom Forsyth, 2006)
+fn forsyth_score(cache_position: Option<usize>, remaining: u32, cache_size: usize) -> f64 {
+    if remaining == 0 {
+        return -1.0;
+    }
+    let cache_score = match cache_position {
+        // The last triangle used these, so don't reuse them right away
+        Some(position) if position < 3 => 0.75,
+        Some(position) => (1.0 - (position - 3) as f64 / (cache_size - 3) as f64).powf(1.5),
+        None => 0.0,
+    };
+    cache_score + 2.0 * (remaining as f64).powf(-0.5)
+}
+
+fn forsyth(triangles: &[U32Vec3], vertex_count: usize, cache_size: usize) -> Vec<u32> {
+    let adjacency = VertexTriangles::new(triangles, vertex_count);
+    let mut remaining: Vec<u32> = (0..vertex_count as u32).map(|v| adjacency.of(v).len() as u32).collect();
+    let mut vertex_scores: Vec<f64> = remaining.iter().map(|r| forsyth_score(None, *r, cache_size)).collect();
+    let triangle_score = |t: &U32Vec3, vertex_scores: &[f64]| (0..3).map(|k| vertex_scores[t[k] as usize]).sum::<f64>();
+    let mut triangle_scores: Vec<f64> = triangles.iter().map(|t| triangle_score(t, &vertex_scores)).collect();
+    let mut is_added = vec![false; triangles.len()];
+
+    let mut order = Vec::with_capacity(triangles.len());
+    // LRU cache, most recent first. It temporarily holds up to 3 more vertices than its size.
+    let mut cache: Vec<u32> = Vec::with_capacity(cache_size + 3);
+    let mut best = (0..triangles.len()).max_by(|a, b| triangle_scores[*a].partial_cmp(&triangle_scores[*b]).unwrap());
+    let mut scan = 0;
+
+    while let Some(t) = best {
+        order.push(t as u32);
+        is_added[t] = true;
+        for &v in triangles[t].iter() {
+            remaining[v as usize] -= 1;
+            cache.retain(|c| *c != v);
+            cache.insert(0, v);
+        }
+
+        let evicted: Vec<u32> = if cache.len() > cache_size { cache.split_off(cache_size) } else { Vec::new() };
+        for v in evicted.iter().chain(cache.iter()) {
+            let position = cache.iter().position(|c| c == v);
+            vertex_scores[*v as usize] = forsyth_score(position, remaining[*v as usize], cache_size);
+        }
+
+        // Best triangle among those touching the cache
+        best = None;
+        let mut best_score = f64::MIN;
+        for v in evicted.iter().chain(cache.iter()) {
+            for t in adjacency.of(*v) {
+                let t = *t as usize;
+                if is_added[t] {
+                    continue;
+                }
+                triangle_scores[t] = triangle_score(&triangles[t], &vertex_scores);
+                if cache.contains(v) && triangle_scores[t] > best_score {
+                    best_score = triangle_scores[t];
+                    best = Some(t);
+                }
+            }
+        }
+
+        // Otherwise, the next triangle not yet added
+        if best.is_none() {
+            while scan < triangles.len() && is_added[scan] {
+                scan += 1;
+            }
+            if scan < triangles.len() {
+                best = Some(scan);
+            }
+        }
+    }
+    order
+}
+
+// "Fast Triangle Reordering for Vertex Locality and Reduced Overdraw" (Sander et al. 2007)
+fn tipsify(triangles: &[U32Vec3], vertex_count: usize, cache_size: usize) -> Vec<u32> {
+    let adjacency = VertexTriangles::new(triangles, vertex_count);
+    let mut live: Vec<u32> = (0..vertex_count as u32).map(|v| adjacency.of(v).len() as u32).collect();
+    let mut cache = FifoCache::new(vertex_count, cache_size);
+    let mut is_emitted = vec![false; triangles.len()];
+    let mut dead_end = Vec::new();
+    let mut order = Vec::with_capacity(triangles.len());
+    let mut cursor = 0u32;
+
+    let mut fanning = triangles.first().map(|t| t[0]);
+    while let Some(f) = fanning {
+        let mut candidates = Vec::new();
+        for t in adjacency.of(f) {
+            let t = *t as usize;
+            if is_emitted[t] {
+                continue;
+            }
+            is_emitted[t] = true;
+            order.push(t as u32);
+            for &v in triangles[t].iter() {
+                dead_end.push(v);
+                candidates.push(v);
+                live[v as usize] -= 1;
+                cache.access(v);
+            }
+        }
+
+        // Next fanning vertex: the candidate with live triangles that stays in the cache the longest after its fan
+        let mut next = None;
+        let mut best_priority = -1i64;
+        for v in candidates {
+            if live[v as usize] == 0 {
+                continue;
+            }
+            let mut priority = 0;
+            let age = (cache.time - cache.timestamps[v as usize]) as i64;
+            if age + 2 * live[v as usize] as i64 <= cache_size as i64 {
+                priority = age;
+            }
+            if priority > best_priority {
+                best_priority = priority;
+                next = Some(v);
+            }
+        }
+
+        // Dead end: back to recently used vertices, then to the next vertex with live triangles
+        if next.is_none() {
+            while let Some(v) = dead_end.pop() {
+                if live[v as usize] > 0 {
+                    next = Some(v);
+                    break;
+                }
+            }
+        }
+        if next.is_none() {
+            while (cursor as usize) < vertex_count && live[cursor as usize] == 0 {
+                cursor += 1;
+            }
+            if (cursor as usize) < vertex_count {
+                next = Some(cursor);
+            }
+        }
+        fanning = next;
+    }
+    order
+}
+
+// Cache misses of each triangle, with a fresh FIFO cache at the start of the range
+fn triangle_misses(triangles: &[U32Vec3], range: Range<usize>, vertex_count: usize, cache_size: usize) -> Vec<u32> {
+    let mut cache = FifoCache::new(vertex_count, cache_size);
+    triangles[range].iter().map(|t| (0..3).filter(|k| !cache.access(t[*k])).count() as u32).collect()
+}
+
+// Clusters starting where the cache is flushed (triangles with 3 misses)
+fn hard_boundaries(triangles: &[U32Vec3], vertex_count: usize, cache_size: usize) -> Vec<Range<usize>> {
+    let misses = triangle_misses(triangles, 0..triangles.len(), vertex_count, cache_size);
+    let mut clusters = Vec::new();
+    let mut start = 0;
+    for (i, m) in misses.iter().enumerate().skip(1) {
+        if *m == 3 {
+            clusters.push(start..i);
+            start = i;
+        }
+    }
+    if start < triangles.len() {
+        clusters.push(start..triangles.len());
+    }
+    clusters
+}
+
+// Splits a cluster further, as soon as the cache miss ratio of the new cluster is within the threshold
+fn soft_boundaries(triangles: &[U32Vec3], cluster: Range<usize>, vertex_count: usize, cache_size: usize, threshold: f64) -> Vec<Range<usize>> {
+    let misses = triangle_misses(triangles, cluster.clone(), vertex_count, cache_size);
+    let target = threshold * misses.iter().sum::<u32>() as f64 / misses.len() as f64;
+
+    let mut clusters = Vec::new();
+    let mut start = cluster.start;
+    let mut cache = FifoCache::new(vertex_count, cache_size);
+    let mut running_misses = 0;
+    for i in cluster.clone() {
+        running_misses += (0..3).filter(|k| !cache.access(triangles[i][*k])).count();
+        let count = i + 1 - start;
+        // Avoid tiny clusters, that would be sorted with a poor estimation of their orientation
+        if count >= 8 && running_misses as f64 / count as f64 <= target && i + 1 < cluster.end {
+            clusters.push(start..i + 1);
+            start = i + 1;
+            running_misses = 0;
+            cache = FifoCache::new(vertex_count, cache_size);
+        }
+    }
+    if start < cluster.end {
+        clusters.push(start..cluster.end);
+    }
+    clusters
+}
+
+#[cfg(test)]
+mod optimize_tests {
+    use super::*;
+    use super::super::Group;
+    use super::super::test_meshes::*;
+
+    // Deterministic shuffle of the triangles
+    fn shuffled(mut mesh: SharedMesh) -> SharedMesh {
+        let mut state = 12345u64;
+        for i in (1..mesh.triangles.len()).rev() {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            mesh.triangles.swap(i, (state >> 33) as usize % (i + 1));
+        }
+        mesh
+    }
+
+    fn sorted_triangles(mesh: &SharedMesh) -> Vec<[u64; 9]> {
+        let mut triangles: Vec<[u64; 9]> = mesh.triangles.iter().map(|t| {
+            let p = [0, 1, 2].map(|k| mesh.positions[t[k] as usize]);
+            [p[0].x, p[0].y, p[0].z, p[1].x, p[1].y, p[1].z, p[2].x, p[2].y, p[2].z].map(f64::to_bits)
+        }).collect();
+        triangles.sort_unstable();
+        triangles
+    }
+
+    #[test]
+    fn vertex_cache() {
+        for algorithm in [VertexCacheAlgorithm::Forsyth, VertexCacheAlgorithm::Tipsify] {
+            let mut mesh = shuffled(grid(40, 1.0));
+            let original = sorted_triangles(&mesh);
+            let before = mesh.vertex_cache_statistics(16);
+            mesh.optimize_vertex_cache(algorithm, 16);
+            let after = mesh.vertex_cache_statistics(16);
+
+            assert_eq!(sorted_triangles(&mesh), original);
+            assert!(before.acmr > 2.0);
+            assert!(after.acmr < 0.8, "{:?} {}", algorithm, after.acmr);
+            assert!(after.atvr < 1.5);
+            assert_eq!(after.vertices_transformed as f64, after.acmr * mesh.triangles.len() as f64);
+        }
+    }
+
+    #[test]
+    fn groups_are_kept() {
+        let mut mesh = shuffled(sphere(DVec3::zeros(), 1.0, 3));
+        mesh.groups = vec![Group::new(0, 300), Group::new(600, 900)];
+        let before: Vec<Vec<[u64; 9]>> = mesh.triangle_ranges().iter()
+            .map(|r| sorted_triangles(&mesh.submesh(&(0..mesh.triangles.len()).map(|t| r.contains(&t)).collect::<Vec<bool>>())))
+            .collect();
+        assert_eq!(before.len(), 4);
+
+        mesh.optimize_vertex_cache(VertexCacheAlgorithm::Tipsify, 16);
+        mesh.optimize_overdraw(16, 1.05);
+        let after: Vec<Vec<[u64; 9]>> = mesh.triangle_ranges().iter()
+            .map(|r| sorted_triangles(&mesh.submesh(&(0..mesh.triangles.len()).map(|t| r.contains(&t)).collect::<Vec<bool>>())))
+            .collect();
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn overdraw_draws_outer_shell_first() {
+        let outer = sphere(DVec3::zeros(), 1.0, 3);
+        let outer_count = outer.triangles.len();
+        let mut mesh = SharedMesh::combine(sphere(DVec3::zeros(), 0.5, 3), outer);
+        mesh.normals = None;
+        mesh.optimize_vertex_cache(VertexCacheAlgorithm::Tipsify, 16);
+        let before = mesh.vertex_cache_statistics(16);
+        mesh.optimize_overdraw(16, 1.05);
+        let after = mesh.vertex_cache_statistics(16);
+
+        assert!(after.acmr <= before.acmr * 1.1);
+        // Clusters are large and curved, so only on average
+        let rank = |outer: bool| {
+            let ranks: Vec<usize> = (0..mesh.triangles.len())
+                .filter(|t| (mesh.positions[mesh.triangles[*t][0] as usize].magnitude() > 0.9) == outer)
+                .collect();
+            assert_eq!(ranks.len(), outer_count);
+            ranks.iter().sum::<usize>() as f64 / ranks.len() as f64
+        };
+        assert!(rank(true) < 0.8 * rank(false));
+    }
+
+    #[test]
+    fn vertex_fetch() {
+        let mut mesh = shuffled(sphere(DVec3::zeros(), 1.0, 2));
+        let original = sorted_triangles(&mesh);
+        mesh.optimize_vertex_cache(VertexCacheAlgorithm::Forsyth, 16);
+        mesh.optimize_vertex_fetch();
+
+        assert_eq!(sorted_triangles(&mesh), original);
+        assert!(mesh.normals.as_ref().unwrap().iter().zip(&mesh.positions).all(|(n, p)| n == p));
+        // Each new vertex is the next index
+        let mut next = 0;
+        for t in &mesh.triangles {
+            for k in 0..3 {
+                assert!(t[k] <= next);
+                if t[k] == next {
+                    next += 1;
+                }
+            }
+        }
+    }
+}
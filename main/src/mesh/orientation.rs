@@ -0,0 +1,186 @@
+use nalgebra_glm as glm;
+use glm::U32Vec3;
+use super::SharedMesh;
+use super::adjacency::*;
+
+use std::collections::VecDeque;
+
+impl SharedMesh {
+    /// Propagates a consistent winding across each edge-connected component.
+    /// Within a component, the winding shared by the majority of triangles is kept.
+    /// Triangles are only propagated through manifold edges (exactly two faces).
+    /// Returns the number of triangles that were flipped.
+    pub fn unify_winding(&mut self) -> usize {
+        let welded = weld_positions(&self.positions);
+        let flips = self.consistent_flips(&welded);
+        self.apply_flips(&flips.flip)
+    }
+
+    /// Makes winding consistent, then orients every closed component so that its normals face outward, from its
+    /// own signed volume. Nested components (a bolt inside a housing, but also a void) all face outward.
+    /// Open components only get a consistent winding, since inside and outside are not defined for them.
+    /// Returns the number of triangles that were flipped.
+    pub fn orient_outward(&mut self) -> usize {
+        let welded = weld_positions(&self.positions);
+        let mut flips = self.consistent_flips(&welded);
+
+        let closed: Vec<bool> = flips.closed.clone();
+        let component_count = closed.len();
+
+        // Orient closed components with a positive signed volume
+        let mut volumes = vec![0.0; component_count];
+        for (f, t) in self.triangles.iter().enumerate() {
+            let component = flips.component[f] as usize;
+            if !closed[component] {
+                continue;
+            }
+            let (a, b, c) = (&self.positions[t[0] as usize], &self.positions[t[1] as usize], &self.positions[t[2] as usize]);
+            let volume = a.dot(&b.cross(c));
+            volumes[component] += if flips.flip[f] { -volume } else { volume };
+        }
+
+        let mut inverted = vec![false; component_count];
+        for component in 0..component_count {
+            inverted[component] = closed[component] && volumes[component] < 0.0;
+        }
+
+        for f in 0..self.triangles.len() {
+            if inverted[flips.component[f] as usize] {
+                flips.flip[f] = !flips.flip[f];
+            }
+        }
+
+        self.apply_flips(&flips.flip)
+    }
+
+    fn consistent_flips(&self, welded: &[u32]) -> Flips {
+        let edges = edge_faces(&self.triangles, welded);
+
+        let mut flip = vec![false; self.triangles.len()];
+        let mut component = vec![u32::MAX; self.triangles.len()];
+        let mut closed = Vec::new();
+        let mut queue = VecDeque::new();
+
+        for seed in 0..self.triangles.len() {
+            if component[seed] != u32::MAX {
+                continue;
+            }
+            let component_id = closed.len() as u32;
+            let mut is_closed = true;
+            let mut members = Vec::new();
+            component[seed] = component_id;
+            queue.push_back(seed as u32);
+
+            while let Some(f) = queue.pop_front() {
+                members.push(f);
+                let t = &self.triangles[f as usize];
+                for k in 0..3 {
+                    let a = welded[t[k] as usize];
+                    let b = welded[t[(k + 1) % 3] as usize];
+                    if a == b {
+                        continue;
+                    }
+                    let faces = &edges[&edge_key(a, b)];
+                    if faces.len() != 2 {
+                        is_closed = false;
+                        continue;
+                    }
+                    let g = if faces[0] == f { faces[1] } else { faces[0] };
+                    if g == f {
+                        continue;
+                    }
+                    // Consistent neighbors walk the shared edge in opposite directions
+                    let same_direction = walks_edge(&self.triangles[g as usize], welded, a, b);
+                    let g_flip = flip[f as usize] ^ same_direction;
+                    if component[g as usize] == u32::MAX {
+                        component[g as usize] = component_id;
+                        flip[g as usize] = g_flip;
+                        queue.push_back(g);
+                    } else if flip[g as usize] != g_flip {
+                        // Non orientable (Möbius strip, Klein bottle...)
+                        is_closed = false;
+                    }
+                }
+            }
+
+            // Keep the winding of the majority to minimize changes
+            let flipped = members.iter().filter(|f| flip[**f as usize]).count();
+            if flipped * 2 > members.len() {
+                for f in &members {
+                    flip[*f as usize] = !flip[*f as usize];
+                }
+            }
+
+            closed.push(is_closed);
+        }
+
+        Flips { flip, component, closed }
+    }
+
+    fn apply_flips(&mut self, flip: &[bool]) -> usize {
+        let mut flipped = 0;
+        for (t, flip) in self.triangles.iter_mut().zip(flip) {
+            if *flip {
+                *t = U32Vec3::new(t[0], t[2], t[1]);
+                flipped += 1;
+            }
+        }
+        flipped
+    }
+}
+
+struct Flips {
+    flip: Vec<bool>,
+    component: Vec<u32>,
+    closed: Vec<bool>,
+}
+
+#[cfg(test)]
+mod orientation_tests {
+    use super::*;
+    use super::super::test_meshes::*;
+    use glm::DVec3;
+
+    fn signed_volume(mesh: &SharedMesh) -> f64 {
+        mesh.triangles.iter()
+            .map(|t| mesh.positions[t[0] as usize].dot(&mesh.positions[t[1] as usize].cross(&mesh.positions[t[2] as usize])) / 6.0)
+            .sum()
+    }
+
+    #[test]
+    fn unify_winding_fixes_flipped_triangles() {
+        let mut mesh = cube(DVec3::zeros(), 1.0);
+        let t = mesh.triangles[3];
+        mesh.triangles[3] = U32Vec3::new(t[0], t[2], t[1]);
+
+        assert_eq!(mesh.unify_winding(), 1);
+        assert!((signed_volume(&mesh) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn orient_outward_flips_inverted_shell() {
+        let mut mesh = cube(DVec3::zeros(), 1.0);
+        for t in mesh.triangles.iter_mut() {
+            *t = U32Vec3::new(t[0], t[2], t[1]);
+        }
+        assert!(signed_volume(&mesh) < 0.0);
+
+        assert_eq!(mesh.orient_outward(), 12);
+        assert!((signed_volume(&mesh) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn orient_outward_keeps_nested_shells_outward() {
+        // A cube of size 1 inside a cube of size 4, both facing outward, like a part inside a housing
+        let mut mesh = SharedMesh::combine(cube(DVec3::zeros(), 4.0), cube(DVec3::zeros(), 1.0));
+        assert_eq!(mesh.orient_outward(), 0);
+        assert!((signed_volume(&mesh) - (64.0 + 1.0)).abs() < 1e-9);
+
+        let inner = mesh.triangles.len() - 12;
+        for t in &mut mesh.triangles[inner..] {
+            *t = U32Vec3::new(t[0], t[2], t[1]);
+        }
+        assert_eq!(mesh.orient_outward(), 12);
+        assert!((signed_volume(&mesh) - (64.0 + 1.0)).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,528 @@
+// Isotropic remeshing ("A Remeshing Approach to Multiresolution Modeling", Botsch & Kobbelt, 2004).
+// Each iteration splits long edges, collapses short ones, flips edges to equalize valences
+// and relaxes vertices tangentially before projecting them back onto the original surface.
+
+use super::spatial::Bvh;
+
+#[derive(Debug, Clone)]
+pub struct RemeshOptions {
+    /// Target edge length. When zero, the mean edge length of the input is used.
+    pub target_edge_length: f64,
+    pub iterations: u32,
+    /// Edges with a dihedral angle above this angle (in radians) are kept as features.
+    /// Boundaries, group borders and normal seams are always kept.
+    pub feature_angle: f64,
+}
+
+impl Default for RemeshOptions {
+    fn default() -> Self {
+        RemeshOptions {
+            target_edge_length: 0.0,
+            iterations: 5,
+            feature_angle: std::f64::consts::FRAC_PI_4,
+        }
+    }
+}
+
+impl ConnectedMesh {
+    /// Remeshes the surface with triangles of about `target_edge_length`, close to equilateral.
+    /// Feature edges are split but never moved. Normals, colors and texture coordinates are resampled from the original surface.
+    pub fn remesh(&mut self, options: &RemeshOptions) {
+        let mut mesh = self.to_polygons();
+        mesh.polygons = triangulate_polygons(mesh.polygons);
+
+        let mut state = RemeshState::new(&mesh, options.feature_angle);
+        let target = if options.target_edge_length > 0.0 { options.target_edge_length } else { state.mean_edge_length() };
+        let original = Bvh::from_triangles(mesh.polygons.iter()
+            .map(|t| [0, 1, 2].map(|k| mesh.positions[t.corners[k].position as usize]))
+            .collect());
+
+        for _ in 0..options.iterations {
+            state.split_long_edges(target * 4.0 / 3.0);
+            state.collapse_short_edges(target * 4.0 / 5.0, target * 4.0 / 3.0);
+            state.equalize_valences();
+            state.relax(&original);
+        }
+
+        let polygons = state.resample_attributes(&mut mesh, &original);
+        mesh.positions = state.positions;
+        mesh.polygons = polygons;
+        self.set_polygons(mesh);
+    }
+}
+
+struct RemeshState {
+    positions: Vec<DVec3>,
+    triangles: Vec<[u32; 3]>,
+    groups: Vec<u32>,
+    is_triangle_removed: Vec<bool>,
+    vertex_triangles: Vec<Vec<u32>>,
+    feature_edges: HashSet<u64>,
+    // Number of feature edges around each vertex
+    feature_degrees: Vec<u32>,
+    // Vertices that must not move (feature corners)
+    is_locked: Vec<bool>,
+}
+
+impl RemeshState {
+    fn new(mesh: &PolygonMesh, feature_angle: f64) -> Self {
+        let creases = SubdivisionOptions { normal_seams_as_creases: true, group_borders_as_creases: true, ..Default::default() };
+        let mut state = RemeshState {
+            positions: mesh.positions.clone(),
+            triangles: mesh.polygons.iter().map(|t| [0, 1, 2].map(|k| t.corners[k].position)).collect(),
+            groups: mesh.polygons.iter().map(|t| t.group).collect(),
+            is_triangle_removed: vec![false; mesh.polygons.len()],
+            vertex_triangles: vec![Vec::new(); mesh.positions.len()],
+            feature_edges: HashSet::new(),
+            feature_degrees: vec![0; mesh.positions.len()],
+            is_locked: vec![false; mesh.positions.len()],
+        };
+
+        for (t, triangle) in state.triangles.iter().enumerate() {
+            for v in triangle {
+                state.vertex_triangles[*v as usize].push(t as u32);
+            }
+        }
+
+        let cos_feature_angle = feature_angle.cos();
+        for (key, edge) in mesh.edges(&creases) {
+            let is_feature = edge.is_crease || {
+                let n0 = state.triangle_normal(edge.sides[0].0).normalize();
+                let n1 = state.triangle_normal(edge.sides[1].0).normalize();
+                n0.dot(&n1) < cos_feature_angle
+            };
+            if is_feature {
+                state.add_feature_edge(key);
+            }
+        }
+
+        // Corners: feature vertices that are not in the middle of a smooth feature line
+        for v in 0..state.positions.len() {
+            state.is_locked[v] = match state.feature_degrees[v] {
+                0 => false,
+                2 => {
+                    let features: Vec<u32> = state.neighbors(v as u32).into_iter()
+                        .filter(|n| state.feature_edges.contains(&adjacency::edge_key(v as u32, *n)))
+                        .collect();
+                    let p = state.positions[v];
+                    let d0 = (state.positions[features[0] as usize] - p).normalize();
+                    let d1 = (state.positions[features[1] as usize] - p).normalize();
+                    d0.dot(&d1) > -cos_feature_angle
+                }
+                _ => true,
+            };
+        }
+
+        state
+    }
+
+    fn add_feature_edge(&mut self, key: u64) {
+        if self.feature_edges.insert(key) {
+            self.feature_degrees[(key >> 32) as usize] += 1;
+            self.feature_degrees[key as u32 as usize] += 1;
+        }
+    }
+
+    fn remove_feature_edge(&mut self, key: u64) -> bool {
+        let removed = self.feature_edges.remove(&key);
+        if removed {
+            self.feature_degrees[(key >> 32) as usize] -= 1;
+            self.feature_degrees[key as u32 as usize] -= 1;
+        }
+        removed
+    }
+
+    fn mean_edge_length(&self) -> f64 {
+        let edges = self.edges();
+        edges.iter().map(|(a, b)| self.edge_length(*a, *b)).sum::<f64>() / edges.len().max(1) as f64
+    }
+
+    fn edge_length(&self, a: u32, b: u32) -> f64 {
+        (self.positions[a as usize] - self.positions[b as usize]).magnitude()
+    }
+
+    fn edges(&self) -> Vec<(u32, u32)> {
+        let mut keys = HashSet::new();
+        let mut edges = Vec::new();
+        for (t, triangle) in self.triangles.iter().enumerate() {
+            if self.is_triangle_removed[t] {
+                continue;
+            }
+            for k in 0..3 {
+                let (a, b) = (triangle[k], triangle[(k + 1) % 3]);
+                if keys.insert(adjacency::edge_key(a, b)) {
+                    edges.push((a, b));
+                }
+            }
+        }
+        edges
+    }
+
+    fn edge_triangles(&self, a: u32, b: u32) -> Vec<u32> {
+        self.vertex_triangles[a as usize].iter()
+            .copied()
+            .filter(|t| self.triangles[*t as usize].contains(&b))
+            .collect()
+    }
+
+    fn neighbors(&self, v: u32) -> Vec<u32> {
+        let mut neighbors = Vec::with_capacity(8);
+        for t in &self.vertex_triangles[v as usize] {
+            for w in &self.triangles[*t as usize] {
+                if *w != v && !neighbors.contains(w) {
+                    neighbors.push(*w);
+                }
+            }
+        }
+        neighbors
+    }
+
+    fn triangle_normal(&self, t: u32) -> DVec3 {
+        let [a, b, c] = self.triangles[t as usize].map(|v| self.positions[v as usize]);
+        (b - a).cross(&(c - a))
+    }
+
+    fn vertex_normal(&self, v: u32) -> DVec3 {
+        let normal = self.vertex_triangles[v as usize].iter().map(|t| self.triangle_normal(*t)).sum::<DVec3>();
+        if normal.magnitude_squared() > 0.0 { normal.normalize() } else { normal }
+    }
+
+    fn is_boundary(&self, v: u32) -> bool {
+        self.neighbors(v).len() > self.vertex_triangles[v as usize].len()
+    }
+
+    fn add_triangle(&mut self, triangle: [u32; 3], group: u32) -> u32 {
+        let t = self.triangles.len() as u32;
+        self.triangles.push(triangle);
+        self.groups.push(group);
+        self.is_triangle_removed.push(false);
+        t
+    }
+
+    fn add_vertex(&mut self, position: DVec3) -> u32 {
+        self.positions.push(position);
+        self.vertex_triangles.push(Vec::new());
+        self.feature_degrees.push(0);
+        self.is_locked.push(false);
+        self.positions.len() as u32 - 1
+    }
+
+    fn split_long_edges(&mut self, max_length: f64) {
+        // Split until no edge is too long, edges longer than twice the limit need several passes
+        let mut long_edges: Vec<(u32, u32)> = self.edges();
+        while !long_edges.is_empty() {
+            for (a, b) in std::mem::take(&mut long_edges) {
+                if self.edge_length(a, b) > max_length && !self.edge_triangles(a, b).is_empty() {
+                    let m = self.split_edge(a, b);
+                    long_edges.extend_from_slice(&[(a, m), (m, b)]);
+                    for t in &self.vertex_triangles[m as usize] {
+                        let opposite = *self.triangles[*t as usize].iter().find(|v| **v != a && **v != b && **v != m).unwrap();
+                        long_edges.push((m, opposite));
+                    }
+                }
+            }
+        }
+    }
+
+    fn split_edge(&mut self, a: u32, b: u32) -> u32 {
+        let m = self.add_vertex((self.positions[a as usize] + self.positions[b as usize]) / 2.0);
+        for t in self.edge_triangles(a, b) {
+            let triangle = self.triangles[t as usize];
+            let k = (0..3).find(|k| !(triangle[*k] == a || triangle[*k] == b)).unwrap();
+            let (x, y, z) = (triangle[(k + 1) % 3], triangle[(k + 2) % 3], triangle[k]);
+            self.triangles[t as usize] = [x, m, z];
+            let new_triangle = self.add_triangle([m, y, z], self.groups[t as usize]);
+            self.vertex_triangles[y as usize].retain(|u| *u != t);
+            self.vertex_triangles[y as usize].push(new_triangle);
+            self.vertex_triangles[z as usize].push(new_triangle);
+            self.vertex_triangles[m as usize].extend_from_slice(&[t, new_triangle]);
+        }
+        if self.remove_feature_edge(adjacency::edge_key(a, b)) {
+            self.add_feature_edge(adjacency::edge_key(a, m));
+            self.add_feature_edge(adjacency::edge_key(m, b));
+        }
+        m
+    }
+
+    fn collapse_short_edges(&mut self, min_length: f64, max_length: f64) {
+        for (a, b) in self.edges() {
+            if self.vertex_triangles[a as usize].is_empty() || self.vertex_triangles[b as usize].is_empty() {
+                continue;
+            }
+            if self.edge_length(a, b) >= min_length {
+                continue;
+            }
+            if !self.try_collapse(a, b, max_length) {
+                self.try_collapse(b, a, max_length);
+            }
+        }
+    }
+
+    // Collapses a into b
+    fn try_collapse(&mut self, a: u32, b: u32, max_length: f64) -> bool {
+        let key = adjacency::edge_key(a, b);
+        let is_feature_edge = self.feature_edges.contains(&key);
+        if self.is_locked[a as usize] || (self.feature_degrees[a as usize] > 0 && !is_feature_edge) {
+            return false;
+        }
+        // Moving along a feature line is fine, leaving it is not
+        if is_feature_edge && self.feature_degrees[a as usize] != 2 {
+            return false;
+        }
+
+        let target = if self.is_locked[b as usize] || self.feature_degrees[b as usize] > 0 || self.feature_degrees[a as usize] > 0 {
+            self.positions[b as usize]
+        } else {
+            (self.positions[a as usize] + self.positions[b as usize]) / 2.0
+        };
+        if target != self.positions[b as usize] && (self.is_locked[b as usize] || self.feature_degrees[b as usize] > 0) {
+            return false;
+        }
+
+        // Link condition: the only vertices adjacent to both a and b are the opposite vertices of the edge
+        let shared = self.edge_triangles(a, b);
+        let neighbors_b = self.neighbors(b);
+        let common = self.neighbors(a).into_iter().filter(|n| neighbors_b.contains(n)).count();
+        if shared.is_empty() || common != shared.len() {
+            return false;
+        }
+
+        // Don't create long edges, and don't fold triangles over
+        for &v in &[a, b] {
+            for &t in &self.vertex_triangles[v as usize] {
+                if shared.contains(&t) {
+                    continue;
+                }
+                let before = self.triangle_normal(t);
+                let after = {
+                    let [p0, p1, p2] = self.triangles[t as usize].map(|w| if w == a || w == b { target } else { self.positions[w as usize] });
+                    (p1 - p0).cross(&(p2 - p0))
+                };
+                if after.dot(&before) <= 0.0 || after.magnitude_squared() < 1e-6 * before.magnitude_squared() {
+                    return false;
+                }
+            }
+        }
+        if self.neighbors(a).iter().any(|n| (self.positions[*n as usize] - target).magnitude() > max_length) {
+            return false;
+        }
+
+        for &t in &shared {
+            self.is_triangle_removed[t as usize] = true;
+            for w in self.triangles[t as usize] {
+                self.vertex_triangles[w as usize].retain(|u| *u != t);
+            }
+        }
+        for n in self.neighbors(a) {
+            if self.remove_feature_edge(adjacency::edge_key(a, n)) && n != b {
+                self.add_feature_edge(adjacency::edge_key(b, n));
+            }
+        }
+        for t in std::mem::take(&mut self.vertex_triangles[a as usize]) {
+            for w in self.triangles[t as usize].iter_mut() {
+                if *w == a {
+                    *w = b;
+                }
+            }
+            self.vertex_triangles[b as usize].push(t);
+        }
+        self.positions[b as usize] = target;
+        true
+    }
+
+    fn equalize_valences(&mut self) {
+        let target_valence = |state: &RemeshState, v: u32| if state.is_boundary(v) { 4 } else { 6 };
+        for (a, b) in self.edges() {
+            if self.feature_edges.contains(&adjacency::edge_key(a, b)) {
+                continue;
+            }
+            let shared = self.edge_triangles(a, b);
+            if shared.len() != 2 || self.groups[shared[0] as usize] != self.groups[shared[1] as usize] {
+                continue;
+            }
+            let (t0, t1) = (shared[0], shared[1]);
+            let opposite = |t: u32| *self.triangles[t as usize].iter().find(|v| **v != a && **v != b).unwrap();
+            let (c, d) = (opposite(t0), opposite(t1));
+            if c == d || self.neighbors(c).contains(&d) {
+                continue;
+            }
+
+            let deviation = |state: &RemeshState, vertices: [(u32, i32); 4]| -> i32 {
+                vertices.iter()
+                    .map(|(v, offset)| (state.neighbors(*v).len() as i32 + offset - target_valence(state, *v)).pow(2))
+                    .sum()
+            };
+            let before = deviation(self, [(a, 0), (b, 0), (c, 0), (d, 0)]);
+            let after = deviation(self, [(a, -1), (b, -1), (c, 1), (d, 1)]);
+            if after >= before {
+                continue;
+            }
+
+            // Orient the new triangles like the current ones: t0 = (a, b, c), t1 = (b, a, d)
+            let (a, b) = {
+                let triangle = self.triangles[t0 as usize];
+                let k = triangle.iter().position(|v| *v == c).unwrap();
+                (triangle[(k + 1) % 3], triangle[(k + 2) % 3])
+            };
+            let n = self.triangle_normal(t0) + self.triangle_normal(t1);
+            let p = |v: u32| self.positions[v as usize];
+            let n0 = (p(d) - p(a)).cross(&(p(c) - p(a)));
+            let n1 = (p(b) - p(d)).cross(&(p(c) - p(d)));
+            if n0.dot(&n) <= 0.0 || n1.dot(&n) <= 0.0 {
+                continue;
+            }
+
+            self.triangles[t0 as usize] = [a, d, c];
+            self.triangles[t1 as usize] = [d, b, c];
+            self.vertex_triangles[a as usize].retain(|t| *t != t1);
+            self.vertex_triangles[b as usize].retain(|t| *t != t0);
+            self.vertex_triangles[c as usize].push(t1);
+            self.vertex_triangles[d as usize].push(t0);
+        }
+    }
+
+    fn relax(&mut self, original: &Bvh) {
+        let mut positions = self.positions.clone();
+        for v in 0..self.positions.len() {
+            if self.vertex_triangles[v].is_empty() || self.feature_degrees[v] > 0 || self.is_locked[v] {
+                continue;
+            }
+            let neighbors = self.neighbors(v as u32);
+            let centroid = neighbors.iter().map(|n| self.positions[*n as usize]).sum::<DVec3>() / neighbors.len() as f64;
+            let normal = self.vertex_normal(v as u32);
+            let p = self.positions[v];
+            let moved = p + (centroid - p) - normal * normal.dot(&(centroid - p));
+            positions[v] = original.closest_point(&moved, f64::MAX).map_or(moved, |closest| closest.point);
+        }
+        self.positions = positions;
+    }
+
+    // Interpolates normals, colors and texture coordinates of the original mesh at each corner. Corners are sampled slightly
+    // inside their triangle, so that each side of a seam picks attributes from its own side.
+    fn resample_attributes(&self, mesh: &mut PolygonMesh, original: &Bvh) -> Vec<Polygon> {
+        let mut normals = mesh.normals.as_ref().map(|_| Vec::new());
+        let mut colors = mesh.colors.as_ref().map(|_| Vec::new());
+        let mut uvs = mesh.uvs.as_ref().map(|_| Vec::new());
+        let mut normal_indices = vec![Vec::<u32>::new(); self.positions.len()];
+        let mut color_indices = vec![Vec::<u32>::new(); self.positions.len()];
+        let mut uv_indices = vec![Vec::<u32>::new(); self.positions.len()];
+
+        let mut polygons = Vec::new();
+        for (t, triangle) in self.triangles.iter().enumerate() {
+            if self.is_triangle_removed[t] {
+                continue;
+            }
+            let centroid = triangle.iter().map(|v| self.positions[*v as usize]).sum::<DVec3>() / 3.0;
+            let mut corners = Vec::with_capacity(3);
+            for &v in triangle {
+                let p = self.positions[v as usize];
+                let closest = original.closest_point(&(p + (centroid - p) * 1e-3), f64::MAX);
+                let sample = |source: &Option<Vec<DVec3>>, target: &mut Option<Vec<DVec3>>, indices: &mut Vec<Vec<u32>>, channel: fn(&Corner) -> u32, normalize: bool| -> u32 {
+                    let (source, target, closest) = match (source, target, &closest) {
+                        (Some(source), Some(target), Some(closest)) => (source, target, closest),
+                        _ => return 0,
+                    };
+                    let corners = &mesh.polygons[closest.triangle as usize].corners;
+                    let attribute = |k: usize| source[channel(&corners[k]) as usize];
+                    let mut value = attribute(0) * closest.barycentric.x + attribute(1) * closest.barycentric.y + attribute(2) * closest.barycentric.z;
+                    if normalize && value.magnitude_squared() > 0.0 {
+                        value = value.normalize();
+                    }
+                    let indices = &mut indices[v as usize];
+                    if let Some(i) = indices.iter().find(|i| (target[**i as usize] - value).magnitude() < 1e-9) {
+                        return *i;
+                    }
+                    target.push(value);
+                    indices.push(target.len() as u32 - 1);
+                    target.len() as u32 - 1
+                };
+                let normal = sample(&mesh.normals, &mut normals, &mut normal_indices, |corner| corner.normal, true);
+                let color = sample(&mesh.colors, &mut colors, &mut color_indices, |corner| corner.color, false);
+                let uv = sample(&mesh.uvs, &mut uvs, &mut uv_indices, |corner| corner.uv, false);
+                corners.push(Corner { position: v, normal, color, uv });
+            }
+            polygons.push(Polygon { corners, group: self.groups[t] });
+        }
+
+        mesh.normals = normals;
+        mesh.colors = colors;
+        mesh.uvs = uvs;
+        polygons
+    }
+}
+
+#[cfg(test)]
+mod remesh_tests {
+    use super::*;
+    use super::test_meshes::*;
+
+    #[test]
+    fn remesh_cube() {
+        let mesh = cube(DVec3::zeros(), 2.0);
+        let mut connected_mesh = ConnectedMesh::from(&mesh);
+        connected_mesh.remesh(&RemeshOptions { target_edge_length: 0.25, ..Default::default() });
+        let result = SharedMesh::from(&connected_mesh);
+
+        // Sharp edges and corners are kept, so is the volume
+        assert!(result.triangles.len() > 200);
+        assert!((result.volume() - 8.0).abs() < 1e-6);
+        assert!(result.positions.iter().all(|p| (p.abs().max() - 1.0).abs() < 1e-9));
+        for corner in &mesh.positions {
+            assert!(result.positions.iter().any(|p| p == corner));
+        }
+
+        // Edges are close to the target length
+        let mut lengths = Vec::new();
+        for t in &result.triangles {
+            for k in 0..3 {
+                lengths.push((result.positions[t[k] as usize] - result.positions[t[(k + 1) % 3] as usize]).magnitude());
+            }
+        }
+        let mean = lengths.iter().sum::<f64>() / lengths.len() as f64;
+        assert!(mean > 0.15 && mean < 0.35);
+        assert!(lengths.iter().all(|l| *l < 0.25 * 2.0));
+    }
+
+    #[test]
+    fn remesh_sphere_keeps_normals() {
+        let mesh = sphere(DVec3::zeros(), 1.0, 3);
+        let mut connected_mesh = ConnectedMesh::from(&mesh);
+        connected_mesh.remesh(&RemeshOptions { target_edge_length: 0.3, ..Default::default() });
+        let result = SharedMesh::from(&connected_mesh);
+
+        assert!(result.triangles.len() < mesh.triangles.len());
+        let normals = result.normals.as_ref().unwrap();
+        for (p, n) in result.positions.iter().zip(normals) {
+            assert!((p.magnitude() - 1.0).abs() < 0.02);
+            assert!(p.normalize().dot(n) > 0.99);
+        }
+    }
+
+    #[test]
+    fn remesh_slivers() {
+        // Long strip split into two sliver triangles, as produced by a CDT
+        let mesh = SharedMesh {
+            groups: Vec::new(),
+            triangles: vec![glm::U32Vec3::new(0, 1, 2), glm::U32Vec3::new(0, 2, 3)],
+            positions: vec![DVec3::new(0., 0., 0.), DVec3::new(8., 0., 0.), DVec3::new(8., 0.5, 0.), DVec3::new(0., 0.5, 0.)],
+            normals: None,
+            colors: None,
+            uvs: None,
+        };
+        let mut connected_mesh = ConnectedMesh::from(&mesh);
+        connected_mesh.remesh(&RemeshOptions { target_edge_length: 0.1, ..Default::default() });
+        let result = SharedMesh::from(&connected_mesh);
+
+        assert!((result.surface_area() - 4.0).abs() < 1e-9);
+        let mut min_angle = f64::MAX;
+        for t in &result.triangles {
+            for k in 0..3 {
+                let p = result.positions[t[k] as usize];
+                let u = (result.positions[t[(k + 1) % 3] as usize] - p).normalize();
+                let v = (result.positions[t[(k + 2) % 3] as usize] - p).normalize();
+                min_angle = min_angle.min(u.dot(&v).clamp(-1.0, 1.0).acos());
+            }
+        }
+        assert!(min_angle > 15f64.to_radians());
+    }
+}
@@ -1,27 +1,116 @@
 use nalgebra_glm as glm;
-use glm::{DVec3, U32Vec3};
+use glm::{DVec2, DVec3, U32Vec3};
 use super::Group;
 use std::convert::TryInto;
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SharedMesh {
     pub groups: Vec<Group>,
     pub triangles: Vec<U32Vec3>,
     pub positions: Vec<DVec3>,
     pub normals: Option<Vec<DVec3>>,
     pub colors: Option<Vec<DVec3>>,
+    /// Texture coordinates, as generated by `unwrap_uvs`
+    pub uvs: Option<Vec<DVec2>>,
 }
 
 impl SharedMesh {
     // Combine two triangulations with an associative binary operator
-    // (why yes, this _is_ a monoid)
+    // (why yes, this _is_ a monoid). Attributes are kept if both have them.
     pub fn combine(mut a: Self, b: Self) -> Self {
+        fn concat<T>(a: Option<Vec<T>>, b: Option<Vec<T>>) -> Option<Vec<T>> {
+            match (a, b) {
+                (Some(mut a), Some(b)) => { a.extend(b); Some(a) }
+                _ => None,
+            }
+        }
+
         let dv: u32 = a.positions.len().try_into()
             .expect("Cannot handle more than 4,294,967,295 triangles");
+        let di = a.triangles.len() as u32 * 3;
+        a.groups.extend(b.groups.into_iter()
+            .map(|g| Group::new(g.first_index + di, g.index_count)));
         a.positions.extend(b.positions);
         a.triangles.extend(b.triangles.into_iter()
             .map(|t| U32Vec3::new(t[0] + dv, t[1] + dv, t[2] + dv)));
+        a.normals = concat(a.normals, b.normals);
+        a.colors = concat(a.colors, b.colors);
+        a.uvs = concat(a.uvs, b.uvs);
         a
     }
+
+    /// Area of the triangle at the given index
+    pub fn triangle_area(&self, triangle_index: usize) -> f64 {
+        let t = &self.triangles[triangle_index];
+        let a = &self.positions[t[0] as usize];
+        let b = &self.positions[t[1] as usize];
+        let c = &self.positions[t[2] as usize];
+        (b - a).cross(&(c - a)).magnitude() / 2.0
+    }
+
+    /// Index of the group each triangle belongs to, or u32::MAX for triangles outside of any group
+    pub fn triangle_groups(&self) -> Vec<u32> {
+        let mut triangle_groups = vec![u32::MAX; self.triangles.len()];
+        for (i, group) in self.groups.iter().enumerate() {
+            for t in group.triangles() {
+                triangle_groups[t] = i as u32;
+            }
+        }
+        triangle_groups
+    }
+
+    /// Builds a new mesh out of the triangles for which `keep` is true.
+    /// Unused vertices are dropped, attributes and groups are carried over.
+    pub fn submesh(&self, keep: &[bool]) -> SharedMesh {
+        debug_assert!(keep.len() == self.triangles.len());
+
+        let triangle_groups = self.triangle_groups();
+        let mut remap = vec![u32::MAX; self.positions.len()];
+        let mut vertices = Vec::new();
+        let mut triangles = Vec::new();
+        let mut groups = Vec::<Group>::new();
+        let mut last_group = u32::MAX;
+
+        for (i, t) in self.triangles.iter().enumerate() {
+            if !keep[i] {
+                continue;
+            }
+            let mut triangle = U32Vec3::default();
+            for k in 0..3 {
+                let v = t[k] as usize;
+                if remap[v] == u32::MAX {
+                    remap[v] = vertices.len() as u32;
+                    vertices.push(v);
+                }
+                triangle[k] = remap[v];
+            }
+            if triangle_groups[i] != u32::MAX {
+                if triangle_groups[i] != last_group {
+                    groups.push(Group::new(triangles.len() as u32 * 3, 0));
+                    last_group = triangle_groups[i];
+                }
+                groups.last_mut().unwrap().index_count += 3;
+            } else {
+                last_group = u32::MAX;
+            }
+            triangles.push(triangle);
+        }
+
+        SharedMesh {
+            groups,
+            triangles,
+            positions: vertices.iter().map(|v| self.positions[*v]).collect(),
+            normals: self.normals.as_ref()
+                .filter(|normals| normals.len() == self.positions.len())
+                .map(|normals| vertices.iter().map(|v| normals[*v]).collect()),
+            colors: self.colors.as_ref()
+                .filter(|colors| colors.len() == self.positions.len())
+                .map(|colors| vertices.iter().map(|v| colors[*v]).collect()),
+            uvs: self.uvs.as_ref()
+                .filter(|uvs| uvs.len() == self.positions.len())
+                .map(|uvs| vertices.iter().map(|v| uvs[*v]).collect()),
+        }
+    }
 }
 
 impl Default for SharedMesh {
@@ -32,6 +121,7 @@ impl Default for SharedMesh {
             positions: Vec::new(),
             normals: Some(Vec::new()),
             colors: Some(Vec::new()),
+            uvs: None,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,405 @@
+// Intersection of a mesh with a plane: cross-section polylines, and cutting into two capped halves.
+// Vertices lying exactly on the plane are considered above it, so that every crossing is well defined.
+
+use nalgebra_glm as glm;
+use glm::{DVec2, DVec3, U32Vec3};
+use hashbrown::HashMap;
+use std::hash::BuildHasherDefault;
+use super::{SharedMesh, Group, adjacency};
+use super::super::base::Plane;
+use super::super::utils::SimpleHasher;
+
+#[derive(Debug, Clone)]
+pub struct Polyline {
+    pub points: Vec<DVec3>,
+    /// Closed polylines don't repeat their first point
+    pub is_closed: bool,
+}
+
+impl Polyline {
+    /// Signed area enclosed by the polyline, seen from the side the plane normal points to.
+    /// Outer contours of a closed, outward oriented mesh are positive and holes negative.
+    pub fn signed_area(&self, plane: &Plane) -> f64 {
+        let mut area = DVec3::zeros();
+        for i in 0..self.points.len() {
+            area += self.points[i].cross(&self.points[(i + 1) % self.points.len()]);
+        }
+        plane.normal.dot(&area) / 2.0
+    }
+}
+
+// Where an edge crosses the plane. Crossings at a vertex are shared by all edges of that vertex.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum Crossing {
+    Vertex(u32),
+    Edge(u32, u32),
+}
+
+type CrossingMap<T> = HashMap<u64, T, BuildHasherDefault<SimpleHasher>>;
+
+impl Crossing {
+    fn key(&self) -> u64 {
+        match *self {
+            Crossing::Vertex(v) => u64::MAX - 1 - v as u64,
+            Crossing::Edge(a, b) => adjacency::edge_key(a, b),
+        }
+    }
+}
+
+struct Section {
+    distances: Vec<f64>,
+    // Welded vertex of each vertex, so that crossings are shared through attribute seams
+    welded: Vec<u32>,
+}
+
+impl Section {
+    fn new(mesh: &SharedMesh, plane: &Plane) -> Self {
+        Section {
+            distances: mesh.positions.iter().map(|p| plane.signed_distance(p)).collect(),
+            welded: adjacency::weld_positions(&mesh.positions),
+        }
+    }
+
+    fn is_above(&self, v: u32) -> bool {
+        self.distances[v as usize] >= 0.0
+    }
+
+    // Interpolation parameter from `above` to `below`, and the crossing identity
+    fn crossing(&self, above: u32, below: u32) -> (f64, Crossing) {
+        let da = self.distances[above as usize];
+        let db = self.distances[below as usize];
+        let crossing = if da == 0.0 {
+            Crossing::Vertex(self.welded[above as usize])
+        } else {
+            Crossing::Edge(self.welded[above as usize], self.welded[below as usize])
+        };
+        (da / (da - db), crossing)
+    }
+
+    // Crossing segment of a triangle, oriented so that closed outward meshes give counterclockwise outer contours
+    fn segment(&self, mesh: &SharedMesh, plane: &Plane, t: &U32Vec3) -> Option<[(Crossing, DVec3); 2]> {
+        let above: Vec<bool> = (0..3).map(|k| self.is_above(t[k])).collect();
+        if above.iter().all(|a| *a) || above.iter().all(|a| !*a) {
+            return None;
+        }
+
+        let mut crossings = Vec::with_capacity(2);
+        for k in 0..3 {
+            let (a, b) = (t[k], t[(k + 1) % 3]);
+            if above[k] != above[(k + 1) % 3] {
+                let (above_vertex, below_vertex) = if above[k] { (a, b) } else { (b, a) };
+                let (s, crossing) = self.crossing(above_vertex, below_vertex);
+                let p = mesh.positions[above_vertex as usize].lerp(&mesh.positions[below_vertex as usize], s);
+                crossings.push((crossing, p));
+            }
+        }
+        if crossings[0].0 == crossings[1].0 {
+            return None;
+        }
+
+        let [a, b, c] = [0, 1, 2].map(|k| mesh.positions[t[k] as usize]);
+        let normal = (b - a).cross(&(c - a));
+        let direction = plane.normal.cross(&normal);
+        if (crossings[1].1 - crossings[0].1).dot(&direction) < 0.0 {
+            crossings.swap(0, 1);
+        }
+        Some([crossings[0], crossings[1]])
+    }
+}
+
+impl SharedMesh {
+    /// Cross-section of the mesh by a plane, as polylines made of chained triangle / plane intersections.
+    /// Closed and consistently oriented meshes give closed polylines, counterclockwise around the
+    /// plane normal for outer contours and clockwise for holes.
+    pub fn slice(&self, plane: &Plane) -> Vec<Polyline> {
+        let section = Section::new(self, plane);
+        let mut next = CrossingMap::<(u64, DVec3)>::default();
+        let mut has_previous = CrossingMap::<bool>::default();
+        for t in &self.triangles {
+            if let Some([from, to]) = section.segment(self, plane, t) {
+                next.insert(from.0.key(), (to.0.key(), from.1));
+                has_previous.insert(to.0.key(), true);
+                next.entry(to.0.key()).or_insert((u64::MAX, to.1));
+            }
+        }
+
+        // Open chains start where no segment ends, then what remains are loops.
+        // Keys are sorted for deterministic results.
+        let mut starts: Vec<u64> = next.keys().copied().collect();
+        starts.sort_unstable();
+        starts.sort_by_key(|key| has_previous.contains_key(key));
+
+        let mut polylines = Vec::new();
+        let mut is_visited = CrossingMap::<bool>::default();
+        for start in starts {
+            if is_visited.contains_key(&start) {
+                continue;
+            }
+            let mut points = Vec::new();
+            let mut key = start;
+            let mut is_closed = false;
+            while let Some((following, point)) = next.get(&key) {
+                is_visited.insert(key, true);
+                points.push(*point);
+                key = *following;
+                if key == start {
+                    is_closed = true;
+                    break;
+                }
+                if is_visited.contains_key(&key) {
+                    break;
+                }
+            }
+            if points.len() > 1 {
+                polylines.push(Polyline { points, is_closed });
+            }
+        }
+        polylines
+    }
+
+    /// Cuts the mesh in two halves: above (on the side the plane normal points to) and below the plane.
+    /// Normals, colors and texture coordinates are interpolated at cut vertices.
+    /// When `cap` is true, closed cross-sections are triangulated to close both halves (cap vertices are white,
+    /// with null texture coordinates).
+    pub fn split_by_plane(&self, plane: &Plane, cap: bool) -> Result<(SharedMesh, SharedMesh), cdt::Error> {
+        let section = Section::new(self, plane);
+        let triangle_groups = self.triangle_groups();
+        let normals = self.normals.as_ref().filter(|normals| normals.len() == self.positions.len());
+        let colors = self.colors.as_ref().filter(|colors| colors.len() == self.positions.len());
+        let uvs = self.uvs.as_ref().filter(|uvs| uvs.len() == self.positions.len());
+
+        // New vertices on cut edges, shared by both halves. Keyed by unwelded vertices to keep attributes.
+        let mut positions = self.positions.clone();
+        let mut new_normals = normals.cloned();
+        let mut new_colors = colors.cloned();
+        let mut new_uvs = uvs.cloned();
+        let mut cut_vertices = CrossingMap::<u32>::default();
+        let mut cut_vertex = |above: u32, below: u32| -> u32 {
+            let (s, crossing) = section.crossing(above, below);
+            if let Crossing::Vertex(_) = crossing {
+                return above;
+            }
+            *cut_vertices.entry(adjacency::edge_key(above, below)).or_insert_with(|| {
+                positions.push(positions[above as usize].lerp(&positions[below as usize], s));
+                if let Some(normals) = new_normals.as_mut() {
+                    let normal = normals[above as usize].lerp(&normals[below as usize], s);
+                    normals.push(if normal.magnitude_squared() > 0.0 { normal.normalize() } else { normal });
+                }
+                if let Some(colors) = new_colors.as_mut() {
+                    colors.push(colors[above as usize].lerp(&colors[below as usize], s));
+                }
+                if let Some(uvs) = new_uvs.as_mut() {
+                    uvs.push(uvs[above as usize].lerp(&uvs[below as usize], s));
+                }
+                positions.len() as u32 - 1
+            })
+        };
+
+        let mut halves = [Vec::<(U32Vec3, u32)>::new(), Vec::new()];
+        for (i, t) in self.triangles.iter().enumerate() {
+            let group = triangle_groups[i];
+            let above: Vec<bool> = (0..3).map(|k| section.is_above(t[k])).collect();
+            let count = above.iter().filter(|a| **a).count();
+            if count == 3 || count == 0 {
+                halves[if count == 3 { 0 } else { 1 }].push((*t, group));
+                continue;
+            }
+
+            // Rotate so that the lone vertex comes first
+            let lone_is_above = count == 1;
+            let k = (0..3).find(|k| above[*k] == lone_is_above).unwrap();
+            let (a, b, c) = (t[k], t[(k + 1) % 3], t[(k + 2) % 3]);
+            let (ab, ac) = if lone_is_above { (cut_vertex(a, b), cut_vertex(a, c)) } else { (cut_vertex(b, a), cut_vertex(c, a)) };
+
+            let (lone_half, other_half) = if lone_is_above { (0, 1) } else { (1, 0) };
+            halves[lone_half].push((U32Vec3::new(a, ab, ac), group));
+            halves[other_half].push((U32Vec3::new(ab, b, c), group));
+            halves[other_half].push((U32Vec3::new(ab, c, ac), group));
+        }
+
+        let mut meshes = [(); 2].map(|_| SharedMesh {
+            groups: Vec::new(),
+            triangles: Vec::new(),
+            positions: positions.clone(),
+            normals: new_normals.clone(),
+            colors: new_colors.clone(),
+            uvs: new_uvs.clone(),
+        });
+
+        if cap {
+            let caps = self.caps(plane)?;
+            for (h, mesh) in meshes.iter_mut().enumerate() {
+                // The cap closes the half below with a normal along the plane normal, and conversely
+                let normal = if h == 0 { -plane.normal } else { plane.normal };
+                let first = mesh.positions.len() as u32;
+                mesh.positions.extend_from_slice(&caps.0);
+                if let Some(normals) = mesh.normals.as_mut() {
+                    normals.extend(caps.0.iter().map(|_| normal));
+                }
+                if let Some(colors) = mesh.colors.as_mut() {
+                    colors.extend(caps.0.iter().map(|_| DVec3::new(1., 1., 1.)));
+                }
+                if let Some(uvs) = mesh.uvs.as_mut() {
+                    uvs.extend(caps.0.iter().map(|_| DVec2::zeros()));
+                }
+                for triangle in &caps.1 {
+                    let t = triangle.add_scalar(first);
+                    let t = if h == 0 { U32Vec3::new(t[0], t[2], t[1]) } else { t };
+                    // Caps get their own group when the mesh has groups
+                    halves[h].push((t, if self.groups.is_empty() { u32::MAX } else { self.groups.len() as u32 }));
+                }
+            }
+        }
+
+        for (h, mesh) in meshes.iter_mut().enumerate() {
+            let mut last_group = u32::MAX;
+            for (t, group) in &halves[h] {
+                if *group != u32::MAX {
+                    if *group != last_group {
+                        mesh.groups.push(Group::new(mesh.triangles.len() as u32 * 3, 0));
+                        last_group = *group;
+                    }
+                    mesh.groups.last_mut().unwrap().index_count += 3;
+                } else {
+                    last_group = u32::MAX;
+                }
+                mesh.triangles.push(*t);
+            }
+            let keep = vec![true; mesh.triangles.len()];
+            *mesh = mesh.submesh(&keep);
+        }
+
+        let [above, below] = meshes;
+        Ok((above, below))
+    }
+
+    // Triangulation of the closed cross-sections, oriented along the plane normal
+    fn caps(&self, plane: &Plane) -> Result<(Vec<DVec3>, Vec<U32Vec3>), cdt::Error> {
+        let polylines: Vec<Polyline> = self.slice(plane).into_iter().filter(|p| p.is_closed && p.points.len() > 2).collect();
+        if polylines.is_empty() {
+            return Ok((Vec::new(), Vec::new()));
+        }
+
+        let (u, v) = plane.basis();
+        let mut points = Vec::new();
+        let mut points_2d = Vec::new();
+        let mut contours = Vec::new();
+        for polyline in &polylines {
+            let first = points.len();
+            let mut contour: Vec<usize> = (first..first + polyline.points.len()).collect();
+            contour.push(first);
+            contours.push(contour);
+            for p in &polyline.points {
+                points.push(*p);
+                points_2d.push((p.dot(&u), p.dot(&v)));
+            }
+        }
+
+        let triangles = cdt::triangulate_contours(&points_2d, &contours)?
+            .into_iter()
+            .map(|(a, b, c)| {
+                let (pa, pb, pc) = (points_2d[a], points_2d[b], points_2d[c]);
+                let area = (pb.0 - pa.0) * (pc.1 - pa.1) - (pb.1 - pa.1) * (pc.0 - pa.0);
+                if area >= 0.0 { U32Vec3::new(a as u32, b as u32, c as u32) } else { U32Vec3::new(a as u32, c as u32, b as u32) }
+            })
+            .collect();
+        Ok((points, triangles))
+    }
+}
+
+#[cfg(test)]
+mod slice_tests {
+    use super::*;
+    use super::super::test_meshes::*;
+
+    #[test]
+    fn slice_cube() {
+        let mesh = cube(DVec3::zeros(), 2.0);
+        let plane = Plane::new(&DVec3::new(0., 0., 0.25), &DVec3::z());
+        let polylines = mesh.slice(&plane);
+
+        assert_eq!(polylines.len(), 1);
+        assert!(polylines[0].is_closed);
+        assert!(polylines[0].points.iter().all(|p| (p.z - 0.25).abs() < 1e-12));
+        assert!((polylines[0].signed_area(&plane) - 4.0).abs() < 1e-9);
+
+        // Seen from the other side, the contour is clockwise
+        let flipped = Plane::new(&DVec3::new(0., 0., 0.25), &-DVec3::z());
+        assert!((mesh.slice(&flipped)[0].signed_area(&flipped) - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn slice_through_vertices() {
+        // The diagonal plane goes through the two vertical edges at (-1, -1) and (1, 1): the section is a 2√2 by 2 rectangle
+        let mesh = cube(DVec3::zeros(), 2.0);
+        let plane = Plane::new(&DVec3::zeros(), &DVec3::new(1., -1., 0.).normalize());
+        let polylines = mesh.slice(&plane);
+        assert_eq!(polylines.len(), 1);
+        let polyline = &polylines[0];
+        assert!(polyline.is_closed);
+        assert!(polyline.points.len() >= 4 && polyline.points.len() <= 6);
+        assert!(polyline.points.iter().all(|q| (q.x - q.y).abs() < 1e-12));
+
+        let perimeter: f64 = (0..polyline.points.len())
+            .map(|i| (polyline.points[(i + 1) % polyline.points.len()] - polyline.points[i]).magnitude())
+            .sum();
+        assert!((perimeter - (4.0 + 4.0 * 2f64.sqrt())).abs() < 1e-9);
+        assert!((polyline.signed_area(&plane).abs() - 4.0 * 2f64.sqrt()).abs() < 1e-9);
+
+        // Vertices on the plane are above it: a plane touching the bottom face doesn't cross the cube
+        let plane = Plane::new(&DVec3::new(0., 0., -1.), &DVec3::z());
+        assert!(mesh.slice(&plane).is_empty());
+    }
+
+    #[test]
+    fn attributes_are_interpolated_at_cuts() {
+        // Texture coordinates and colors varying linearly with positions stay linear after cutting
+        let mut mesh = cube(DVec3::zeros(), 2.0);
+        mesh.uvs = Some(mesh.positions.iter().map(|p| DVec2::new(p.x, p.y + p.z)).collect());
+        mesh.colors = Some(mesh.positions.iter().map(|p| (p + DVec3::repeat(1.0)) / 2.0).collect());
+        let plane = Plane::new(&DVec3::new(0.1, 0., 0.), &DVec3::new(1., 0.2, 0.1));
+
+        let (above, below) = mesh.split_by_plane(&plane, false).unwrap();
+        for half in [&above, &below] {
+            let uvs = half.uvs.as_ref().unwrap();
+            let colors = half.colors.as_ref().unwrap();
+            assert_eq!(uvs.len(), half.positions.len());
+            for ((p, uv), color) in half.positions.iter().zip(uvs).zip(colors) {
+                assert!((uv - DVec2::new(p.x, p.y + p.z)).magnitude() < 1e-9);
+                assert!((color - (p + DVec3::repeat(1.0)) / 2.0).magnitude() < 1e-9);
+            }
+        }
+
+        let (above, _) = mesh.split_by_plane(&plane, true).unwrap();
+        assert_eq!(above.uvs.as_ref().unwrap().len(), above.positions.len());
+    }
+
+    #[test]
+    fn hollow_section_and_capped_halves() {
+        // A cube with an inner, inward facing cube: the section is a square ring
+        let outer = cube(DVec3::zeros(), 2.0);
+        let mut inner = cube(DVec3::zeros(), 1.0);
+        for t in inner.triangles.iter_mut() {
+            t.swap_rows(1, 2);
+        }
+        let mesh = SharedMesh::combine(outer, inner);
+        let plane = Plane::new(&DVec3::new(0.1, 0., 0.), &DVec3::new(1., 0.2, 0.1));
+
+        let polylines = mesh.slice(&plane);
+        assert_eq!(polylines.len(), 2);
+        assert!(polylines.iter().all(|p| p.is_closed));
+        let areas: Vec<f64> = polylines.iter().map(|p| p.signed_area(&plane)).collect();
+        assert!(areas.iter().any(|a| *a > 0.0) && areas.iter().any(|a| *a < 0.0));
+
+        let (above, below) = mesh.split_by_plane(&plane, true).unwrap();
+        let volume = mesh.volume();
+        assert!((above.volume() + below.volume() - volume).abs() < 1e-9);
+        assert!(above.volume() > 0.0 && below.volume() > 0.0);
+        // Each half is closed: every edge is shared by exactly two triangles
+        for half in [&above, &below] {
+            let welded = adjacency::weld_positions(&half.positions);
+            let edges = adjacency::edge_faces(&half.triangles, &welded);
+            assert!(edges.values().all(|faces| faces.len() == 2));
+        }
+    }
+}
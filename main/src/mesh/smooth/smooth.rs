@@ -0,0 +1,466 @@
+// Smoothing operators, working on the one-ring of each position (siblings and their relatives)
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SmoothingMethod {
+    /// Moves each vertex toward the average of its neighbors. Shrinks the mesh.
+    Laplacian { lambda: f64 },
+    /// Laplacian with cotangent weights, which depends much less on the triangulation
+    Cotangent { lambda: f64 },
+    /// Alternates a shrinking (`lambda` > 0) and an inflating (`mu` < -`lambda`) step, preserving the volume
+    /// ("A Signal Processing Approach To Fair Surface Design", Taubin, 1995)
+    Taubin { lambda: f64, mu: f64 },
+    /// Filters face normals while preserving sharp features, then moves vertices to fit the filtered normals
+    /// ("Bilateral Normal Filtering for Mesh Denoising", Zheng et al., 2011).
+    /// `normal_sigma` is the range of normal differences considered as noise (about 0.3 to 0.6).
+    Bilateral { normal_sigma: f64, vertex_iterations: u32 },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BoundaryHandling {
+    /// Boundary vertices don't move
+    Fixed,
+    /// Boundary vertices are smoothed along the boundary only, which keeps the outline in place
+    AlongBoundary,
+    /// Boundary vertices are smoothed like the others
+    Free,
+}
+
+#[derive(Debug, Clone)]
+pub struct SmoothingOptions {
+    pub method: SmoothingMethod,
+    pub iterations: u32,
+    pub boundary: BoundaryHandling,
+    /// Vertices (as `VertexId`, positions of the `ConnectedMesh`) that must not move. Missing vertices are ignored.
+    pub pinned: Vec<VertexId>,
+}
+
+impl Default for SmoothingOptions {
+    fn default() -> Self {
+        SmoothingOptions {
+            method: SmoothingMethod::Taubin { lambda: 0.5, mu: -0.53 },
+            iterations: 10,
+            boundary: BoundaryHandling::Fixed,
+            pinned: Vec::new(),
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+struct OneRing {
+    // Next and previous positions of each face around the vertex
+    faces: Vec<(u32, u32)>,
+    neighbors: Vec<u32>,
+    // Neighbors along a boundary edge (an edge with a single face)
+    boundary_neighbors: Vec<u32>,
+}
+
+impl ConnectedMesh {
+    pub fn smooth(&mut self, options: &SmoothingOptions) {
+        let rings = self.one_rings();
+        let mut is_movable: Vec<bool> = rings.iter().map(|ring| !ring.faces.is_empty()).collect();
+        for pinned in &options.pinned {
+            if let Some(is_movable) = is_movable.get_mut(pinned.0 as usize) {
+                *is_movable = false;
+            }
+        }
+
+        for _ in 0..options.iterations {
+            match options.method {
+                SmoothingMethod::Laplacian { lambda } => {
+                    self.laplacian_step(&rings, &is_movable, options.boundary, lambda, false);
+                }
+                SmoothingMethod::Cotangent { lambda } => {
+                    self.laplacian_step(&rings, &is_movable, options.boundary, lambda, true);
+                }
+                SmoothingMethod::Taubin { lambda, mu } => {
+                    self.laplacian_step(&rings, &is_movable, options.boundary, lambda, false);
+                    self.laplacian_step(&rings, &is_movable, options.boundary, mu, false);
+                }
+                SmoothingMethod::Bilateral { normal_sigma, vertex_iterations } => {
+                    self.bilateral_step(&rings, &is_movable, options.boundary, normal_sigma, vertex_iterations);
+                }
+            }
+        }
+
+        if options.iterations > 0 {
+            self.update_normals(&is_movable);
+        }
+    }
+
+    // Recomputes the normals of faces around movable vertices, as the area weighted average of the faces using them.
+    // Normals split along hard edges stay split, since each one only averages the faces referencing it.
+    fn update_normals(&mut self, is_movable: &[bool]) {
+        let normal_count = match &self.normals {
+            Some(normals) => normals.len(),
+            None => return,
+        };
+
+        let mut is_visited = vec![false; self.nodes.len()];
+        let mut faces = Vec::with_capacity(self.face_count as usize);
+        for i in 0..self.nodes.len() {
+            if self.nodes[i].is_removed || is_visited[i] {
+                continue;
+            }
+            let mut face = [0u32; 3];
+            let mut k = 0;
+            loop_relatives!(i as u32, self.nodes, relative, {
+                is_visited[relative as usize] = true;
+                if k < 3 {
+                    face[k] = relative;
+                }
+                k += 1;
+            });
+            faces.push(face);
+        }
+
+        let mut is_dirty = vec![false; normal_count];
+        for face in &faces {
+            if face.iter().any(|node| is_movable[self.nodes[*node as usize].position as usize]) {
+                for node in face {
+                    is_dirty[self.nodes[*node as usize].normal as usize] = true;
+                }
+            }
+        }
+
+        let mut sums = vec![DVec3::zeros(); normal_count];
+        for face in &faces {
+            let [a, b, c] = face.map(|node| self.positions[self.nodes[node as usize].position as usize]);
+            // Twice the area, times the unit normal
+            let weighted_normal = (b - a).cross(&(c - a));
+            for node in face {
+                sums[self.nodes[*node as usize].normal as usize] += weighted_normal;
+            }
+        }
+
+        let normals = self.normals.as_mut().unwrap();
+        for (normal, (sum, is_dirty)) in normals.iter_mut().zip(sums.iter().zip(&is_dirty)) {
+            if *is_dirty && sum.magnitude_squared() > 0.0 {
+                *normal = sum.normalize();
+            }
+        }
+    }
+
+    fn one_rings(&self) -> Vec<OneRing> {
+        let mut rings = vec![OneRing::default(); self.positions.len()];
+        let mut is_visited = vec![false; self.positions.len()];
+        for i in 0..self.nodes.len() {
+            let position = self.nodes[i].position as usize;
+            if self.nodes[i].is_removed || is_visited[position] {
+                continue;
+            }
+            is_visited[position] = true;
+
+            let ring = &mut rings[position];
+            loop_siblings!(i as u32, self.nodes, sibling, {
+                if !self.nodes[sibling as usize].is_removed {
+                    let next = self.nodes[sibling as usize].relative;
+                    let previous = self.nodes[next as usize].relative;
+                    ring.faces.push((self.nodes[next as usize].position, self.nodes[previous as usize].position));
+                }
+            });
+
+            // On a manifold, inner edges are shared by two faces of the ring: the neighbor is seen twice
+            for (next, previous) in &ring.faces {
+                for neighbor in [*next, *previous] {
+                    if !ring.neighbors.contains(&neighbor) {
+                        ring.neighbors.push(neighbor);
+                    }
+                }
+            }
+            for neighbor in &ring.neighbors {
+                let count = ring.faces.iter().filter(|(next, previous)| next == neighbor || previous == neighbor).count();
+                if count == 1 {
+                    ring.boundary_neighbors.push(*neighbor);
+                }
+            }
+        }
+        rings
+    }
+
+    fn laplacian_step(&mut self, rings: &[OneRing], is_movable: &[bool], boundary: BoundaryHandling, lambda: f64, cotangent: bool) {
+        let mut positions = self.positions.clone();
+        let mut weights = Vec::new();
+        for (i, ring) in rings.iter().enumerate() {
+            if !is_movable[i] {
+                continue;
+            }
+            let p = self.positions[i];
+
+            let is_boundary = !ring.boundary_neighbors.is_empty();
+            if is_boundary && boundary != BoundaryHandling::Free {
+                if boundary == BoundaryHandling::AlongBoundary && ring.boundary_neighbors.len() == 2 {
+                    let average = ring.boundary_neighbors.iter().map(|n| self.positions[*n as usize]).sum::<DVec3>() / 2.0;
+                    positions[i] = p + (average - p) * lambda;
+                }
+                continue;
+            }
+
+            weights.clear();
+            weights.resize(ring.neighbors.len(), if cotangent { 0.0 } else { 1.0 });
+            if cotangent {
+                for (next, previous) in &ring.faces {
+                    let a = self.positions[*next as usize];
+                    let b = self.positions[*previous as usize];
+                    // Edge (p, a) is opposite to the angle at b and edge (p, b) to the angle at a
+                    let cot_b = cotangent_at(&b, &p, &a);
+                    let cot_a = cotangent_at(&a, &p, &b);
+                    weights[ring.neighbors.iter().position(|n| n == next).unwrap()] += cot_b / 2.0;
+                    weights[ring.neighbors.iter().position(|n| n == previous).unwrap()] += cot_a / 2.0;
+                }
+                // Obtuse triangles give negative weights, which make the operator unstable
+                for weight in weights.iter_mut() {
+                    *weight = weight.max(0.0);
+                }
+            }
+
+            let total = weights.iter().sum::<f64>();
+            if total <= f64::EPSILON {
+                continue;
+            }
+            let average = ring.neighbors.iter().zip(&weights).map(|(n, w)| self.positions[*n as usize] * *w).sum::<DVec3>() / total;
+            positions[i] = p + (average - p) * lambda;
+        }
+        self.positions = positions;
+    }
+
+    fn bilateral_step(&mut self, rings: &[OneRing], is_movable: &[bool], boundary: BoundaryHandling, normal_sigma: f64, vertex_iterations: u32) {
+        let faces = self.face_positions();
+        let mut vertex_faces = vec![Vec::new(); self.positions.len()];
+        for (f, face) in faces.iter().enumerate() {
+            for v in face {
+                vertex_faces[*v as usize].push(f as u32);
+            }
+        }
+
+        let centroid = |positions: &[DVec3], face: &[u32; 3]| face.iter().map(|v| positions[*v as usize]).sum::<DVec3>() / 3.0;
+        let centroids: Vec<DVec3> = faces.iter().map(|face| centroid(&self.positions, face)).collect();
+        let areas_normals: Vec<(f64, DVec3)> = faces.iter()
+            .map(|face| {
+                let [a, b, c] = face.map(|v| self.positions[v as usize]);
+                let cross = (b - a).cross(&(c - a));
+                let length = cross.magnitude();
+                (length / 2.0, if length > 0.0 { cross / length } else { cross })
+            })
+            .collect();
+
+        // Faces sharing at least a vertex
+        let neighbor_faces = |f: usize| -> Vec<u32> {
+            let mut neighbors = Vec::new();
+            for v in &faces[f] {
+                for g in &vertex_faces[*v as usize] {
+                    if !neighbors.contains(g) {
+                        neighbors.push(*g);
+                    }
+                }
+            }
+            neighbors
+        };
+
+        // Spatial range: average distance between neighboring face centers
+        let mut distance_sum = 0.0;
+        let mut distance_count = 0;
+        for f in 0..faces.len() {
+            for g in neighbor_faces(f) {
+                distance_sum += (centroids[f] - centroids[g as usize]).magnitude();
+                distance_count += 1;
+            }
+        }
+        let spatial_sigma = (distance_sum / distance_count.max(1) as f64).max(f64::EPSILON);
+
+        let filtered: Vec<DVec3> = (0..faces.len())
+            .map(|f| {
+                let (_, normal) = areas_normals[f];
+                let mut sum = DVec3::zeros();
+                for g in neighbor_faces(f) {
+                    let (area, other_normal) = areas_normals[g as usize];
+                    let spatial = (centroids[f] - centroids[g as usize]).magnitude_squared() / (2.0 * spatial_sigma * spatial_sigma);
+                    let range = (normal - other_normal).magnitude_squared() / (2.0 * normal_sigma * normal_sigma);
+                    sum += other_normal * (area * (-spatial - range).exp());
+                }
+                if sum.magnitude_squared() > 0.0 { sum.normalize() } else { normal }
+            })
+            .collect();
+
+        // Vertex update fitting the filtered normals ("Fast and Effective Feature-Preserving Mesh Denoising", Sun et al., 2007)
+        for _ in 0..vertex_iterations {
+            let centroids: Vec<DVec3> = faces.iter().map(|face| centroid(&self.positions, face)).collect();
+            let mut positions = self.positions.clone();
+            for (i, ring) in rings.iter().enumerate() {
+                if !is_movable[i] || vertex_faces[i].is_empty() {
+                    continue;
+                }
+                let p = self.positions[i];
+                let mut displacement = vertex_faces[i].iter()
+                    .map(|f| {
+                        let normal = filtered[*f as usize];
+                        normal * normal.dot(&(centroids[*f as usize] - p))
+                    })
+                    .sum::<DVec3>() / vertex_faces[i].len() as f64;
+
+                if !ring.boundary_neighbors.is_empty() {
+                    match boundary {
+                        BoundaryHandling::Fixed => continue,
+                        BoundaryHandling::AlongBoundary if ring.boundary_neighbors.len() == 2 => {
+                            let a = self.positions[ring.boundary_neighbors[0] as usize];
+                            let b = self.positions[ring.boundary_neighbors[1] as usize];
+                            let tangent = (b - a).normalize();
+                            displacement = tangent * tangent.dot(&displacement);
+                        }
+                        BoundaryHandling::AlongBoundary => continue,
+                        BoundaryHandling::Free => {}
+                    }
+                }
+                positions[i] = p + displacement;
+            }
+            self.positions = positions;
+        }
+    }
+
+    // Positions of each (non removed) triangle
+    fn face_positions(&self) -> Vec<[u32; 3]> {
+        let mut is_visited = vec![false; self.nodes.len()];
+        let mut faces = Vec::with_capacity(self.face_count as usize);
+        for i in 0..self.nodes.len() {
+            if self.nodes[i].is_removed || is_visited[i] {
+                continue;
+            }
+            let mut face = [0; 3];
+            let mut k = 0;
+            loop_relatives!(i as u32, self.nodes, relative, {
+                is_visited[relative as usize] = true;
+                if k < 3 {
+                    face[k] = self.nodes[relative as usize].position;
+                }
+                k += 1;
+            });
+            faces.push(face);
+        }
+        faces
+    }
+}
+
+// Cotangent of the angle at `apex` in triangle (apex, a, b)
+fn cotangent_at(apex: &DVec3, a: &DVec3, b: &DVec3) -> f64 {
+    let u = a - apex;
+    let v = b - apex;
+    let sin = u.cross(&v).magnitude();
+    if sin <= f64::EPSILON { 0.0 } else { u.dot(&v) / sin }
+}
+
+#[cfg(test)]
+mod smooth_tests {
+    use super::*;
+    use super::test_meshes::*;
+
+    // Deterministic pseudo random offsets in [-amplitude, amplitude]
+    fn add_noise(positions: &mut [DVec3], amplitude: f64, along: impl Fn(&DVec3) -> DVec3) {
+        let mut seed = 12345u32;
+        for p in positions.iter_mut() {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            let offset = ((seed >> 8) as f64 / (1u32 << 24) as f64 * 2.0 - 1.0) * amplitude;
+            *p += along(p) * offset;
+        }
+    }
+
+    fn smoothed(mesh: &SharedMesh, options: &SmoothingOptions) -> SharedMesh {
+        let mut connected_mesh = ConnectedMesh::from(mesh);
+        connected_mesh.smooth(options);
+        SharedMesh::from(&connected_mesh)
+    }
+
+    fn radius_deviation(mesh: &SharedMesh) -> f64 {
+        mesh.positions.iter().map(|p| (p.magnitude() - 1.0).abs()).fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn taubin_preserves_volume() {
+        let mut mesh = sphere(DVec3::zeros(), 1.0, 3);
+        add_noise(&mut mesh.positions, 0.03, |p| p.normalize());
+
+        let laplacian = smoothed(&mesh, &SmoothingOptions { method: SmoothingMethod::Laplacian { lambda: 0.5 }, ..Default::default() });
+        let taubin = smoothed(&mesh, &SmoothingOptions::default());
+
+        let volume = mesh.volume();
+        assert!((taubin.volume() - volume).abs() < (laplacian.volume() - volume).abs() / 4.0);
+
+        // Noise is mostly gone: radii are much closer to their mean
+        let spread = |mesh: &SharedMesh| {
+            let mean = mesh.positions.iter().map(|p| p.magnitude()).sum::<f64>() / mesh.positions.len() as f64;
+            (mesh.positions.iter().map(|p| (p.magnitude() - mean).powi(2)).sum::<f64>() / mesh.positions.len() as f64).sqrt()
+        };
+        assert!(spread(&taubin) < spread(&mesh) / 2.0);
+    }
+
+    #[test]
+    fn normals_follow_positions() {
+        let mut mesh = sphere(DVec3::zeros(), 1.0, 3);
+        add_noise(&mut mesh.positions, 0.03, |p| p.normalize());
+        // Stale normals, which smoothing must replace
+        mesh.normals = Some(vec![DVec3::z(); mesh.positions.len()]);
+
+        let smoothed = smoothed(&mesh, &SmoothingOptions::default());
+        let normals = smoothed.normals.as_ref().unwrap();
+        assert_eq!(normals.len(), smoothed.positions.len());
+        for (normal, position) in normals.iter().zip(&smoothed.positions) {
+            assert!((normal.magnitude() - 1.0).abs() < 1e-9);
+            assert!(normal.dot(&position.normalize()) > 0.95);
+        }
+    }
+
+    #[test]
+    fn cotangent_boundary_and_pins() {
+        let mut mesh = grid(10, 1.0);
+        add_noise(&mut mesh.positions, 0.05, |_| DVec3::z());
+        let pinned = 60;
+
+        let mut connected_mesh = ConnectedMesh::from(&mesh);
+        let vertex = (0..connected_mesh.vertex_count()).map(VertexId).find(|v| connected_mesh.position(*v) == Ok(mesh.positions[pinned])).unwrap();
+        let options = SmoothingOptions {
+            method: SmoothingMethod::Cotangent { lambda: 0.5 },
+            iterations: 20,
+            boundary: BoundaryHandling::Fixed,
+            pinned: vec![vertex],
+        };
+        connected_mesh.smooth(&options);
+        let result = SharedMesh::from(&connected_mesh);
+
+        let find = |p: &DVec3| result.positions.iter().position(|q| q == p);
+        for (i, p) in mesh.positions.iter().enumerate() {
+            let (x, y) = (i % 11, i / 11);
+            if x == 0 || y == 0 || x == 10 || y == 10 || i == pinned {
+                assert!(find(p).is_some());
+            }
+        }
+
+        let max_z = |mesh: &SharedMesh| mesh.positions.iter().map(|p| p.z.abs()).fold(0.0, f64::max);
+        let interior_max_z = result.positions.iter()
+            .filter(|p| p.x > 0.15 && p.x < 0.85 && p.y > 0.15 && p.y < 0.85 && (p.x - 0.6).abs() + (p.y - 0.5).abs() > 0.25)
+            .map(|p| p.z.abs())
+            .fold(0.0, f64::max);
+        assert!(interior_max_z < max_z(&mesh) / 2.0);
+    }
+
+    #[test]
+    fn bilateral_keeps_sharp_edges() {
+        let mut connected_mesh = ConnectedMesh::from(&cube(DVec3::zeros(), 2.0));
+        connected_mesh.remesh(&RemeshOptions { target_edge_length: 0.2, ..Default::default() });
+        let mut mesh = SharedMesh::from(&connected_mesh);
+        add_noise(&mut mesh.positions, 0.02, |p| {
+            // Along the normal of the closest cube face
+            let a = p.abs();
+            if a.x >= a.y && a.x >= a.z { DVec3::x() } else if a.y >= a.z { DVec3::y() } else { DVec3::z() }
+        });
+
+        let options = SmoothingOptions {
+            method: SmoothingMethod::Bilateral { normal_sigma: 0.4, vertex_iterations: 10 },
+            iterations: 5,
+            ..Default::default()
+        };
+        let result = smoothed(&mesh, &options);
+
+        let flatness = |mesh: &SharedMesh| mesh.positions.iter().map(|p| (p.abs().max() - 1.0).abs()).sum::<f64>() / mesh.positions.len() as f64;
+        assert!(flatness(&result) < flatness(&mesh) / 2.0);
+        assert!(radius_deviation(&result) > 0.5);
+        assert!((result.volume() - 8.0).abs() < 0.2);
+    }
+}
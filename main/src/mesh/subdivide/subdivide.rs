@@ -0,0 +1,504 @@
+// Subdivision surfaces. The mesh is converted to polygons sharing position indices,
+// refined with the chosen scheme and converted back to triangles.
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SubdivisionScheme {
+    /// Approximating scheme for triangles (Loop, 1987)
+    Loop,
+    /// Interpolating scheme for triangles (Dyn, Levin & Gregory, 1990). Original vertices are not moved.
+    Butterfly,
+    /// Approximating scheme producing quads (Catmull & Clark, 1978). Quads are split in two triangles in the result.
+    CatmullClark,
+}
+
+#[derive(Debug, Clone)]
+pub struct SubdivisionOptions {
+    pub scheme: SubdivisionScheme,
+    pub iterations: u32,
+    /// Keep edges where normals are split (hard edges) sharp
+    pub normal_seams_as_creases: bool,
+    /// Keep edges between two different groups sharp
+    pub group_borders_as_creases: bool,
+    /// Additional sharp edges, as pairs of position indices. Boundary edges are always sharp.
+    pub creases: Vec<(u32, u32)>,
+}
+
+impl Default for SubdivisionOptions {
+    fn default() -> Self {
+        SubdivisionOptions {
+            scheme: SubdivisionScheme::Loop,
+            iterations: 1,
+            normal_seams_as_creases: true,
+            group_borders_as_creases: true,
+            creases: Vec::new(),
+        }
+    }
+}
+
+impl ConnectedMesh {
+    /// Subdivides the mesh. Normals, colors and texture coordinates are interpolated, groups are kept.
+    /// Positions of the original mesh keep their indices (butterfly leaves them untouched).
+    pub fn subdivide(&mut self, options: &SubdivisionOptions) {
+        let mut mesh = self.to_polygons();
+        mesh.creases = options.creases.iter().map(|(a, b)| adjacency::edge_key(*a, *b)).collect();
+
+        if options.scheme != SubdivisionScheme::CatmullClark {
+            mesh.polygons = triangulate_polygons(mesh.polygons);
+        }
+
+        for _ in 0..options.iterations {
+            mesh = mesh.subdivide(options);
+        }
+
+        self.set_polygons(mesh);
+    }
+
+    fn to_polygons(&self) -> PolygonMesh {
+        let node_groups = self.node_groups();
+        let mut visited = vec![false; self.nodes.len()];
+        let mut polygons = Vec::with_capacity(self.face_count as usize);
+        for i in 0..self.nodes.len() {
+            if self.nodes[i].is_removed || visited[i] {
+                continue;
+            }
+            let mut corners = Vec::with_capacity(3);
+            loop_relatives!(i as u32, self.nodes, relative, {
+                let node = &self.nodes[relative as usize];
+                visited[relative as usize] = true;
+                corners.push(Corner { position: node.position, normal: node.normal, color: node.color, uv: node.uv });
+            });
+            polygons.push(Polygon { corners, group: node_groups[i] });
+        }
+
+        PolygonMesh {
+            positions: self.positions.clone(),
+            normals: self.normals.clone(),
+            colors: self.colors.clone(),
+            uvs: self.uvs.as_ref().map(|uvs| uvs.iter().map(|uv| DVec3::new(uv.x, uv.y, 0.0)).collect()),
+            polygons,
+            creases: HashSet::new(),
+        }
+    }
+
+    fn set_polygons(&mut self, mesh: PolygonMesh) {
+        let mut polygons = triangulate_polygons(mesh.polygons);
+        // Stable sort, so that each group is a contiguous range of nodes
+        polygons.sort_by_key(|polygon| polygon.group);
+
+        let mut nodes = Vec::with_capacity(polygons.len() * 3);
+        for (i, polygon) in polygons.iter().enumerate() {
+            for (k, corner) in polygon.corners.iter().enumerate() {
+                nodes.push(Node {
+                    position: corner.position,
+                    normal: corner.normal,
+                    color: corner.color,
+                    uv: corner.uv,
+                    sibling: 0,
+                    relative: (i * 3 + (k + 1) % 3) as u32,
+                    is_removed: false,
+                });
+            }
+        }
+        link_siblings(&mut nodes);
+
+        for (g, group) in self.groups.iter_mut().enumerate() {
+            let first = polygons.partition_point(|polygon| polygon.group < g as u32);
+            let count = polygons[first..].iter().take_while(|polygon| polygon.group == g as u32).count();
+            *group = Group::new(first as u32 * 3, count as u32 * 3);
+        }
+
+        self.face_count = polygons.len() as u32;
+        self.nodes = nodes;
+        self.positions = mesh.positions;
+        self.normals = mesh.normals;
+        self.colors = mesh.colors;
+        self.uvs = mesh.uvs.map(|uvs| uvs.iter().map(|uv| uv.xy()).collect());
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Corner {
+    position: u32,
+    normal: u32,
+    color: u32,
+    uv: u32,
+}
+
+#[derive(Debug, Clone)]
+struct Polygon {
+    corners: Vec<Corner>,
+    group: u32,
+}
+
+struct PolygonMesh {
+    positions: Vec<DVec3>,
+    normals: Option<Vec<DVec3>>,
+    colors: Option<Vec<DVec3>>,
+    // Texture coordinates, with z = 0 so that they are interpolated like other attributes
+    uvs: Option<Vec<DVec3>>,
+    polygons: Vec<Polygon>,
+    // Edges (position pairs) to keep sharp, in addition to the ones detected
+    creases: HashSet<u64>,
+}
+
+#[derive(Default)]
+struct SubdivisionEdge {
+    // (polygon, corner) of each polygon side lying on this edge
+    sides: Vec<(u32, u32)>,
+    is_crease: bool,
+    // Index of the point inserted on this edge
+    point: u32,
+}
+
+#[derive(Default, Clone)]
+struct SubdivisionVertex {
+    neighbors: Vec<u32>,
+    crease_neighbors: Vec<u32>,
+    polygons: Vec<u32>,
+}
+
+fn triangulate_polygons(polygons: Vec<Polygon>) -> Vec<Polygon> {
+    if polygons.iter().all(|polygon| polygon.corners.len() == 3) {
+        return polygons;
+    }
+    let mut triangles = Vec::with_capacity(polygons.len() * 2);
+    for polygon in polygons {
+        for k in 1..polygon.corners.len().saturating_sub(1) {
+            triangles.push(Polygon {
+                corners: vec![polygon.corners[0], polygon.corners[k], polygon.corners[k + 1]],
+                group: polygon.group,
+            });
+        }
+    }
+    triangles
+}
+
+impl PolygonMesh {
+    fn subdivide(&self, options: &SubdivisionOptions) -> PolygonMesh {
+        let mut edges = self.edges(options);
+        let vertices = self.vertices(&edges);
+
+        let face_points: Vec<DVec3> = self.polygons.iter()
+            .map(|polygon| polygon.corners.iter().map(|c| self.positions[c.position as usize]).sum::<DVec3>() / polygon.corners.len() as f64)
+            .collect();
+
+        // Vertex points, keeping original indices
+        let mut positions = self.positions.clone();
+        if options.scheme != SubdivisionScheme::Butterfly {
+            for (i, vertex) in vertices.iter().enumerate() {
+                if !vertex.neighbors.is_empty() {
+                    positions[i] = self.vertex_point(i as u32, vertex, &face_points, options.scheme);
+                }
+            }
+        }
+
+        // Edge points
+        for (key, edge) in edges.iter_mut() {
+            let (a, b) = ((key >> 32) as u32, *key as u32);
+            edge.point = positions.len() as u32;
+            positions.push(self.edge_point(a, b, edge, &vertices, &face_points, options.scheme));
+        }
+
+        // Face points
+        let first_face_point = positions.len() as u32;
+        if options.scheme == SubdivisionScheme::CatmullClark {
+            positions.extend_from_slice(&face_points);
+        }
+
+        let mut normals = self.normals.clone();
+        let mut colors = self.colors.clone();
+        let mut uvs = self.uvs.clone();
+        let mut normal_midpoints = adjacency::EdgeMap::<u32>::default();
+        let mut color_midpoints = adjacency::EdgeMap::<u32>::default();
+        let mut uv_midpoints = adjacency::EdgeMap::<u32>::default();
+
+        let mut polygons = Vec::with_capacity(self.polygons.len() * 4);
+        for (f, polygon) in self.polygons.iter().enumerate() {
+            let n = polygon.corners.len();
+            let c = &polygon.corners;
+            let mids: Vec<Corner> = (0..n)
+                .map(|k| {
+                    let (a, b) = (&c[k], &c[(k + 1) % n]);
+                    Corner {
+                        position: edges[&adjacency::edge_key(a.position, b.position)].point,
+                        normal: interpolate_attribute(&mut normals, &mut normal_midpoints, a.normal, b.normal, true),
+                        color: interpolate_attribute(&mut colors, &mut color_midpoints, a.color, b.color, false),
+                        uv: interpolate_attribute(&mut uvs, &mut uv_midpoints, a.uv, b.uv, false),
+                    }
+                })
+                .collect();
+            let group = polygon.group;
+            if options.scheme == SubdivisionScheme::CatmullClark {
+                let center = Corner {
+                    position: first_face_point + f as u32,
+                    normal: average_attribute(&mut normals, c.iter().map(|corner| corner.normal), true),
+                    color: average_attribute(&mut colors, c.iter().map(|corner| corner.color), false),
+                    uv: average_attribute(&mut uvs, c.iter().map(|corner| corner.uv), false),
+                };
+                for k in 0..n {
+                    polygons.push(Polygon { corners: vec![c[k], mids[k], center, mids[(k + n - 1) % n]], group });
+                }
+            } else {
+                polygons.push(Polygon { corners: vec![c[0], mids[0], mids[2]], group });
+                polygons.push(Polygon { corners: vec![mids[0], c[1], mids[1]], group });
+                polygons.push(Polygon { corners: vec![mids[2], mids[1], c[2]], group });
+                polygons.push(Polygon { corners: vec![mids[0], mids[1], mids[2]], group });
+            }
+        }
+
+        // User creases are split along with their edges
+        let mut creases = HashSet::with_capacity(self.creases.len() * 2);
+        for key in &self.creases {
+            if let Some(edge) = edges.get(key) {
+                creases.insert(adjacency::edge_key((key >> 32) as u32, edge.point));
+                creases.insert(adjacency::edge_key(*key as u32, edge.point));
+            }
+        }
+
+        PolygonMesh { positions, normals, colors, uvs, polygons, creases }
+    }
+
+    fn edges(&self, options: &SubdivisionOptions) -> adjacency::EdgeMap<SubdivisionEdge> {
+        let mut edges = adjacency::EdgeMap::<SubdivisionEdge>::default();
+        for (f, polygon) in self.polygons.iter().enumerate() {
+            let n = polygon.corners.len();
+            for k in 0..n {
+                let key = adjacency::edge_key(polygon.corners[k].position, polygon.corners[(k + 1) % n].position);
+                edges.entry(key).or_default().sides.push((f as u32, k as u32));
+            }
+        }
+
+        for (key, edge) in edges.iter_mut() {
+            edge.is_crease = edge.sides.len() != 2 || self.creases.contains(key) || {
+                let (f0, k0) = edge.sides[0];
+                let (f1, k1) = edge.sides[1];
+                let (p0, p1) = (&self.polygons[f0 as usize], &self.polygons[f1 as usize]);
+                if options.group_borders_as_creases && p0.group != p1.group {
+                    true
+                } else if options.normal_seams_as_creases && self.normals.is_some() {
+                    // Sides run in opposite directions on a consistently oriented surface, but don't rely on it
+                    let side0 = [p0.corners[k0 as usize], p0.corners[(k0 as usize + 1) % p0.corners.len()]];
+                    let side1 = [p1.corners[k1 as usize], p1.corners[(k1 as usize + 1) % p1.corners.len()]];
+                    side0.iter().any(|a| {
+                        let b = side1.iter().find(|b| b.position == a.position).unwrap_or(&side1[0]);
+                        attributes_differ(&self.normals, a.normal, b.normal)
+                    })
+                } else {
+                    false
+                }
+            };
+        }
+        edges
+    }
+
+    fn vertices(&self, edges: &adjacency::EdgeMap<SubdivisionEdge>) -> Vec<SubdivisionVertex> {
+        let mut vertices = vec![SubdivisionVertex::default(); self.positions.len()];
+        for (key, edge) in edges {
+            let (a, b) = ((key >> 32) as u32, *key as u32);
+            vertices[a as usize].neighbors.push(b);
+            vertices[b as usize].neighbors.push(a);
+            if edge.is_crease {
+                vertices[a as usize].crease_neighbors.push(b);
+                vertices[b as usize].crease_neighbors.push(a);
+            }
+        }
+        for (f, polygon) in self.polygons.iter().enumerate() {
+            for corner in &polygon.corners {
+                vertices[corner.position as usize].polygons.push(f as u32);
+            }
+        }
+        vertices
+    }
+
+    fn vertex_point(&self, index: u32, vertex: &SubdivisionVertex, face_points: &[DVec3], scheme: SubdivisionScheme) -> DVec3 {
+        let p = self.positions[index as usize];
+        match vertex.crease_neighbors.len() {
+            // Smooth vertex, or dart (a crease ending in the vertex)
+            0 | 1 => {
+                let n = vertex.neighbors.len() as f64;
+                let neighbors = vertex.neighbors.iter().map(|v| self.positions[*v as usize]).sum::<DVec3>();
+                if scheme == SubdivisionScheme::CatmullClark {
+                    let faces = vertex.polygons.iter().map(|f| face_points[*f as usize]).sum::<DVec3>() / vertex.polygons.len() as f64;
+                    let edges = (p * n + neighbors) / (2.0 * n);
+                    (faces + edges * 2.0 + p * (n - 3.0)) / n
+                } else {
+                    let beta = (5.0 / 8.0 - (3.0 / 8.0 + (2.0 * std::f64::consts::PI / n).cos() / 4.0).powi(2)) / n;
+                    p * (1.0 - n * beta) + neighbors * beta
+                }
+            }
+            // Vertex on a crease curve
+            2 => {
+                let a = self.positions[vertex.crease_neighbors[0] as usize];
+                let b = self.positions[vertex.crease_neighbors[1] as usize];
+                p * 0.75 + (a + b) * 0.125
+            }
+            // Corner
+            _ => p,
+        }
+    }
+
+    fn edge_point(&self, a: u32, b: u32, edge: &SubdivisionEdge, vertices: &[SubdivisionVertex], face_points: &[DVec3], scheme: SubdivisionScheme) -> DVec3 {
+        let pa = self.positions[a as usize];
+        let pb = self.positions[b as usize];
+
+        if edge.is_crease {
+            // Four-point scheme along crease curves keeps the butterfly scheme smooth along boundaries
+            if scheme == SubdivisionScheme::Butterfly {
+                let next = |v: u32, from: u32| -> Option<u32> {
+                    let creases = &vertices[v as usize].crease_neighbors;
+                    if creases.len() == 2 { creases.iter().copied().find(|c| *c != from) } else { None }
+                };
+                if let (Some(a2), Some(b2)) = (next(a, b), next(b, a)) {
+                    return (pa + pb) * (9.0 / 16.0) - (self.positions[a2 as usize] + self.positions[b2 as usize]) / 16.0;
+                }
+            }
+            return (pa + pb) / 2.0;
+        }
+
+        match scheme {
+            SubdivisionScheme::CatmullClark => {
+                let (f0, f1) = (edge.sides[0].0 as usize, edge.sides[1].0 as usize);
+                (pa + pb + face_points[f0] + face_points[f1]) / 4.0
+            }
+            SubdivisionScheme::Loop => {
+                let opposite = edge.sides.iter().map(|(f, k)| self.opposite_position(*f, *k)).sum::<DVec3>();
+                (pa + pb) * 0.375 + opposite * 0.125
+            }
+            SubdivisionScheme::Butterfly => {
+                let mut opposite = DVec3::zeros();
+                let mut wings = DVec3::zeros();
+                for (f, k) in &edge.sides {
+                    let corners = &self.polygons[*f as usize].corners;
+                    let c = corners[(*k as usize + 2) % 3].position;
+                    opposite += self.positions[c as usize];
+                    // Vertices across the two other sides of the triangle
+                    for (x, y) in [(corners[*k as usize].position, c), (corners[(*k as usize + 1) % 3].position, c)] {
+                        wings += self.across(x, y, *f, vertices);
+                    }
+                }
+                (pa + pb) * 0.5 + opposite * 0.125 - wings * 0.0625
+            }
+        }
+    }
+
+    fn opposite_position(&self, polygon: u32, corner: u32) -> DVec3 {
+        let corners = &self.polygons[polygon as usize].corners;
+        self.positions[corners[(corner as usize + 2) % corners.len()].position as usize]
+    }
+
+    // Vertex of the triangle on the other side of edge (x, y) from `polygon`.
+    // Reflected when there is none, which amounts to a linear extension of the surface.
+    fn across(&self, x: u32, y: u32, polygon: u32, vertices: &[SubdivisionVertex]) -> DVec3 {
+        let corners = &self.polygons[polygon as usize].corners;
+        let z = corners.iter().find(|c| c.position != x && c.position != y).map_or(x, |c| c.position);
+        for &other in &vertices[x as usize].polygons {
+            if other == polygon {
+                continue;
+            }
+            let corners = &self.polygons[other as usize].corners;
+            if corners.len() == 3 && corners.iter().any(|c| c.position == y) {
+                if let Some(w) = corners.iter().find(|c| c.position != x && c.position != y) {
+                    return self.positions[w.position as usize];
+                }
+            }
+        }
+        self.positions[x as usize] + self.positions[y as usize] - self.positions[z as usize]
+    }
+
+}
+
+fn attributes_differ(attributes: &Option<Vec<DVec3>>, a: u32, b: u32) -> bool {
+    a != b && attributes.as_ref().map_or(false, |attributes| attributes[a as usize] != attributes[b as usize])
+}
+
+fn interpolate_attribute(attributes: &mut Option<Vec<DVec3>>, midpoints: &mut adjacency::EdgeMap<u32>, a: u32, b: u32, normalize: bool) -> u32 {
+    match attributes {
+        None => 0,
+        Some(_) if a == b => a,
+        Some(attributes) => *midpoints.entry(adjacency::edge_key(a, b)).or_insert_with(|| {
+            let mut value = (attributes[a as usize] + attributes[b as usize]) / 2.0;
+            if normalize && value.magnitude_squared() > 0.0 {
+                value = value.normalize();
+            }
+            attributes.push(value);
+            attributes.len() as u32 - 1
+        }),
+    }
+}
+
+fn average_attribute(attributes: &mut Option<Vec<DVec3>>, indices: impl Iterator<Item = u32>, normalize: bool) -> u32 {
+    match attributes {
+        None => 0,
+        Some(attributes) => {
+            let (sum, count) = indices.fold((DVec3::zeros(), 0), |(sum, count), i| (sum + attributes[i as usize], count + 1));
+            let mut value = sum / count as f64;
+            if normalize && value.magnitude_squared() > 0.0 {
+                value = value.normalize();
+            }
+            attributes.push(value);
+            attributes.len() as u32 - 1
+        }
+    }
+}
+
+#[cfg(test)]
+mod subdivide_tests {
+    use super::*;
+    use super::test_meshes::*;
+
+    fn subdivided(mesh: &SharedMesh, options: &SubdivisionOptions) -> SharedMesh {
+        let mut connected_mesh = ConnectedMesh::from(mesh);
+        connected_mesh.subdivide(options);
+        SharedMesh::from(&connected_mesh)
+    }
+
+    #[test]
+    fn loop_sphere() {
+        let mesh = sphere(DVec3::zeros(), 1.0, 1);
+        let options = SubdivisionOptions { iterations: 2, ..Default::default() };
+        let result = subdivided(&mesh, &options);
+
+        assert_eq!(result.triangles.len(), mesh.triangles.len() * 16);
+        assert!(result.volume() > 0.0 && result.volume() < mesh.volume());
+        for p in &result.positions {
+            assert!(p.magnitude() > 0.7 && p.magnitude() < 1.0);
+        }
+        let normals = result.normals.unwrap();
+        assert_eq!(normals.len(), result.positions.len());
+        assert!(normals.iter().all(|n| (n.magnitude() - 1.0).abs() < 1e-9));
+    }
+
+    #[test]
+    fn butterfly_interpolates() {
+        let mesh = sphere(DVec3::zeros(), 1.0, 1);
+        let options = SubdivisionOptions { scheme: SubdivisionScheme::Butterfly, ..Default::default() };
+        let result = subdivided(&mesh, &options);
+
+        assert_eq!(result.triangles.len(), mesh.triangles.len() * 4);
+        for p in &mesh.positions {
+            assert!(result.positions.iter().any(|q| q == p));
+        }
+        // New vertices bulge out of the flat faces, toward the sphere
+        let min_radius = result.positions.iter().map(|p| p.magnitude()).fold(f64::MAX, f64::min);
+        assert!(min_radius > (mesh.positions[0] + mesh.positions[2]).magnitude() / 2.0);
+    }
+
+    #[test]
+    fn catmull_clark_creases() {
+        let mut mesh = cube(DVec3::zeros(), 2.0);
+        let options = SubdivisionOptions { scheme: SubdivisionScheme::CatmullClark, iterations: 2, ..Default::default() };
+
+        // Without creases the cube is rounded
+        let smooth = subdivided(&mesh, &options);
+        assert_eq!(smooth.triangles.len(), 12 * 3 * 4 * 2);
+        assert!(smooth.positions.iter().all(|p| p.abs().max() < 1.0 - 1e-6));
+
+        // With one group per face, all edges are creases and the cube keeps its shape
+        mesh.groups = (0..6).map(|i| Group::new(i * 6, 6)).collect();
+        let sharp = subdivided(&mesh, &options);
+        assert!(sharp.positions.iter().all(|p| (p.abs().max() - 1.0).abs() < 1e-9));
+        assert!((sharp.volume() - 8.0).abs() < 1e-9);
+        assert_eq!(sharp.groups.len(), 6);
+        assert!(sharp.groups.iter().all(|group| group.index_count == 48 * 3));
+    }
+}
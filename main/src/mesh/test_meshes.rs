@@ -0,0 +1,112 @@
+// Small procedural meshes shared by unit tests
+
+use nalgebra_glm as glm;
+use glm::{DVec3, U32Vec3};
+use super::SharedMesh;
+
+/// Closed axis aligned cube with 8 shared vertices and outward facing triangles
+pub fn cube(center: DVec3, size: f64) -> SharedMesh {
+    let mut positions = Vec::new();
+    for i in 0..8 {
+        let corner = DVec3::new((i & 1) as f64, ((i >> 1) & 1) as f64, ((i >> 2) & 1) as f64);
+        positions.push(center + (corner - DVec3::new(0.5, 0.5, 0.5)) * size);
+    }
+
+    let triangles = vec![
+        U32Vec3::new(0, 2, 3), U32Vec3::new(0, 3, 1), // -z
+        U32Vec3::new(4, 5, 7), U32Vec3::new(4, 7, 6), // +z
+        U32Vec3::new(0, 1, 5), U32Vec3::new(0, 5, 4), // -y
+        U32Vec3::new(2, 6, 7), U32Vec3::new(2, 7, 3), // +y
+        U32Vec3::new(0, 4, 6), U32Vec3::new(0, 6, 2), // -x
+        U32Vec3::new(1, 3, 7), U32Vec3::new(1, 7, 5), // +x
+    ];
+
+    SharedMesh {
+        groups: Vec::new(),
+        triangles,
+        positions,
+        normals: None,
+        colors: None,
+        uvs: None,
+    }
+}
+
+/// Flat grid in the XY plane, facing +Z, made of `n` x `n` quads spanning [0, size]
+pub fn grid(n: u32, size: f64) -> SharedMesh {
+    let mut positions = Vec::new();
+    for y in 0..=n {
+        for x in 0..=n {
+            positions.push(DVec3::new(x as f64 * size / n as f64, y as f64 * size / n as f64, 0.));
+        }
+    }
+
+    let mut triangles = Vec::new();
+    for y in 0..n {
+        for x in 0..n {
+            let a = y * (n + 1) + x;
+            let b = a + 1;
+            let c = a + n + 1;
+            let d = c + 1;
+            triangles.push(U32Vec3::new(a, b, d));
+            triangles.push(U32Vec3::new(a, d, c));
+        }
+    }
+
+    SharedMesh {
+        groups: Vec::new(),
+        triangles,
+        positions,
+        normals: None,
+        colors: None,
+        uvs: None,
+    }
+}
+
+/// Closed sphere built by subdividing an octahedron `subdivisions` times
+pub fn sphere(center: DVec3, radius: f64, subdivisions: u32) -> SharedMesh {
+    let mut positions = vec![
+        DVec3::new(1., 0., 0.), DVec3::new(-1., 0., 0.),
+        DVec3::new(0., 1., 0.), DVec3::new(0., -1., 0.),
+        DVec3::new(0., 0., 1.), DVec3::new(0., 0., -1.),
+    ];
+    let mut triangles = vec![
+        U32Vec3::new(0, 2, 4), U32Vec3::new(2, 1, 4), U32Vec3::new(1, 3, 4), U32Vec3::new(3, 0, 4),
+        U32Vec3::new(2, 0, 5), U32Vec3::new(1, 2, 5), U32Vec3::new(3, 1, 5), U32Vec3::new(0, 3, 5),
+    ];
+
+    for _ in 0..subdivisions {
+        let mut midpoints = std::collections::HashMap::<(u32, u32), u32>::new();
+        let mut midpoint = |a: u32, b: u32, positions: &mut Vec<DVec3>| -> u32 {
+            let key = if a < b { (a, b) } else { (b, a) };
+            *midpoints.entry(key).or_insert_with(|| {
+                positions.push(((positions[a as usize] + positions[b as usize]) / 2.0).normalize());
+                positions.len() as u32 - 1
+            })
+        };
+        let mut subdivided = Vec::with_capacity(triangles.len() * 4);
+        for t in &triangles {
+            let ab = midpoint(t[0], t[1], &mut positions);
+            let bc = midpoint(t[1], t[2], &mut positions);
+            let ca = midpoint(t[2], t[0], &mut positions);
+            subdivided.push(U32Vec3::new(t[0], ab, ca));
+            subdivided.push(U32Vec3::new(t[1], bc, ab));
+            subdivided.push(U32Vec3::new(t[2], ca, bc));
+            subdivided.push(U32Vec3::new(ab, bc, ca));
+        }
+        triangles = subdivided;
+    }
+
+    let normals = positions.clone();
+    for p in positions.iter_mut() {
+        *p = center + *p * radius;
+    }
+
+    SharedMesh {
+        groups: Vec::new(),
+        triangles,
+        positions,
+        normals: Some(normals),
+        colors: None,
+        uvs: None,
+    }
+}
@@ -0,0 +1,823 @@
+// Public topology API over nodes. Each face is a triple of consecutive nodes, and each node is both a corner of
+// its face and the half-edge going from this corner to the next one (its relative).
+// Edits keep sibling and relative rings valid, so they can be mixed with the other algorithms.
+// Handles may go stale (removed faces, faces moved by `split_edge`) or be built by hand: every accessor taking a handle
+// checks it and fails with `TopologyError::InvalidHandle` rather than panicking or reading a removed face.
+
+/// A position of the mesh
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VertexId(pub u32);
+
+/// A face of the mesh, made of the nodes `3 * id` to `3 * id + 2`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FaceId(pub u32);
+
+/// A node of the mesh, seen as the directed edge from its corner to the next corner of its face
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HalfEdgeId(pub u32);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TopologyError {
+    /// The handle is out of range or refers to a removed face
+    InvalidHandle,
+    /// The edge has no opposite half-edge
+    BoundaryEdge,
+    /// The edge is shared by more than two faces
+    NonManifoldEdge,
+    /// The collapse would pinch the surface (the one-rings of both vertices share more than the edge's faces)
+    LinkCondition,
+    /// The flipped edge already exists, or would be degenerate
+    EdgeExists,
+    /// The relatives of this node don't form a valid face
+    InvalidFace(u32),
+    /// The siblings of this node don't form a valid ring of all nodes at its position
+    InvalidSiblings(u32),
+    /// This node refers to a position, normal or color that doesn't exist
+    InvalidAttribute(u32),
+    /// The face count doesn't match the number of faces that aren't removed
+    InvalidFaceCount { expected: u32, actual: u32 },
+}
+
+impl Display for TopologyError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match self {
+            TopologyError::InvalidHandle => write!(f, "invalid handle"),
+            TopologyError::BoundaryEdge => write!(f, "edge is on a boundary"),
+            TopologyError::NonManifoldEdge => write!(f, "edge is shared by more than two faces"),
+            TopologyError::LinkCondition => write!(f, "collapse would violate the link condition"),
+            TopologyError::EdgeExists => write!(f, "flipped edge already exists"),
+            TopologyError::InvalidFace(node) => write!(f, "invalid relatives at node {}", node),
+            TopologyError::InvalidSiblings(node) => write!(f, "invalid siblings at node {}", node),
+            TopologyError::InvalidAttribute(node) => write!(f, "invalid attribute index at node {}", node),
+            TopologyError::InvalidFaceCount { expected, actual } => write!(f, "face count is {} but {} faces remain", actual, expected),
+        }
+    }
+}
+
+impl std::error::Error for TopologyError {}
+
+/// Iterates over the siblings of a node, starting with the node itself
+pub struct Siblings<'a> {
+    nodes: &'a [Node],
+    first: u32,
+    next: Option<u32>,
+}
+
+impl Iterator for Siblings<'_> {
+    type Item = HalfEdgeId;
+
+    fn next(&mut self) -> Option<HalfEdgeId> {
+        let current = self.next?;
+        let sibling = self.nodes[current as usize].sibling;
+        self.next = if sibling == self.first { None } else { Some(sibling) };
+        Some(HalfEdgeId(current))
+    }
+}
+
+impl ConnectedMesh {
+    pub fn face_count(&self) -> u32 {
+        self.face_count
+    }
+
+    pub fn vertex_count(&self) -> u32 {
+        self.positions.len() as u32
+    }
+
+    pub fn position(&self, vertex: VertexId) -> std::result::Result<DVec3, TopologyError> {
+        self.positions.get(vertex.0 as usize).copied().ok_or(TopologyError::InvalidHandle)
+    }
+
+    pub fn set_position(&mut self, vertex: VertexId, position: DVec3) -> std::result::Result<(), TopologyError> {
+        *self.positions.get_mut(vertex.0 as usize).ok_or(TopologyError::InvalidHandle)? = position;
+        Ok(())
+    }
+
+    /// Faces that aren't removed
+    pub fn faces(&self) -> impl Iterator<Item = FaceId> + '_ {
+        (0..self.nodes.len() as u32 / 3).filter(move |f| !self.nodes[*f as usize * 3].is_removed).map(FaceId)
+    }
+
+    pub fn is_face_removed(&self, face: FaceId) -> bool {
+        (face.0 as usize).checked_mul(3).and_then(|node| self.nodes.get(node)).map_or(true, |node| node.is_removed)
+    }
+
+    pub fn face_half_edges(&self, face: FaceId) -> std::result::Result<[HalfEdgeId; 3], TopologyError> {
+        self.validate_face(face)?;
+        Ok(self.face_half_edges_unchecked(face))
+    }
+
+    pub fn face_vertices(&self, face: FaceId) -> std::result::Result<[VertexId; 3], TopologyError> {
+        self.validate_face(face)?;
+        Ok(self.face_vertices_unchecked(face))
+    }
+
+    /// Unit normal of the face, following its winding
+    pub fn face_normal(&self, face: FaceId) -> std::result::Result<DVec3, TopologyError> {
+        self.validate_face(face)?;
+        Ok(self.face_normal_unchecked(face))
+    }
+
+    pub fn face(&self, half_edge: HalfEdgeId) -> std::result::Result<FaceId, TopologyError> {
+        self.validate(half_edge)?;
+        Ok(self.face_unchecked(half_edge))
+    }
+
+    pub fn origin(&self, half_edge: HalfEdgeId) -> std::result::Result<VertexId, TopologyError> {
+        self.validate(half_edge)?;
+        Ok(self.origin_unchecked(half_edge))
+    }
+
+    pub fn destination(&self, half_edge: HalfEdgeId) -> std::result::Result<VertexId, TopologyError> {
+        self.validate(half_edge)?;
+        Ok(self.destination_unchecked(half_edge))
+    }
+
+    /// Next half-edge of the same face
+    pub fn next(&self, half_edge: HalfEdgeId) -> std::result::Result<HalfEdgeId, TopologyError> {
+        self.validate(half_edge)?;
+        Ok(self.next_unchecked(half_edge))
+    }
+
+    /// Previous half-edge of the same face
+    pub fn previous(&self, half_edge: HalfEdgeId) -> std::result::Result<HalfEdgeId, TopologyError> {
+        self.validate(half_edge)?;
+        Ok(self.previous_unchecked(half_edge))
+    }
+
+    /// Half-edge going the other way along the same edge, in the adjacent face.
+    /// None on boundaries. On non-manifold edges, one of the opposite half-edges is returned.
+    pub fn opposite(&self, half_edge: HalfEdgeId) -> std::result::Result<Option<HalfEdgeId>, TopologyError> {
+        self.validate(half_edge)?;
+        Ok(self.opposite_unchecked(half_edge))
+    }
+
+    pub fn is_boundary_edge(&self, half_edge: HalfEdgeId) -> std::result::Result<bool, TopologyError> {
+        self.validate(half_edge)?;
+        Ok(self.is_boundary_edge_unchecked(half_edge))
+    }
+
+    /// One outgoing half-edge for each vertex, to start one-ring traversals from.
+    /// None for vertices that are no longer used by any face.
+    pub fn vertex_half_edges(&self) -> Vec<Option<HalfEdgeId>> {
+        let mut half_edges = vec![None; self.positions.len()];
+        for (i, node) in self.nodes.iter().enumerate() {
+            if !node.is_removed && half_edges[node.position as usize].is_none() {
+                half_edges[node.position as usize] = Some(HalfEdgeId(i as u32));
+            }
+        }
+        half_edges
+    }
+
+    /// All half-edges leaving the origin of the given half-edge, in no particular order
+    pub fn outgoing_half_edges(&self, half_edge: HalfEdgeId) -> std::result::Result<Siblings<'_>, TopologyError> {
+        self.validate(half_edge)?;
+        Ok(self.outgoing_half_edges_unchecked(half_edge))
+    }
+
+    /// Faces around the origin of the given half-edge
+    pub fn vertex_faces(&self, half_edge: HalfEdgeId) -> std::result::Result<impl Iterator<Item = FaceId> + '_, TopologyError> {
+        self.validate(half_edge)?;
+        Ok(self.vertex_faces_unchecked(half_edge))
+    }
+
+    /// Vertices sharing an edge with the origin of the given half-edge
+    pub fn vertex_neighbors(&self, half_edge: HalfEdgeId) -> std::result::Result<impl Iterator<Item = VertexId>, TopologyError> {
+        self.validate(half_edge)?;
+        Ok(self.vertex_neighbors_unchecked(half_edge))
+    }
+
+    pub fn is_boundary_vertex(&self, half_edge: HalfEdgeId) -> std::result::Result<bool, TopologyError> {
+        self.validate(half_edge)?;
+        Ok(self.is_boundary_vertex_unchecked(half_edge))
+    }
+
+    /// Closed loops of boundary vertices, following the winding of the faces
+    pub fn boundary_loops(&self) -> Vec<Vec<VertexId>> {
+        let mut is_visited = vec![false; self.nodes.len()];
+        let mut loops = Vec::new();
+        for face in self.faces() {
+            for start in self.face_half_edges_unchecked(face) {
+                if is_visited[start.0 as usize] || !self.is_boundary_edge_unchecked(start) {
+                    continue;
+                }
+                let mut vertices = Vec::new();
+                let mut current = start;
+                loop {
+                    is_visited[current.0 as usize] = true;
+                    vertices.push(self.origin_unchecked(current));
+                    let next = self.outgoing_half_edges_unchecked(self.next_unchecked(current))
+                        .find(|h| !is_visited[h.0 as usize] && self.is_boundary_edge_unchecked(*h));
+                    match next {
+                        Some(next) => current = next,
+                        None => break,
+                    }
+                }
+                loops.push(vertices);
+            }
+        }
+        loops
+    }
+
+    /// Rotates an interior edge inside the quad formed by its two faces. Returns the new half-edge in the face of
+    /// `half_edge`. Face attributes follow their corners.
+    pub fn flip_edge(&mut self, half_edge: HalfEdgeId) -> std::result::Result<HalfEdgeId, TopologyError> {
+        self.validate(half_edge)?;
+        let opposite = self.manifold_opposite(half_edge)?.ok_or(TopologyError::BoundaryEdge)?;
+
+        // Faces (a, b, c) and (b, a, d) become (a, d, c) and (b, c, d)
+        let [n1, n2] = [self.next_unchecked(half_edge).0, self.previous_unchecked(half_edge).0];
+        let [m1, m2] = [self.next_unchecked(opposite).0, self.previous_unchecked(opposite).0];
+        let c = self.nodes[n2 as usize].position;
+        let d = self.nodes[m2 as usize].position;
+        if c == d || self.vertex_neighbors_unchecked(HalfEdgeId(n2)).any(|v| v.0 == d) {
+            return Err(TopologyError::EdgeExists);
+        }
+
+        self.unlink_sibling(n1);
+        self.unlink_sibling(m1);
+        self.copy_corner(m2, n1);
+        self.copy_corner(n2, m1);
+        self.link_sibling(n1, m2);
+        self.link_sibling(m1, n2);
+
+        Ok(HalfEdgeId(n1))
+    }
+
+    /// Inserts a vertex at `position` on the edge, splitting its faces in two. Normals, colors and texture coordinates are interpolated.
+    /// New faces stay in the group of the face they were split from: faces of the following groups may be
+    /// moved for groups to remain contiguous, which invalidates their face and half-edge handles.
+    pub fn split_edge(&mut self, half_edge: HalfEdgeId, position: DVec3) -> std::result::Result<VertexId, TopologyError> {
+        self.validate(half_edge)?;
+        let mut half_edges = vec![half_edge];
+        half_edges.extend(self.manifold_opposite(half_edge)?);
+
+        // Reserve slots for the new faces first, as reserving moves faces around
+        let node_groups = self.node_groups();
+        let groups: Vec<u32> = half_edges.iter().map(|h| node_groups[h.0 as usize]).collect();
+        let mut slots = Vec::new();
+        for group in groups {
+            let (slot, moves) = self.reserve_face(group);
+            for index in half_edges.iter_mut().map(|h| &mut h.0).chain(slots.iter_mut()) {
+                for (from, to) in &moves {
+                    if *index / 3 == *from / 3 {
+                        *index = to + *index % 3;
+                    }
+                }
+            }
+            slots.push(slot);
+        }
+
+        let vertex = self.positions.len() as u32;
+        self.positions.push(position);
+
+        // Both faces share the new attributes, unless the edge is a seam
+        let mut interpolated = Vec::<([u32; 6], [u32; 3])>::new();
+        let mut vertex_nodes = Vec::new();
+        for (h, slot) in half_edges.iter().zip(slots) {
+            // Face (x, y, z) becomes (x, m, z), and the new face is (m, y, z)
+            let [n0, n1, n2] = [h.0, self.next_unchecked(*h).0, self.previous_unchecked(*h).0];
+            let [x, y] = [n0, n1].map(|n| self.nodes[n as usize]);
+            let key = [x.normal, y.normal, x.color, y.color, x.uv, y.uv];
+            let reversed = [y.normal, x.normal, y.color, x.color, y.uv, x.uv];
+            let attributes = match interpolated.iter().find(|(k, _)| *k == key || *k == reversed) {
+                Some((_, attributes)) => *attributes,
+                None => {
+                    let attributes = [
+                        push_midpoint(&mut self.normals, [x.normal, y.normal], true),
+                        push_midpoint(&mut self.colors, [x.color, y.color], false),
+                        match &mut self.uvs {
+                            Some(uvs) => {
+                                uvs.push((uvs[x.uv as usize] + uvs[y.uv as usize]) / 2.0);
+                                uvs.len() as u32 - 1
+                            }
+                            None => 0,
+                        },
+                    ];
+                    interpolated.push((key, attributes));
+                    attributes
+                }
+            };
+
+            self.copy_corner(n1, slot + 1);
+            self.copy_corner(n2, slot + 2);
+            for k in 0..3 {
+                self.nodes[(slot + k) as usize].relative = slot + (k + 1) % 3;
+                self.nodes[(slot + k) as usize].is_removed = false;
+            }
+            // The new face takes the place of n1 in the ring at y
+            self.replace_sibling(n1, slot + 1);
+            self.link_sibling(slot + 2, n2);
+
+            for node in [n1, slot] {
+                let node = &mut self.nodes[node as usize];
+                node.position = vertex;
+                node.normal = attributes[0];
+                node.color = attributes[1];
+                node.uv = attributes[2];
+            }
+            vertex_nodes.extend([n1, slot]);
+            self.face_count += 1;
+        }
+
+        for (i, node) in vertex_nodes.iter().enumerate() {
+            self.nodes[*node as usize].sibling = vertex_nodes[(i + 1) % vertex_nodes.len()];
+        }
+
+        Ok(VertexId(vertex))
+    }
+
+    /// Collapses the edge into its origin, moved to `position`. Fails if the collapse would change the topology
+    /// of the surface, as checked by the link condition.
+    pub fn collapse_edge(&mut self, half_edge: HalfEdgeId, position: DVec3) -> std::result::Result<VertexId, TopologyError> {
+        self.validate(half_edge)?;
+        if !self.satisfies_link_condition_unchecked(half_edge) {
+            return Err(TopologyError::LinkCondition);
+        }
+
+        let origin = self.origin_unchecked(half_edge);
+        self.collapse_edge_to_a(half_edge.0, self.next_unchecked(half_edge).0, &mut None);
+        self.positions[origin.0 as usize] = position;
+        Ok(origin)
+    }
+
+    /// Whether collapsing the edge keeps the surface manifold with the same topology
+    /// ("Topology Preserving Edge Contraction", Dey et al., 1999)
+    pub fn satisfies_link_condition(&self, half_edge: HalfEdgeId) -> std::result::Result<bool, TopologyError> {
+        self.validate(half_edge)?;
+        Ok(self.satisfies_link_condition_unchecked(half_edge))
+    }
+
+    fn satisfies_link_condition_unchecked(&self, half_edge: HalfEdgeId) -> bool {
+        let b = self.destination_unchecked(half_edge);
+
+        // Vertices opposite to the edge, in the faces sharing it
+        let mut opposites = Vec::new();
+        for h in self.outgoing_half_edges_unchecked(half_edge) {
+            if self.destination_unchecked(h) == b {
+                opposites.push(self.previous_unchecked(h));
+            } else if self.origin_unchecked(self.previous_unchecked(h)) == b {
+                opposites.push(self.next_unchecked(h));
+            }
+        }
+
+        let neighbors_of_b: Vec<VertexId> = self.vertex_neighbors_unchecked(self.next_unchecked(half_edge)).collect();
+        let common = self.vertex_neighbors_unchecked(half_edge).filter(|v| neighbors_of_b.contains(v)).count();
+        if common != opposites.len() || opposites.iter().any(|h| !neighbors_of_b.contains(&self.origin_unchecked(*h))) {
+            return false;
+        }
+
+        // An interior vertex of valence 3 would be left with two faces on top of each other (as in a tetrahedron)
+        if opposites.iter().any(|h| self.vertex_neighbors_unchecked(*h).count() <= 3 && !self.is_boundary_vertex_unchecked(*h)) {
+            return false;
+        }
+
+        // An interior edge between two boundaries would pinch the surface
+        opposites.len() != 2 || !(self.is_boundary_vertex_unchecked(half_edge) && self.is_boundary_vertex_unchecked(self.next_unchecked(half_edge)))
+    }
+
+    /// Removes a face, leaving a hole. Its vertices are kept even if they are no longer used.
+    pub fn remove_face(&mut self, face: FaceId) -> std::result::Result<(), TopologyError> {
+        self.validate_face(face)?;
+        for h in self.face_half_edges_unchecked(face) {
+            self.unlink_sibling(h.0);
+            self.nodes[h.0 as usize].is_removed = true;
+        }
+        self.face_count -= 1;
+        Ok(())
+    }
+
+    /// Validates relative rings (faces), sibling rings (one ring per position, holding all of its nodes),
+    /// attribute indices and the face count
+    pub fn check(&self) -> std::result::Result<(), TopologyError> {
+        let node_count = self.nodes.len() as u32;
+        let mut nodes_per_position = vec![0u32; self.positions.len()];
+        for (i, node) in self.nodes.iter().enumerate() {
+            let i = i as u32;
+            if node.is_removed {
+                continue;
+            }
+            if node.position as usize >= self.positions.len()
+                || self.normals.as_ref().map_or(false, |normals| node.normal as usize >= normals.len())
+                || self.colors.as_ref().map_or(false, |colors| node.color as usize >= colors.len())
+                || self.uvs.as_ref().map_or(false, |uvs| node.uv as usize >= uvs.len()) {
+                return Err(TopologyError::InvalidAttribute(i));
+            }
+            nodes_per_position[node.position as usize] += 1;
+
+            let relatives = [node.relative, self.nodes.get(node.relative as usize).map_or(u32::MAX, |n| n.relative)];
+            let is_face = relatives.iter().all(|r| *r < node_count && r / 3 == i / 3 && *r != i && !self.nodes[*r as usize].is_removed)
+                && self.nodes[relatives[1] as usize].relative == i;
+            if !is_face {
+                return Err(TopologyError::InvalidFace(i));
+            }
+        }
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            if node.is_removed {
+                continue;
+            }
+            let mut count = 0;
+            let mut sibling = i as u32;
+            loop {
+                let current = match self.nodes.get(sibling as usize) {
+                    Some(current) if !current.is_removed && current.position == node.position => current,
+                    _ => return Err(TopologyError::InvalidSiblings(i as u32)),
+                };
+                count += 1;
+                if count > nodes_per_position[node.position as usize] {
+                    return Err(TopologyError::InvalidSiblings(i as u32));
+                }
+                sibling = current.sibling;
+                if sibling == i as u32 {
+                    break;
+                }
+            }
+            if count != nodes_per_position[node.position as usize] {
+                return Err(TopologyError::InvalidSiblings(i as u32));
+            }
+        }
+
+        let faces = self.faces().count() as u32;
+        if faces != self.face_count {
+            return Err(TopologyError::InvalidFaceCount { expected: faces, actual: self.face_count });
+        }
+        Ok(())
+    }
+
+    fn validate(&self, half_edge: HalfEdgeId) -> std::result::Result<(), TopologyError> {
+        match self.nodes.get(half_edge.0 as usize) {
+            Some(node) if !node.is_removed => Ok(()),
+            _ => Err(TopologyError::InvalidHandle),
+        }
+    }
+
+    fn validate_face(&self, face: FaceId) -> std::result::Result<(), TopologyError> {
+        if self.is_face_removed(face) {
+            return Err(TopologyError::InvalidHandle);
+        }
+        Ok(())
+    }
+
+    // Accessors for handles known to be valid, used by the public ones once validated and by the algorithms
+
+    fn position_unchecked(&self, vertex: VertexId) -> DVec3 {
+        self.positions[vertex.0 as usize]
+    }
+    fn face_half_edges_unchecked(&self, face: FaceId) -> [HalfEdgeId; 3] {
+        let first = HalfEdgeId(face.0 * 3);
+        let second = self.next_unchecked(first);
+        [first, second, self.next_unchecked(second)]
+    }
+
+    fn face_vertices_unchecked(&self, face: FaceId) -> [VertexId; 3] {
+        self.face_half_edges_unchecked(face).map(|h| self.origin_unchecked(h))
+    }
+
+    /// Unit normal of the face, following its winding
+    fn face_normal_unchecked(&self, face: FaceId) -> DVec3 {
+        let [a, b, c] = self.face_vertices_unchecked(face).map(|v| self.position_unchecked(v));
+        (b - a).cross(&(c - a)).normalize()
+    }
+
+    fn face_unchecked(&self, half_edge: HalfEdgeId) -> FaceId {
+        FaceId(half_edge.0 / 3)
+    }
+
+    fn origin_unchecked(&self, half_edge: HalfEdgeId) -> VertexId {
+        VertexId(self.nodes[half_edge.0 as usize].position)
+    }
+
+    fn destination_unchecked(&self, half_edge: HalfEdgeId) -> VertexId {
+        self.origin_unchecked(self.next_unchecked(half_edge))
+    }
+
+    /// Next half-edge of the same face
+    fn next_unchecked(&self, half_edge: HalfEdgeId) -> HalfEdgeId {
+        HalfEdgeId(self.nodes[half_edge.0 as usize].relative)
+    }
+
+    /// Previous half-edge of the same face
+    fn previous_unchecked(&self, half_edge: HalfEdgeId) -> HalfEdgeId {
+        self.next_unchecked(self.next_unchecked(half_edge))
+    }
+
+    /// Half-edge going the other way along the same edge, in the adjacent face.
+    /// None on boundaries. On non-manifold edges, one of the opposite half-edges is returned.
+    fn opposite_unchecked(&self, half_edge: HalfEdgeId) -> Option<HalfEdgeId> {
+        let origin = self.origin_unchecked(half_edge);
+        self.outgoing_half_edges_unchecked(self.next_unchecked(half_edge)).find(|h| self.destination_unchecked(*h) == origin)
+    }
+
+    fn is_boundary_edge_unchecked(&self, half_edge: HalfEdgeId) -> bool {
+        self.opposite_unchecked(half_edge).is_none()
+    }
+
+    /// All half-edges leaving the origin of the given half-edge, in no particular order
+    fn outgoing_half_edges_unchecked(&self, half_edge: HalfEdgeId) -> Siblings<'_> {
+        Siblings { nodes: &self.nodes, first: half_edge.0, next: Some(half_edge.0) }
+    }
+
+    /// Faces around the origin of the given half-edge
+    fn vertex_faces_unchecked(&self, half_edge: HalfEdgeId) -> impl Iterator<Item = FaceId> + '_ {
+        self.outgoing_half_edges_unchecked(half_edge).map(move |h| self.face_unchecked(h))
+    }
+
+    /// Vertices sharing an edge with the origin of the given half-edge
+    fn vertex_neighbors_unchecked(&self, half_edge: HalfEdgeId) -> impl Iterator<Item = VertexId> {
+        let mut neighbors = Vec::new();
+        for h in self.outgoing_half_edges_unchecked(half_edge) {
+            for neighbor in [self.destination_unchecked(h), self.origin_unchecked(self.previous_unchecked(h))] {
+                if !neighbors.contains(&neighbor) {
+                    neighbors.push(neighbor);
+                }
+            }
+        }
+        neighbors.into_iter()
+    }
+
+    fn is_boundary_vertex_unchecked(&self, half_edge: HalfEdgeId) -> bool {
+        self.outgoing_half_edges_unchecked(half_edge).any(|h| self.is_boundary_edge_unchecked(h) || self.is_boundary_edge_unchecked(self.previous_unchecked(h)))
+    }
+
+    // The opposite half-edge, failing if the edge is shared by more than two faces
+    fn manifold_opposite(&self, half_edge: HalfEdgeId) -> std::result::Result<Option<HalfEdgeId>, TopologyError> {
+        let (a, b) = (self.origin_unchecked(half_edge), self.destination_unchecked(half_edge));
+        let faces = self.outgoing_half_edges_unchecked(half_edge)
+            .filter(|h| self.destination_unchecked(*h) == b || self.origin_unchecked(self.previous_unchecked(*h)) == b)
+            .count();
+        if faces > 2 {
+            return Err(TopologyError::NonManifoldEdge);
+        }
+        Ok(self.opposite_unchecked(half_edge).filter(|o| self.destination_unchecked(*o) == a))
+    }
+
+    fn copy_corner(&mut self, from: u32, to: u32) {
+        let from = self.nodes[from as usize];
+        let to = &mut self.nodes[to as usize];
+        to.position = from.position;
+        to.normal = from.normal;
+        to.color = from.color;
+        to.uv = from.uv;
+    }
+
+    fn sibling_before(&self, node: u32) -> u32 {
+        let mut previous = node;
+        while self.nodes[previous as usize].sibling != node {
+            previous = self.nodes[previous as usize].sibling;
+        }
+        previous
+    }
+
+    // Takes the node out of its sibling ring
+    fn unlink_sibling(&mut self, node: u32) {
+        let previous = self.sibling_before(node);
+        self.nodes[previous as usize].sibling = self.nodes[node as usize].sibling;
+        self.nodes[node as usize].sibling = node;
+    }
+
+    // Inserts the node in the sibling ring of `member`
+    fn link_sibling(&mut self, node: u32, member: u32) {
+        self.nodes[node as usize].sibling = self.nodes[member as usize].sibling;
+        self.nodes[member as usize].sibling = node;
+    }
+
+    // Puts `replacement` in the sibling ring of `node`, in its place
+    fn replace_sibling(&mut self, node: u32, replacement: u32) {
+        let previous = self.sibling_before(node);
+        let next = self.nodes[node as usize].sibling;
+        self.nodes[replacement as usize].sibling = if next == node { replacement } else { next };
+        if previous != node {
+            self.nodes[previous as usize].sibling = replacement;
+        }
+        self.nodes[node as usize].sibling = node;
+    }
+
+    // Makes room for a removed face at the end of the given group (u32::MAX for none), by moving the first face
+    // of each following range of nodes to the end of that range. Returns the first node of the new face,
+    // and the moves as (first node before, first node after).
+    fn reserve_face(&mut self, group: u32) -> (u32, Vec<(u32, u32)>) {
+        let node_count = self.nodes.len() as u32;
+        let end = self.groups.get(group as usize).map_or(node_count, |g| g.first_index + g.index_count);
+
+        let mut bounds: Vec<u32> = self.groups.iter()
+            .flat_map(|g| [g.first_index, g.first_index + g.index_count])
+            .filter(|b| *b > end && *b < node_count)
+            .collect();
+        bounds.push(end);
+        bounds.push(node_count);
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        let removed = Node { is_removed: true, ..Node::default() };
+        self.nodes.extend([removed; 3]);
+        let mut moves = Vec::new();
+        for range in bounds.windows(2).rev() {
+            self.move_face(range[0], range[1]);
+            moves.push((range[0], range[1]));
+        }
+        for k in 0..3 {
+            self.nodes[(end + k) as usize] = removed;
+        }
+
+        for g in self.groups.iter_mut() {
+            if g.first_index >= end && g.first_index < node_count {
+                g.first_index += 3;
+            }
+        }
+        if let Some(g) = self.groups.get_mut(group as usize) {
+            g.index_count += 3;
+        }
+        (end, moves)
+    }
+
+    // Moves the face starting at node `from` to the (free) face starting at node `to`
+    fn move_face(&mut self, from: u32, to: u32) {
+        for k in 0..3 {
+            let mut node = self.nodes[(from + k) as usize];
+            node.relative = to + node.relative % 3;
+            self.nodes[(to + k) as usize] = node;
+        }
+        if self.nodes[from as usize].is_removed {
+            return;
+        }
+        for k in 0..3 {
+            let previous = self.sibling_before(from + k);
+            self.nodes[previous as usize].sibling = to + k;
+            if (from..from + 3).contains(&previous) {
+                self.nodes[(to + previous - from) as usize].sibling = to + k;
+            }
+        }
+    }
+}
+
+// Adds the midpoint of two attributes, returning its index
+fn push_midpoint(attributes: &mut Option<Vec<DVec3>>, indices: [u32; 2], normalize: bool) -> u32 {
+    match attributes {
+        Some(attributes) => {
+            let mut midpoint = (attributes[indices[0] as usize] + attributes[indices[1] as usize]) / 2.0;
+            if normalize && midpoint.magnitude_squared() > 0.0 {
+                midpoint = midpoint.normalize();
+            }
+            attributes.push(midpoint);
+            attributes.len() as u32 - 1
+        }
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod topology_tests {
+    use super::*;
+    use super::test_meshes::*;
+
+    fn euler_characteristic(mesh: &ConnectedMesh) -> i64 {
+        let mut edges = HashSet::new();
+        let mut vertices = HashSet::new();
+        for face in mesh.faces() {
+            for h in mesh.face_half_edges(face).unwrap() {
+                let (a, b) = (mesh.origin(h).unwrap().0, mesh.destination(h).unwrap().0);
+                edges.insert((a.min(b), a.max(b)));
+                vertices.insert(a);
+            }
+        }
+        vertices.len() as i64 - edges.len() as i64 + mesh.face_count() as i64
+    }
+
+    #[test]
+    fn traversals() {
+        let mesh = ConnectedMesh::from(&grid(4, 1.0));
+        mesh.check().unwrap();
+
+        let half_edges = mesh.vertex_half_edges();
+        let center = half_edges[12].unwrap();
+        assert_eq!(mesh.origin(center).unwrap(), VertexId(12));
+        assert!(!mesh.is_boundary_vertex(center).unwrap());
+        assert_eq!(mesh.vertex_neighbors(center).unwrap().count(), 6);
+        assert_eq!(mesh.vertex_faces(center).unwrap().count(), 6);
+        assert!(mesh.outgoing_half_edges(center).unwrap().all(|h| mesh.opposite(h).unwrap().map(|o| mesh.opposite(o).unwrap()) == Some(Some(h))));
+
+        let corner = half_edges[0].unwrap();
+        assert!(mesh.is_boundary_vertex(corner).unwrap());
+
+        let loops = mesh.boundary_loops();
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].len(), 16);
+
+        let sphere = ConnectedMesh::from(&sphere(DVec3::zeros(), 1.0, 2));
+        assert!(sphere.boundary_loops().is_empty());
+        assert_eq!(euler_characteristic(&sphere), 2);
+    }
+
+    #[test]
+    fn flip() {
+        let mut mesh = ConnectedMesh::from(&grid(4, 1.0));
+        let center = mesh.vertex_half_edges()[12].unwrap();
+        let h = mesh.outgoing_half_edges(center).unwrap().next().unwrap();
+        let (a, b) = (mesh.origin(h).unwrap(), mesh.destination(h).unwrap());
+
+        let flipped = mesh.flip_edge(h).unwrap();
+        mesh.check().unwrap();
+        let (c, d) = (mesh.origin(flipped).unwrap(), mesh.destination(flipped).unwrap());
+        assert!(![a, b].contains(&c) && ![a, b].contains(&d));
+        assert!(mesh.opposite(flipped).unwrap().is_some());
+
+        // Flipping back restores the edge
+        let back = mesh.flip_edge(flipped).unwrap();
+        mesh.check().unwrap();
+        let edge = [mesh.origin(back).unwrap(), mesh.destination(back).unwrap()];
+        assert!(edge == [a, b] || edge == [b, a]);
+
+        let boundary = mesh.faces().flat_map(|f| mesh.face_half_edges(f).unwrap()).find(|h| mesh.is_boundary_edge(*h).unwrap()).unwrap();
+        assert_eq!(mesh.flip_edge(boundary), Err(TopologyError::BoundaryEdge));
+    }
+
+    #[test]
+    fn split() {
+        let mut shared_mesh = sphere(DVec3::zeros(), 1.0, 2);
+        let face_count = shared_mesh.triangles.len() as u32;
+        shared_mesh.groups = vec![Group::new(0, 96), Group::new(96, 192), Group::new(288, face_count * 3 - 288)];
+        let mut mesh = ConnectedMesh::from(&shared_mesh);
+
+        // An interior edge of the first group
+        let h = mesh.face_half_edges(FaceId(5)).unwrap()[0];
+        let (a, b) = (mesh.origin(h).unwrap(), mesh.destination(h).unwrap());
+        let midpoint = (mesh.position(a).unwrap() + mesh.position(b).unwrap()) / 2.0;
+        let vertex = mesh.split_edge(h, midpoint).unwrap();
+        mesh.check().unwrap();
+
+        assert_eq!(mesh.face_count(), face_count + 2);
+        assert_eq!(euler_characteristic(&mesh), 2);
+        let new_half_edge = mesh.vertex_half_edges()[vertex.0 as usize].unwrap();
+        let neighbors: Vec<VertexId> = mesh.vertex_neighbors(new_half_edge).unwrap().collect();
+        assert_eq!(neighbors.len(), 4);
+        assert!(neighbors.contains(&a) && neighbors.contains(&b));
+
+        // Groups grew, and stayed contiguous
+        let result = SharedMesh::from(&mesh);
+        let counts: Vec<u32> = result.groups.iter().map(|g| g.index_count / 3).collect();
+        assert_eq!(counts.iter().sum::<u32>(), face_count + 2);
+        assert_eq!(counts.len(), 3);
+        let normals = result.normals.unwrap();
+        assert!(normals.iter().all(|n| (n.magnitude() - 1.0).abs() < 1e-9));
+
+        // Boundary edges are split in a single face
+        let mut grid = ConnectedMesh::from(&grid(2, 1.0));
+        let boundary = grid.faces().flat_map(|f| grid.face_half_edges(f).unwrap()).find(|h| grid.is_boundary_edge(*h).unwrap()).unwrap();
+        grid.split_edge(boundary, DVec3::zeros()).unwrap();
+        grid.check().unwrap();
+        assert_eq!(grid.face_count(), 9);
+    }
+
+    #[test]
+    fn collapse() {
+        let mut mesh = ConnectedMesh::from(&sphere(DVec3::zeros(), 1.0, 1));
+        let h = mesh.face_half_edges(FaceId(0)).unwrap()[0];
+        let position = mesh.position(mesh.origin(h).unwrap()).unwrap();
+        let vertex = mesh.collapse_edge(h, position).unwrap();
+        mesh.check().unwrap();
+        assert_eq!(mesh.face_count(), 30);
+        assert_eq!(euler_characteristic(&mesh), 2);
+        assert!(mesh.vertex_half_edges()[vertex.0 as usize].is_some());
+
+        // Collapsing any edge of a tetrahedron would flatten it
+        let tetrahedron = SharedMesh {
+            groups: Vec::new(),
+            triangles: vec![U32Vec3::new(0, 2, 1), U32Vec3::new(0, 1, 3), U32Vec3::new(1, 2, 3), U32Vec3::new(2, 0, 3)],
+            positions: vec![DVec3::new(0., 0., 0.), DVec3::new(1., 0., 0.), DVec3::new(0., 1., 0.), DVec3::new(0., 0., 1.)],
+            normals: None,
+            colors: None,
+            uvs: None,
+        };
+        let mut mesh = ConnectedMesh::from(&tetrahedron);
+        assert_eq!(mesh.collapse_edge(HalfEdgeId(0), DVec3::zeros()), Err(TopologyError::LinkCondition));
+        mesh.check().unwrap();
+    }
+
+    #[test]
+    fn remove_faces() {
+        let mut mesh = ConnectedMesh::from(&sphere(DVec3::zeros(), 1.0, 1));
+        mesh.remove_face(FaceId(3)).unwrap();
+        mesh.check().unwrap();
+        assert_eq!(mesh.remove_face(FaceId(3)), Err(TopologyError::InvalidHandle));
+
+        // Handles on removed faces or out of range are rejected instead of reading stale nodes
+        assert_eq!(mesh.face_half_edges(FaceId(3)), Err(TopologyError::InvalidHandle));
+        assert_eq!(mesh.face_normal(FaceId(3)), Err(TopologyError::InvalidHandle));
+        assert_eq!(mesh.origin(HalfEdgeId(10)), Err(TopologyError::InvalidHandle));
+        assert_eq!(mesh.opposite(HalfEdgeId(u32::MAX)), Err(TopologyError::InvalidHandle));
+        assert_eq!(mesh.face_vertices(FaceId(u32::MAX / 3 + 1)), Err(TopologyError::InvalidHandle));
+        assert_eq!(mesh.remove_face(FaceId(u32::MAX / 3 + 1)), Err(TopologyError::InvalidHandle));
+        assert!(mesh.outgoing_half_edges(HalfEdgeId(11)).is_err());
+        let vertex = VertexId(mesh.vertex_count());
+        assert_eq!(mesh.position(vertex), Err(TopologyError::InvalidHandle));
+        assert_eq!(mesh.set_position(vertex, DVec3::zeros()), Err(TopologyError::InvalidHandle));
+
+        let loops = mesh.boundary_loops();
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].len(), 3);
+
+        // Broken rings are reported
+        mesh.nodes[0].sibling = 0;
+        assert!(matches!(mesh.check(), Err(TopologyError::InvalidSiblings(_))));
+    }
+}
@@ -0,0 +1,439 @@
+// Automatic UV unwrapping. The surface is cut into charts of similar orientation, each chart is flattened with
+// least squares conformal maps (Lévy et al. 2002), then charts are packed into a single atlas with shelf packing.
+// Vertices on chart borders are split, so that each chart gets its own texture coordinates.
+
+use nalgebra_glm as glm;
+use glm::{DVec2, DVec3};
+use hashbrown::HashMap;
+use std::collections::VecDeque;
+use super::{SharedMesh, adjacency};
+use super::super::base::Plane;
+
+#[derive(Debug, Clone)]
+pub struct UnwrapOptions {
+    /// Maximum angle between the normal of a triangle and the average normal of its chart, in radians
+    pub max_chart_angle: f64,
+    /// Size of the texture the atlas is meant for, in texels
+    pub resolution: u32,
+    /// Space left around each chart, in texels
+    pub padding: u32,
+}
+
+impl Default for UnwrapOptions {
+    fn default() -> Self {
+        UnwrapOptions {
+            max_chart_angle: std::f64::consts::FRAC_PI_3,
+            resolution: 1024,
+            padding: 4,
+        }
+    }
+}
+
+// A chart, on welded vertices
+struct Chart {
+    triangles: Vec<u32>,
+    vertices: Vec<u32>,
+    normal: DVec3,
+    uvs: Vec<DVec2>,
+    size: DVec2,
+}
+
+impl SharedMesh {
+    /// Generates texture coordinates in [0, 1] and stores them in `uvs`.
+    /// Vertices shared by several charts are split, along with their normals and colors. Triangle order is kept.
+    /// Returns the number of charts.
+    pub fn unwrap_uvs(&mut self, options: &UnwrapOptions) -> usize {
+        let welded = adjacency::weld_positions(&self.positions);
+        let mut charts = self.segment_charts(&welded, options.max_chart_angle.cos());
+        for chart in charts.iter_mut() {
+            chart.parameterize(self, &welded);
+        }
+        let (offsets, scale) = pack_charts(&charts, options.padding as f64 / options.resolution.max(1) as f64);
+
+        // One vertex per original vertex and chart
+        let normals = self.normals.take().filter(|normals| normals.len() == self.positions.len());
+        let colors = self.colors.take().filter(|colors| colors.len() == self.positions.len());
+        let mut positions = Vec::with_capacity(self.positions.len());
+        let mut new_normals = normals.as_ref().map(|_| Vec::with_capacity(self.positions.len()));
+        let mut new_colors = colors.as_ref().map(|_| Vec::with_capacity(self.positions.len()));
+        let mut uvs = Vec::with_capacity(self.positions.len());
+        let mut split_vertices = HashMap::<(u32, u32), u32>::new();
+
+        for (c, chart) in charts.iter().enumerate() {
+            let local: HashMap<u32, usize> = chart.vertices.iter().enumerate().map(|(i, v)| (*v, i)).collect();
+            for t in &chart.triangles {
+                for k in 0..3 {
+                    let vertex = self.triangles[*t as usize][k];
+                    let next = positions.len() as u32;
+                    let index = *split_vertices.entry((vertex, c as u32)).or_insert_with(|| {
+                        positions.push(self.positions[vertex as usize]);
+                        if let (Some(new_normals), Some(normals)) = (new_normals.as_mut(), normals.as_ref()) {
+                            new_normals.push(normals[vertex as usize]);
+                        }
+                        if let (Some(new_colors), Some(colors)) = (new_colors.as_mut(), colors.as_ref()) {
+                            new_colors.push(colors[vertex as usize]);
+                        }
+                        uvs.push((chart.uvs[local[&welded[vertex as usize]]] + offsets[c]) * scale);
+                        next
+                    });
+                    self.triangles[*t as usize][k] = index;
+                }
+            }
+        }
+
+        self.positions = positions;
+        self.normals = new_normals;
+        self.colors = new_colors;
+        self.uvs = Some(uvs);
+        charts.len()
+    }
+
+    // Grows charts from seed triangles over manifold and consistently oriented edges,
+    // while triangles stay within a cone around the average normal of the chart
+    fn segment_charts(&self, welded: &[u32], min_cosine: f64) -> Vec<Chart> {
+        let edge_faces = adjacency::edge_faces(&self.triangles, welded);
+        let triangle_normals: Vec<DVec3> = self.triangles.iter().map(|t| {
+            let [a, b, c] = [0, 1, 2].map(|k| self.positions[t[k] as usize]);
+            (b - a).cross(&(c - a))
+        }).collect();
+
+        let mut chart_of = vec![u32::MAX; self.triangles.len()];
+        let mut charts = Vec::new();
+        for seed in 0..self.triangles.len() {
+            if chart_of[seed] != u32::MAX {
+                continue;
+            }
+            let id = charts.len() as u32;
+            // Area weighted sum of normals
+            let mut normal_sum = triangle_normals[seed];
+            let mut triangles = vec![seed as u32];
+            let mut queue = VecDeque::from(vec![seed]);
+            chart_of[seed] = id;
+
+            while let Some(t) = queue.pop_front() {
+                let triangle = &self.triangles[t];
+                for k in 0..3 {
+                    let (a, b) = (welded[triangle[k] as usize], welded[triangle[(k + 1) % 3] as usize]);
+                    let faces = match edge_faces.get(&adjacency::edge_key(a, b)) {
+                        Some(faces) if faces.len() == 2 => faces,
+                        _ => continue,
+                    };
+                    let neighbor = if faces[0] as usize == t { faces[1] } else { faces[0] } as usize;
+                    if chart_of[neighbor] != u32::MAX || !adjacency::walks_edge(&self.triangles[neighbor], welded, b, a) {
+                        continue;
+                    }
+                    let normal = triangle_normals[neighbor];
+                    if normal.magnitude_squared() > 0.0 && normal_sum.magnitude_squared() > 0.0
+                        && normal.normalize().dot(&normal_sum.normalize()) < min_cosine {
+                        continue;
+                    }
+                    normal_sum += normal;
+                    chart_of[neighbor] = id;
+                    triangles.push(neighbor as u32);
+                    queue.push_back(neighbor);
+                }
+            }
+
+            let mut vertices: Vec<u32> = triangles.iter()
+                .flat_map(|t| (0..3).map(move |k| welded[self.triangles[*t as usize][k] as usize]))
+                .collect();
+            vertices.sort_unstable();
+            vertices.dedup();
+            let normal = if normal_sum.magnitude_squared() > 0.0 { normal_sum.normalize() } else { DVec3::z() };
+            charts.push(Chart { triangles, vertices, normal, uvs: Vec::new(), size: DVec2::zeros() });
+        }
+        charts
+    }
+}
+
+impl Chart {
+    // Least squares conformal map, scaled to the area of the chart and rotated along its principal axis
+    fn parameterize(&mut self, mesh: &SharedMesh, welded: &[u32]) {
+        let local: HashMap<u32, usize> = self.vertices.iter().enumerate().map(|(i, v)| (*v, i)).collect();
+        let (u, v) = Plane::new(&DVec3::zeros(), &self.normal).basis();
+        let projected: Vec<DVec2> = self.vertices.iter()
+            .map(|p| DVec2::new(mesh.positions[*p as usize].dot(&u), mesh.positions[*p as usize].dot(&v)))
+            .collect();
+
+        let triangles: Vec<[usize; 3]> = self.triangles.iter()
+            .map(|t| [0, 1, 2].map(|k| local[&welded[mesh.triangles[*t as usize][k] as usize]]))
+            .collect();
+        let mut uvs = least_squares_conformal_map(mesh, &self.vertices, &triangles, &projected);
+        if uvs.iter().any(|uv| !uv.x.is_finite() || !uv.y.is_finite()) {
+            uvs = projected;
+        }
+
+        // Same area as in 3D
+        let mut area_3d = 0.0;
+        let mut area_uv = 0.0;
+        for t in &triangles {
+            let [a, b, c] = t.map(|i| mesh.positions[self.vertices[i] as usize]);
+            area_3d += (b - a).cross(&(c - a)).magnitude() / 2.0;
+            area_uv += signed_area(&uvs[t[0]], &uvs[t[1]], &uvs[t[2]]);
+        }
+        if area_uv < 0.0 {
+            uvs.iter_mut().for_each(|uv| uv.y = -uv.y);
+            area_uv = -area_uv;
+        }
+        let scale = if area_uv > 0.0 && area_3d > 0.0 { (area_3d / area_uv).sqrt() } else { 1.0 };
+
+        // Principal axis along u, for tighter bounds
+        let center = uvs.iter().sum::<DVec2>() / uvs.len() as f64;
+        let (mut xx, mut xy, mut yy) = (0.0, 0.0, 0.0);
+        for uv in &uvs {
+            let d = uv - center;
+            xx += d.x * d.x;
+            xy += d.x * d.y;
+            yy += d.y * d.y;
+        }
+        let angle = 0.5 * (2.0 * xy).atan2(xx - yy);
+        let (sin, cos) = (-angle).sin_cos();
+        for uv in uvs.iter_mut() {
+            let d = (*uv - center) * scale;
+            *uv = DVec2::new(d.x * cos - d.y * sin, d.x * sin + d.y * cos);
+        }
+
+        let min = uvs.iter().fold(DVec2::repeat(f64::MAX), |m, uv| glm::min2(&m, uv));
+        let max = uvs.iter().fold(DVec2::repeat(f64::MIN), |m, uv| glm::max2(&m, uv));
+        uvs.iter_mut().for_each(|uv| *uv -= min);
+        self.size = max - min;
+        self.uvs = uvs;
+    }
+}
+
+fn signed_area(a: &DVec2, b: &DVec2, c: &DVec2) -> f64 {
+    ((b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)) / 2.0
+}
+
+// Minimizes the conformal energy with the two most distant vertices along the projection pinned to their
+// projected coordinates, with conjugate gradients on the normal equations starting from the projection.
+// Unknowns are all u coordinates followed by all v coordinates.
+fn least_squares_conformal_map(mesh: &SharedMesh, vertices: &[u32], triangles: &[[usize; 3]], projected: &[DVec2]) -> Vec<DVec2> {
+    let n = vertices.len();
+    let pins = [
+        (0..n).min_by(|a, b| projected[*a].x.partial_cmp(&projected[*b].x).unwrap()).unwrap(),
+        (0..n).max_by(|a, b| projected[*a].x.partial_cmp(&projected[*b].x).unwrap()).unwrap(),
+    ];
+    if pins[0] == pins[1] {
+        return projected.to_vec();
+    }
+
+    // Two rows (real and imaginary parts) per triangle, with 6 coefficients each
+    let mut rows = Vec::with_capacity(triangles.len() * 2);
+    for t in triangles {
+        let [a, b, c] = t.map(|i| mesh.positions[vertices[i] as usize]);
+        let x_axis = b - a;
+        let length = x_axis.magnitude();
+        let normal = x_axis.cross(&(c - a));
+        if length == 0.0 || normal.magnitude_squared() == 0.0 {
+            continue;
+        }
+        let x_axis = x_axis / length;
+        let y_axis = normal.normalize().cross(&x_axis);
+        // Triangle in its own counterclockwise frame
+        let local = [DVec2::zeros(), DVec2::new(length, 0.0), DVec2::new((c - a).dot(&x_axis), (c - a).dot(&y_axis))];
+        let weight = 1.0 / (2.0 * signed_area(&local[0], &local[1], &local[2])).sqrt();
+        let mut real = [(0usize, 0.0f64); 6];
+        let mut imaginary = [(0usize, 0.0f64); 6];
+        for j in 0..3 {
+            let w = (local[(j + 2) % 3] - local[(j + 1) % 3]) * weight;
+            // (w.x + i w.y) (u + i v)
+            real[j] = (t[j], w.x);
+            real[j + 3] = (n + t[j], -w.y);
+            imaginary[j] = (t[j], w.y);
+            imaginary[j + 3] = (n + t[j], w.x);
+        }
+        rows.push(real);
+        rows.push(imaginary);
+    }
+
+    let mut x: Vec<f64> = projected.iter().map(|p| p.x).chain(projected.iter().map(|p| p.y)).collect();
+    let mut is_free = vec![true; 2 * n];
+    for pin in pins {
+        is_free[pin] = false;
+        is_free[n + pin] = false;
+    }
+    let multiply = |x: &[f64]| -> Vec<f64> { rows.iter().map(|row| row.iter().map(|(i, a)| a * x[*i]).sum()).collect() };
+    let multiply_transposed = |r: &[f64]| -> Vec<f64> {
+        let mut result = vec![0.0; 2 * n];
+        for (row, value) in rows.iter().zip(r) {
+            for (i, a) in row {
+                result[*i] += a * value;
+            }
+        }
+        for (i, value) in result.iter_mut().enumerate() {
+            if !is_free[i] {
+                *value = 0.0;
+            }
+        }
+        result
+    };
+    let dot = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(x, y)| x * y).sum::<f64>();
+
+    let mut r: Vec<f64> = multiply(&x).iter().map(|v| -v).collect();
+    let mut s = multiply_transposed(&r);
+    let mut p = s.clone();
+    let mut gamma = dot(&s, &s);
+    let initial_gamma = gamma;
+    for _ in 0..(4 * n).clamp(100, 5000) {
+        if gamma <= initial_gamma * 1e-20 || gamma == 0.0 {
+            break;
+        }
+        let q = multiply(&p);
+        let qq = dot(&q, &q);
+        if qq == 0.0 {
+            break;
+        }
+        let alpha = gamma / qq;
+        x.iter_mut().zip(&p).for_each(|(x, p)| *x += alpha * p);
+        r.iter_mut().zip(&q).for_each(|(r, q)| *r -= alpha * q);
+        s = multiply_transposed(&r);
+        let next_gamma = dot(&s, &s);
+        let beta = next_gamma / gamma;
+        p.iter_mut().zip(&s).for_each(|(p, s)| *p = s + beta * *p);
+        gamma = next_gamma;
+    }
+
+    (0..n).map(|i| DVec2::new(x[i], x[n + i])).collect()
+}
+
+// Shelf packing of the chart rectangles, tallest first. Returns the offset of each chart, and the scale fitting
+// the atlas in [0, 1]. The padding is relative to the atlas size, so packing is repeated until it is respected.
+fn pack_charts(charts: &[Chart], padding: f64) -> (Vec<DVec2>, f64) {
+    let mut order: Vec<usize> = (0..charts.len()).collect();
+    order.sort_by(|a, b| charts[*b].size.y.partial_cmp(&charts[*a].size.y).unwrap());
+
+    let pack = |margin: f64| -> (Vec<DVec2>, f64) {
+        let padded = |c: usize| charts[c].size + DVec2::repeat(2.0 * margin);
+        let area: f64 = (0..charts.len()).map(|c| padded(c).x * padded(c).y).sum();
+        let widest = (0..charts.len()).map(|c| padded(c).x).fold(0.0, f64::max);
+        let shelf_width = (area.sqrt() * 1.1).max(widest);
+
+        let mut offsets = vec![DVec2::zeros(); charts.len()];
+        let (mut x, mut y, mut shelf_height, mut width) = (0.0f64, 0.0f64, 0.0f64, 0.0f64);
+        for c in &order {
+            let size = padded(*c);
+            if x > 0.0 && x + size.x > shelf_width {
+                y += shelf_height;
+                x = 0.0;
+                shelf_height = 0.0;
+            }
+            offsets[*c] = DVec2::new(x + margin, y + margin);
+            x += size.x;
+            width = width.max(x);
+            shelf_height = shelf_height.max(size.y);
+        }
+        (offsets, width.max(y + shelf_height))
+    };
+
+    let (mut offsets, mut extent) = pack(0.0);
+    let mut margin = padding * extent;
+    for _ in 0..16 {
+        let (next_offsets, next_extent) = pack(margin);
+        offsets = next_offsets;
+        extent = next_extent;
+        if margin >= padding * extent || padding >= 0.5 {
+            break;
+        }
+        margin = padding * extent * 1.01;
+    }
+
+    (offsets, if extent > 0.0 { 1.0 / extent } else { 1.0 })
+}
+
+#[cfg(test)]
+mod unwrap_tests {
+    use super::*;
+    use super::super::test_meshes::*;
+
+    // Bounds of each island of triangles connected through vertices
+    fn island_bounds(mesh: &SharedMesh) -> Vec<(DVec2, DVec2)> {
+        let mut island = (0..mesh.positions.len()).collect::<Vec<usize>>();
+        fn find(island: &mut [usize], i: usize) -> usize {
+            let mut root = i;
+            while island[root] != root {
+                root = island[root];
+            }
+            island[i] = root;
+            root
+        }
+        for t in &mesh.triangles {
+            for k in 1..3 {
+                let (a, b) = (find(&mut island, t[0] as usize), find(&mut island, t[k] as usize));
+                island[a] = b;
+            }
+        }
+        let mut bounds = HashMap::<usize, (DVec2, DVec2)>::new();
+        let uvs = mesh.uvs.as_ref().unwrap();
+        for (v, uv) in uvs.iter().enumerate() {
+            let root = find(&mut island, v);
+            let entry = bounds.entry(root).or_insert((DVec2::repeat(f64::MAX), DVec2::repeat(f64::MIN)));
+            entry.0 = glm::min2(&entry.0, uv);
+            entry.1 = glm::max2(&entry.1, uv);
+        }
+        bounds.values().copied().collect()
+    }
+
+    fn assert_valid_atlas(mesh: &SharedMesh, options: &UnwrapOptions) {
+        let uvs = mesh.uvs.as_ref().unwrap();
+        assert_eq!(uvs.len(), mesh.positions.len());
+        assert!(uvs.iter().all(|uv| uv.x >= 0.0 && uv.y >= 0.0 && uv.x <= 1.0 && uv.y <= 1.0));
+        for t in &mesh.triangles {
+            assert!(signed_area(&uvs[t[0] as usize], &uvs[t[1] as usize], &uvs[t[2] as usize]) > 0.0);
+        }
+        // Charts are apart by twice the padding
+        let gap = 2.0 * options.padding as f64 / options.resolution as f64;
+        let bounds = island_bounds(mesh);
+        for (i, a) in bounds.iter().enumerate() {
+            for b in &bounds[i + 1..] {
+                let separation = (b.0 - a.1).max().max((a.0 - b.1).max());
+                assert!(separation >= gap * 0.999);
+            }
+        }
+    }
+
+    #[test]
+    fn cube_charts() {
+        let mut mesh = cube(DVec3::zeros(), 2.0);
+        let options = UnwrapOptions { resolution: 256, padding: 8, ..Default::default() };
+        assert_eq!(mesh.unwrap_uvs(&options), 6);
+        // Each corner is split in 3
+        assert_eq!(mesh.positions.len(), 24);
+        assert_eq!(mesh.triangles.len(), 12);
+        assert_valid_atlas(&mesh, &options);
+
+        // Faces are squares of the same size
+        let uvs = mesh.uvs.as_ref().unwrap();
+        let areas: Vec<f64> = mesh.triangles.iter()
+            .map(|t| signed_area(&uvs[t[0] as usize], &uvs[t[1] as usize], &uvs[t[2] as usize]))
+            .collect();
+        assert!(areas.iter().all(|a| (a - areas[0]).abs() < 1e-9));
+    }
+
+    #[test]
+    fn sphere_is_conformal() {
+        let mut mesh = sphere(DVec3::zeros(), 1.0, 3);
+        let vertex_count = mesh.positions.len();
+        let options = UnwrapOptions::default();
+        let chart_count = mesh.unwrap_uvs(&options);
+        assert!((6..40).contains(&chart_count));
+        assert!(mesh.positions.len() > vertex_count);
+        assert_eq!(mesh.normals.as_ref().unwrap().len(), mesh.positions.len());
+        assert!(mesh.normals.as_ref().unwrap().iter().zip(&mesh.positions).all(|(n, p)| (n - p).magnitude() < 1e-12));
+        assert_valid_atlas(&mesh, &options);
+
+        // Angles are mostly preserved, the sphere being discretized and curved
+        let uvs = mesh.uvs.as_ref().unwrap();
+        let mut distortion = 0.0;
+        for t in &mesh.triangles {
+            for k in 0..3 {
+                let [a, b, c] = [k, (k + 1) % 3, (k + 2) % 3].map(|i| t[i] as usize);
+                let angle_3d = (mesh.positions[b] - mesh.positions[a]).angle(&(mesh.positions[c] - mesh.positions[a]));
+                let angle_uv = (uvs[b] - uvs[a]).angle(&(uvs[c] - uvs[a]));
+                distortion += (angle_3d - angle_uv).abs();
+            }
+        }
+        // About 0.15 radians with a planar projection of the charts
+        assert!(distortion / ((mesh.triangles.len() * 3) as f64) < 0.03);
+    }
+}
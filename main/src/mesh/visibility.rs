@@ -0,0 +1,140 @@
+// Hidden surface removal by ray casting: a triangle is kept if it can be seen from outside,
+// from at least one of a set of directions evenly distributed on the sphere.
+
+use nalgebra_glm as glm;
+use glm::{DVec3};
+use super::SharedMesh;
+use super::super::base::Ray;
+use super::super::spatial::Bvh;
+
+#[derive(Debug, Clone)]
+pub struct VisibilityOptions {
+    /// Number of view directions, evenly distributed on the sphere
+    pub direction_count: u32,
+    /// Number of points tested on each triangle (1 tests the centroid only)
+    pub samples_per_triangle: u32,
+    /// Test triangles on several threads. Requires the `parallel` feature, ignored otherwise.
+    pub parallel: bool,
+}
+
+impl Default for VisibilityOptions {
+    fn default() -> Self {
+        VisibilityOptions {
+            direction_count: 64,
+            samples_per_triangle: 4,
+            parallel: false,
+        }
+    }
+}
+
+impl SharedMesh {
+    /// For each triangle, whether it is visible from outside of the mesh from any of the sampled directions.
+    /// Both sides of triangles are considered.
+    pub fn visible_triangles(&self, options: &VisibilityOptions) -> Vec<bool> {
+        let bvh = Bvh::new(self);
+        let directions = sphere_directions(options.direction_count.max(1));
+        let bounds = self.bounding_box();
+        let epsilon = if bounds.is_valid() { bounds.size().magnitude() * 1e-7 } else { 0.0 };
+
+        let is_visible = |t: usize| -> bool {
+            let triangle = &self.triangles[t];
+            let [a, b, c] = [0, 1, 2].map(|k| self.positions[triangle[k] as usize]);
+            let normal = (b - a).cross(&(c - a));
+            if normal.magnitude_squared() == 0.0 {
+                return false;
+            }
+            let normal = normal.normalize();
+            sample_points(&a, &b, &c, options.samples_per_triangle).iter().any(|point| {
+                directions.iter().any(|direction| {
+                    let side = normal.dot(direction);
+                    if side == 0.0 {
+                        return false;
+                    }
+                    // Start slightly off the triangle, on the side the ray leaves from, to not hit it
+                    let origin = point + normal * (epsilon * side.signum()) + direction * epsilon;
+                    bvh.raycast(&Ray { origin, direction: *direction }, f64::MAX).is_none()
+                })
+            })
+        };
+
+        visible_map(self.triangles.len(), options.parallel, &is_visible)
+    }
+
+    /// Removes triangles that are not visible from outside, such as internal parts of assemblies
+    pub fn remove_hidden(&self, options: &VisibilityOptions) -> SharedMesh {
+        self.submesh(&self.visible_triangles(options))
+    }
+}
+
+#[cfg(feature = "parallel")]
+fn visible_map(count: usize, parallel: bool, is_visible: &(dyn Fn(usize) -> bool + Sync)) -> Vec<bool> {
+    use rayon::prelude::*;
+    if parallel {
+        (0..count).into_par_iter().map(is_visible).collect()
+    } else {
+        (0..count).map(is_visible).collect()
+    }
+}
+
+#[cfg(not(feature = "parallel"))]
+fn visible_map(count: usize, _parallel: bool, is_visible: &dyn Fn(usize) -> bool) -> Vec<bool> {
+    (0..count).map(is_visible).collect()
+}
+
+// Fibonacci lattice on the unit sphere
+fn sphere_directions(count: u32) -> Vec<DVec3> {
+    let golden_angle = std::f64::consts::PI * (3.0 - 5f64.sqrt());
+    (0..count)
+        .map(|i| {
+            let z = 1.0 - (2.0 * i as f64 + 1.0) / count as f64;
+            let radius = (1.0 - z * z).sqrt();
+            let theta = golden_angle * i as f64;
+            DVec3::new(radius * theta.cos(), radius * theta.sin(), z)
+        })
+        .collect()
+}
+
+// Centroid first, then points toward each corner
+fn sample_points(a: &DVec3, b: &DVec3, c: &DVec3, count: u32) -> Vec<DVec3> {
+    let centroid = (a + b + c) / 3.0;
+    let mut points = vec![centroid];
+    for k in 1..count.max(1) {
+        let corner = [a, b, c][(k as usize - 1) % 3];
+        // Further points get closer to the corners, while staying inside of the triangle
+        let t = 1.0 - 0.5f64.powi(((k - 1) / 3 + 1) as i32) * 0.9 - 0.05;
+        points.push(centroid + (corner - centroid) * t);
+    }
+    points
+}
+
+#[cfg(test)]
+mod visibility_tests {
+    use super::*;
+    use super::super::test_meshes::*;
+
+    #[test]
+    fn inner_cube_is_hidden() {
+        let outer = cube(DVec3::zeros(), 2.0);
+        let inner = cube(DVec3::new(0.2, 0., 0.), 0.5);
+        let mesh = SharedMesh::combine(outer, inner);
+
+        let visible = mesh.visible_triangles(&VisibilityOptions::default());
+        assert!(visible[..12].iter().all(|v| *v));
+        assert!(visible[12..].iter().all(|v| !*v));
+        assert_eq!(mesh.remove_hidden(&VisibilityOptions::default()).triangles.len(), 12);
+    }
+
+    #[test]
+    fn partially_covered_parts() {
+        // A plate lying on a box: the plate and every face of the box but the covered one remain
+        let plate = grid(4, 2.0);
+        let mut block = cube(DVec3::new(1., 1., -0.5), 1.0);
+        block.positions.iter_mut().for_each(|p| p.z = p.z.min(-1e-3));
+        let mesh = SharedMesh::combine(plate, block);
+
+        let visible = mesh.visible_triangles(&VisibilityOptions { direction_count: 128, ..Default::default() });
+        assert!(visible[..32].iter().all(|v| *v));
+        // The top face of the block (+z) touches the plate, seen only from the side through a thin gap
+        assert_eq!(visible[32..].iter().filter(|v| **v).count(), 10);
+    }
+}
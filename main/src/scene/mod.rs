@@ -2,13 +2,13 @@ pub mod scene;
 pub use scene::Scene as Scene;
 
 use slotmap::*;
-use std::fmt::{Display, Result, Formatter};
+use std::fmt::{self, Display, Formatter};
 new_key_type! {
     pub struct EntityId;
 }
 
 impl Display for EntityId {
-    fn fmt(&self, f: &mut Formatter) -> Result {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(f, "{}", self.0.as_ffi())
     }
 }
@@ -8,7 +8,8 @@ use std::cell::{RefCell, Ref, RefMut};
 use super::{EntityId};
 use nanomesh_macros::entity;
 
-type Arena<T> = DenseSlotMap<EntityId, T>;
+/// Entities of a given type. With the `serde` feature, arenas can be serialized (keys included) and restored with `Scene::set_entities`.
+pub type Arena<T> = DenseSlotMap<EntityId, T>;
 
 pub trait Entity {
     fn get_id() -> u64;
@@ -18,6 +19,7 @@ pub trait Entity {
 
 #[entity]
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Attachment {
     attached_entity_type: u64,
     attached_entity: EntityId,
@@ -78,6 +80,12 @@ impl Scene {
         }
     }
 
+    /// Replaces all entities of the given type, for instance with a deserialized arena.
+    /// Entity ids are kept, so attachments stay valid if the `Attachment` arena is restored as well.
+    pub fn set_entities<T: Entity+'static>(&mut self, entities: Arena<T>) {
+        self.entities_per_type.insert(T::get_id(), Box::new(RefCell::new(entities)));
+    }
+
     /// Attach two entities together. If entities were already attached, they will end up be attached as well.
     /// ⚠️ Avoid attaching several entities of the same type. This will result in undefined behaviour
     pub fn attach_entities<TA: Entity+'static, TB: Entity+'static>(&mut self, entity_id_a: EntityId, entity_id_b: EntityId) -> Result<(), ()> {
@@ -235,11 +243,13 @@ mod tests {
     use super::*;
 
     #[entity]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct MyEntityA {
         pub my_value: u32,
     }
 
     #[entity]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct MyEntityB {
         pub my_value: u32,
     }
@@ -382,4 +392,26 @@ mod tests {
             assert_eq!(3, result.my_value);
         }
     }
-}
\ No newline at end of file
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_entities() {
+        let mut scene = Scene::new();
+        let a_id = scene.add_entity(MyEntityA { attachement_id: None, my_value: 42 });
+        let b_id = scene.add_entity(MyEntityB { attachement_id: None, my_value: 69 });
+        scene.attach_entities::<MyEntityA, MyEntityB>(a_id, b_id).unwrap();
+
+        let entities_a = serde_json::to_string(&*scene.get_entities::<MyEntityA>().unwrap()).unwrap();
+        let entities_b = serde_json::to_string(&*scene.get_entities::<MyEntityB>().unwrap()).unwrap();
+        let attachments = serde_json::to_string(&*scene.get_entities::<Attachment>().unwrap()).unwrap();
+
+        let mut restored = Scene::new();
+        restored.set_entities::<MyEntityA>(serde_json::from_str(&entities_a).unwrap());
+        restored.set_entities::<MyEntityB>(serde_json::from_str(&entities_b).unwrap());
+        restored.set_entities::<Attachment>(serde_json::from_str(&attachments).unwrap());
+
+        let result_id = restored.get_attached_entity::<MyEntityA, MyEntityB>(a_id).unwrap();
+        assert_eq!(result_id, b_id);
+        assert_eq!(69, restored.get_entities::<MyEntityB>().unwrap().get(result_id).unwrap().my_value);
+    }
+}
@@ -0,0 +1,472 @@
+use nalgebra_glm as glm;
+use glm::{DVec3};
+use super::triangle;
+use super::super::base::{Box3, Ray, Sphere};
+use super::super::mesh::SharedMesh;
+
+const BIN_COUNT: usize = 16;
+const MAX_LEAF_SIZE: u32 = 4;
+// Relative cost of a ray / box test compared to a ray / triangle test
+const TRAVERSAL_COST: f64 = 1.0;
+const INTERSECTION_COST: f64 = 1.5;
+
+/// Bounding volume hierarchy over the triangles of a mesh, built with binned SAH splits.
+/// Triangle positions are copied, so the BVH does not borrow the mesh and can be stored on its own
+/// (for instance in a wasm-bindgen exported struct).
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    triangles: Vec<[DVec3; 3]>,
+    triangle_indices: Vec<u32>,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct BvhNode {
+    bounds: Box3,
+    // For leaves: first triangle. For interior nodes: index of the left child (right child is next).
+    start: u32,
+    // 0 for interior nodes
+    count: u32,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct RayHit {
+    /// Index of the triangle in the source mesh
+    pub triangle: u32,
+    pub distance: f64,
+    pub point: DVec3,
+    /// Barycentric coordinates of the hit relative to the triangle vertices
+    pub barycentric: DVec3,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ClosestPoint {
+    /// Index of the triangle in the source mesh
+    pub triangle: u32,
+    pub distance: f64,
+    pub point: DVec3,
+    /// Barycentric coordinates of the point relative to the triangle vertices
+    pub barycentric: DVec3,
+}
+
+struct Bin {
+    bounds: Box3,
+    count: u32,
+}
+
+impl Bvh {
+    pub fn new(shared_mesh: &SharedMesh) -> Self {
+        let triangles: Vec<[DVec3; 3]> = shared_mesh.triangles.iter()
+            .map(|t| [shared_mesh.positions[t[0] as usize], shared_mesh.positions[t[1] as usize], shared_mesh.positions[t[2] as usize]])
+            .collect();
+        Bvh::from_triangles(triangles)
+    }
+
+    pub fn from_triangles(triangles: Vec<[DVec3; 3]>) -> Self {
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(triangles.len() * 2 / MAX_LEAF_SIZE as usize + 1),
+            triangle_indices: (0..triangles.len() as u32).collect(),
+            triangles,
+        };
+
+        let bounds: Vec<Box3> = bvh.triangles.iter().map(|t| Box3::from_points(t.iter())).collect();
+        let centroids: Vec<DVec3> = bounds.iter().map(|b| b.center()).collect();
+
+        bvh.nodes.push(BvhNode { bounds: Box3::unfitted(), start: 0, count: bvh.triangles.len() as u32 });
+
+        let mut stack = vec![0usize];
+        while let Some(node_index) = stack.pop() {
+            let node = bvh.nodes[node_index];
+            let range = node.start as usize..(node.start + node.count) as usize;
+
+            let mut node_bounds = Box3::unfitted();
+            let mut centroid_bounds = Box3::unfitted();
+            for i in range.clone() {
+                let t = bvh.triangle_indices[i] as usize;
+                node_bounds = node_bounds.union(&bounds[t]);
+                centroid_bounds.encapsulate(&centroids[t]);
+            }
+            bvh.nodes[node_index].bounds = node_bounds;
+
+            if node.count <= MAX_LEAF_SIZE {
+                continue;
+            }
+
+            let split = match find_split(&bvh.triangle_indices[range.clone()], &bounds, &centroids, &node_bounds, &centroid_bounds) {
+                Some(split) => split,
+                None => continue,
+            };
+
+            // Partition triangles of the node around the split plane
+            let (axis, position) = split;
+            let slice = &mut bvh.triangle_indices[range.clone()];
+            let mut left = 0;
+            for i in 0..slice.len() {
+                if centroids[slice[i] as usize][axis] < position {
+                    slice.swap(i, left);
+                    left += 1;
+                }
+            }
+            if left == 0 || left == slice.len() {
+                continue;
+            }
+
+            let left_index = bvh.nodes.len();
+            bvh.nodes.push(BvhNode { bounds: Box3::unfitted(), start: node.start, count: left as u32 });
+            bvh.nodes.push(BvhNode { bounds: Box3::unfitted(), start: node.start + left as u32, count: node.count - left as u32 });
+            bvh.nodes[node_index].start = left_index as u32;
+            bvh.nodes[node_index].count = 0;
+
+            stack.push(left_index);
+            stack.push(left_index + 1);
+        }
+
+        // Store triangles in leaf order for better memory locality during traversal
+        bvh.triangles = bvh.triangle_indices.iter().map(|t| bvh.triangles[*t as usize]).collect();
+
+        bvh
+    }
+
+    pub fn bounds(&self) -> Box3 {
+        self.nodes[0].bounds
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    /// First intersection along the ray, within `max_distance`
+    pub fn raycast(&self, ray: &Ray, max_distance: f64) -> Option<RayHit> {
+        if self.triangles.is_empty() {
+            return None;
+        }
+
+        let inverse_direction = DVec3::new(1.0 / ray.direction.x, 1.0 / ray.direction.y, 1.0 / ray.direction.z);
+        let mut closest: Option<RayHit> = None;
+        let mut max_distance = max_distance;
+        let mut stack = vec![0u32];
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index as usize];
+            if ray_box_distance(ray, &inverse_direction, &node.bounds, max_distance).is_none() {
+                continue;
+            }
+            if node.count > 0 {
+                for i in node.start..node.start + node.count {
+                    let [a, b, c] = &self.triangles[i as usize];
+                    if let Some((distance, barycentric)) = triangle::intersect_ray(ray, a, b, c) {
+                        if distance >= 0.0 && distance <= max_distance {
+                            max_distance = distance;
+                            closest = Some(RayHit { triangle: self.triangle_indices[i as usize], distance, point: ray.at(distance), barycentric });
+                        }
+                    }
+                }
+            } else {
+                // Visit the nearest child first
+                let left = node.start;
+                let right = node.start + 1;
+                let distance_left = ray_box_distance(ray, &inverse_direction, &self.nodes[left as usize].bounds, max_distance);
+                let distance_right = ray_box_distance(ray, &inverse_direction, &self.nodes[right as usize].bounds, max_distance);
+                match (distance_left, distance_right) {
+                    (Some(l), Some(r)) => {
+                        if l < r {
+                            stack.push(right);
+                            stack.push(left);
+                        } else {
+                            stack.push(left);
+                            stack.push(right);
+                        }
+                    },
+                    (Some(_), None) => stack.push(left),
+                    (None, Some(_)) => stack.push(right),
+                    (None, None) => (),
+                }
+            }
+        }
+
+        closest
+    }
+
+    /// All intersections along the ray within `max_distance`, sorted by distance
+    pub fn raycast_all(&self, ray: &Ray, max_distance: f64) -> Vec<RayHit> {
+        let mut hits = Vec::new();
+        if self.triangles.is_empty() {
+            return hits;
+        }
+
+        let inverse_direction = DVec3::new(1.0 / ray.direction.x, 1.0 / ray.direction.y, 1.0 / ray.direction.z);
+        let mut stack = vec![0u32];
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index as usize];
+            if ray_box_distance(ray, &inverse_direction, &node.bounds, max_distance).is_none() {
+                continue;
+            }
+            if node.count > 0 {
+                for i in node.start..node.start + node.count {
+                    let [a, b, c] = &self.triangles[i as usize];
+                    if let Some((distance, barycentric)) = triangle::intersect_ray(ray, a, b, c) {
+                        if distance >= 0.0 && distance <= max_distance {
+                            hits.push(RayHit { triangle: self.triangle_indices[i as usize], distance, point: ray.at(distance), barycentric });
+                        }
+                    }
+                }
+            } else {
+                stack.push(node.start);
+                stack.push(node.start + 1);
+            }
+        }
+
+        hits.sort_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap());
+        hits
+    }
+
+    /// Closest point on the mesh surface, within `max_distance` of the given point
+    pub fn closest_point(&self, point: &DVec3, max_distance: f64) -> Option<ClosestPoint> {
+        if self.triangles.is_empty() {
+            return None;
+        }
+
+        let mut closest: Option<ClosestPoint> = None;
+        let mut best_distance_squared = max_distance * max_distance;
+        let mut stack = vec![0u32];
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index as usize];
+            if box_distance_squared(&node.bounds, point) > best_distance_squared {
+                continue;
+            }
+            if node.count > 0 {
+                for i in node.start..node.start + node.count {
+                    let [a, b, c] = &self.triangles[i as usize];
+                    let (closest_on_triangle, barycentric) = triangle::closest_point(point, a, b, c);
+                    let distance_squared = (closest_on_triangle - point).magnitude_squared();
+                    if distance_squared <= best_distance_squared {
+                        best_distance_squared = distance_squared;
+                        closest = Some(ClosestPoint {
+                            triangle: self.triangle_indices[i as usize],
+                            distance: distance_squared.sqrt(),
+                            point: closest_on_triangle,
+                            barycentric,
+                        });
+                    }
+                }
+            } else {
+                // Visit the nearest child first
+                let left = node.start;
+                let right = node.start + 1;
+                let distance_left = box_distance_squared(&self.nodes[left as usize].bounds, point);
+                let distance_right = box_distance_squared(&self.nodes[right as usize].bounds, point);
+                if distance_left < distance_right {
+                    stack.push(right);
+                    stack.push(left);
+                } else {
+                    stack.push(left);
+                    stack.push(right);
+                }
+            }
+        }
+
+        closest
+    }
+
+    /// Indices of triangles overlapping the box
+    pub fn overlap_box(&self, bounds: &Box3) -> Vec<u32> {
+        self.overlap(
+            |node_bounds| node_bounds.intersects(bounds),
+            |a, b, c| triangle::intersects_box(bounds, a, b, c))
+    }
+
+    /// Indices of triangles overlapping the sphere
+    pub fn overlap_sphere(&self, sphere: &Sphere) -> Vec<u32> {
+        let radius_squared = sphere.radius * sphere.radius;
+        self.overlap(
+            |node_bounds| box_distance_squared(node_bounds, &sphere.center) <= radius_squared,
+            |a, b, c| (triangle::closest_point(&sphere.center, a, b, c).0 - sphere.center).magnitude_squared() <= radius_squared)
+    }
+
+    fn overlap<N, T>(&self, node_test: N, triangle_test: T) -> Vec<u32>
+        where N: Fn(&Box3) -> bool, T: Fn(&DVec3, &DVec3, &DVec3) -> bool
+    {
+        let mut triangles = Vec::new();
+        if self.triangles.is_empty() {
+            return triangles;
+        }
+
+        let mut stack = vec![0u32];
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index as usize];
+            if !node_test(&node.bounds) {
+                continue;
+            }
+            if node.count > 0 {
+                for i in node.start..node.start + node.count {
+                    let [a, b, c] = &self.triangles[i as usize];
+                    if triangle_test(a, b, c) {
+                        triangles.push(self.triangle_indices[i as usize]);
+                    }
+                }
+            } else {
+                stack.push(node.start);
+                stack.push(node.start + 1);
+            }
+        }
+        triangles
+    }
+}
+
+// Binned surface area heuristic. Returns the split axis and position, or None if a leaf is cheaper.
+fn find_split(triangle_indices: &[u32], bounds: &[Box3], centroids: &[DVec3], node_bounds: &Box3, centroid_bounds: &Box3) -> Option<(usize, f64)> {
+    let leaf_cost = INTERSECTION_COST * triangle_indices.len() as f64;
+    let mut best: Option<(usize, f64)> = None;
+    let mut best_cost = leaf_cost;
+    let parent_area = surface_area(node_bounds);
+    if parent_area <= 0.0 {
+        return None;
+    }
+
+    for axis in [0usize, 1, 2] {
+        let min = centroid_bounds.min[axis];
+        let max = centroid_bounds.max[axis];
+        if max - min <= 0.0 {
+            continue;
+        }
+        let scale = BIN_COUNT as f64 / (max - min);
+
+        let mut bins: Vec<Bin> = (0..BIN_COUNT).map(|_| Bin { bounds: Box3::unfitted(), count: 0 }).collect();
+        for t in triangle_indices {
+            let bin = (((centroids[*t as usize][axis] - min) * scale) as usize).min(BIN_COUNT - 1);
+            bins[bin].count += 1;
+            bins[bin].bounds = bins[bin].bounds.union(&bounds[*t as usize]);
+        }
+
+        // Sweep from the right to gather right side areas and counts
+        let mut right_areas = [0.0; BIN_COUNT];
+        let mut right_counts = [0u32; BIN_COUNT];
+        let mut accumulated = Box3::unfitted();
+        let mut count = 0;
+        for i in (1..BIN_COUNT).rev() {
+            accumulated = accumulated.union(&bins[i].bounds);
+            count += bins[i].count;
+            right_areas[i] = surface_area(&accumulated);
+            right_counts[i] = count;
+        }
+
+        let mut accumulated = Box3::unfitted();
+        let mut count = 0;
+        for i in 0..BIN_COUNT - 1 {
+            accumulated = accumulated.union(&bins[i].bounds);
+            count += bins[i].count;
+            if count == 0 || right_counts[i + 1] == 0 {
+                continue;
+            }
+            let cost = TRAVERSAL_COST + INTERSECTION_COST
+                * (surface_area(&accumulated) * count as f64 + right_areas[i + 1] * right_counts[i + 1] as f64) / parent_area;
+            if cost < best_cost {
+                best_cost = cost;
+                best = Some((axis, min + (i + 1) as f64 / scale));
+            }
+        }
+    }
+
+    best
+}
+
+fn surface_area(bounds: &Box3) -> f64 {
+    if !bounds.is_valid() {
+        return 0.0;
+    }
+    let size = bounds.size();
+    2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+}
+
+// Slab test, returns the entry distance if the ray hits the box within max_distance
+fn ray_box_distance(ray: &Ray, inverse_direction: &DVec3, bounds: &Box3, max_distance: f64) -> Option<f64> {
+    let mut t_min = 0.0f64;
+    let mut t_max = max_distance;
+    for axis in 0..3 {
+        let t1 = (bounds.min[axis] - ray.origin[axis]) * inverse_direction[axis];
+        let t2 = (bounds.max[axis] - ray.origin[axis]) * inverse_direction[axis];
+        // NaN (0 * inf) when the origin lies on a slab boundary with a parallel ray: min/max ignore it
+        t_min = t_min.max(t1.min(t2));
+        t_max = t_max.min(t1.max(t2));
+    }
+    if t_min <= t_max {
+        Some(t_min)
+    } else {
+        None
+    }
+}
+
+fn box_distance_squared(bounds: &Box3, point: &DVec3) -> f64 {
+    let clamped = glm::clamp_vec(point, &bounds.min, &bounds.max);
+    (clamped - point).magnitude_squared()
+}
+
+#[cfg(test)]
+mod bvh_tests {
+    use super::*;
+    use super::super::super::mesh::test_meshes::*;
+
+    #[test]
+    fn raycast_sphere() {
+        let mesh = sphere(DVec3::zeros(), 1.0, 4);
+        let bvh = Bvh::new(&mesh);
+
+        let ray = Ray::new(DVec3::new(-5., 0.01, 0.02), DVec3::new(1., 0., 0.));
+        let hit = bvh.raycast(&ray, f64::MAX).unwrap();
+        assert!((hit.distance - 4.0).abs() < 0.01);
+        assert!(hit.point.x < 0.0);
+
+        let hits = bvh.raycast_all(&ray, f64::MAX);
+        assert_eq!(hits.len(), 2);
+        assert!((hits[1].distance - 6.0).abs() < 0.01);
+
+        assert!(bvh.raycast(&ray, 3.0).is_none());
+        assert!(bvh.raycast(&Ray::new(DVec3::new(-5., 2., 0.), DVec3::new(1., 0., 0.)), f64::MAX).is_none());
+    }
+
+    #[test]
+    fn raycast_matches_brute_force() {
+        let mesh = sphere(DVec3::new(0.3, -0.2, 0.1), 2.0, 3);
+        let bvh = Bvh::new(&mesh);
+        for i in 0..50 {
+            let angle = i as f64 * 0.37;
+            let ray = Ray::new(DVec3::new(angle.cos() * 5., angle.sin() * 5., (angle * 0.5).sin()), DVec3::new(-angle.cos(), -angle.sin(), 0.05));
+            let brute_force = mesh.triangles.iter()
+                .filter_map(|t| triangle::intersect_ray(&ray, &mesh.positions[t[0] as usize], &mesh.positions[t[1] as usize], &mesh.positions[t[2] as usize]))
+                .map(|x| x.0)
+                .filter(|d| *d >= 0.0)
+                .fold(f64::MAX, f64::min);
+            let hit = bvh.raycast(&ray, f64::MAX).map(|h| h.distance).unwrap_or(f64::MAX);
+            assert_eq!(hit, brute_force);
+        }
+    }
+
+    #[test]
+    fn closest_point_on_cube() {
+        let mesh = cube(DVec3::zeros(), 2.0);
+        let bvh = Bvh::new(&mesh);
+
+        let closest = bvh.closest_point(&DVec3::new(3., 0.5, 0.25), f64::MAX).unwrap();
+        assert!((closest.distance - 2.0).abs() < 1e-9);
+        assert!((closest.point - DVec3::new(1., 0.5, 0.25)).magnitude() < 1e-9);
+
+        assert!(bvh.closest_point(&DVec3::new(3., 0.5, 0.25), 1.0).is_none());
+    }
+
+    #[test]
+    fn overlaps() {
+        let mesh = cube(DVec3::zeros(), 2.0);
+        let bvh = Bvh::new(&mesh);
+
+        // Box touching only the +x face
+        let bounds = Box3::new(DVec3::new(0.9, -0.1, -0.1), DVec3::new(1.1, 0.1, 0.1));
+        let mut triangles = bvh.overlap_box(&bounds);
+        triangles.sort();
+        assert_eq!(triangles, vec![10, 11]);
+
+        // Sphere inside the cube without touching it
+        assert!(bvh.overlap_sphere(&Sphere::new(DVec3::zeros(), 0.5)).is_empty());
+        assert_eq!(bvh.overlap_sphere(&Sphere::new(DVec3::zeros(), 2.0)).len(), 12);
+    }
+}
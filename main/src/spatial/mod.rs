@@ -0,0 +1,4 @@
+pub mod triangle;
+
+pub mod bvh;
+pub use bvh::{Bvh, RayHit, ClosestPoint};
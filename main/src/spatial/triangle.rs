@@ -0,0 +1,123 @@
+// Geometric queries on single triangles
+
+use nalgebra_glm as glm;
+use glm::{DVec3};
+use super::super::base::{Box3, Ray};
+
+/// Möller–Trumbore ray / triangle intersection. Both sides of the triangle are considered.
+/// Returns the distance along the ray and the barycentric coordinates (of a, b and c).
+pub fn intersect_ray(ray: &Ray, a: &DVec3, b: &DVec3, c: &DVec3) -> Option<(f64, DVec3)> {
+    let ab = b - a;
+    let ac = c - a;
+    let p = ray.direction.cross(&ac);
+    let det = ab.dot(&p);
+    if det.abs() < 1e-300 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let s = ray.origin - a;
+    let u = s.dot(&p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(&ab);
+    let v = ray.direction.dot(&q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let distance = ac.dot(&q) * inv_det;
+    Some((distance, DVec3::new(1.0 - u - v, u, v)))
+}
+
+/// Closest point on a triangle ("Real-Time Collision Detection", Ericson, 5.1.5).
+/// Returns the point and its barycentric coordinates (of a, b and c).
+pub fn closest_point(p: &DVec3, a: &DVec3, b: &DVec3, c: &DVec3) -> (DVec3, DVec3) {
+    let ab = b - a;
+    let ac = c - a;
+    let ap = p - a;
+    let d1 = ab.dot(&ap);
+    let d2 = ac.dot(&ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return (*a, DVec3::new(1., 0., 0.));
+    }
+
+    let bp = p - b;
+    let d3 = ab.dot(&bp);
+    let d4 = ac.dot(&bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return (*b, DVec3::new(0., 1., 0.));
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        let v = d1 / (d1 - d3);
+        return (a + ab * v, DVec3::new(1.0 - v, v, 0.));
+    }
+
+    let cp = p - c;
+    let d5 = ab.dot(&cp);
+    let d6 = ac.dot(&cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return (*c, DVec3::new(0., 0., 1.));
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        let w = d2 / (d2 - d6);
+        return (a + ac * w, DVec3::new(1.0 - w, 0., w));
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+        return (b + (c - b) * w, DVec3::new(0., 1.0 - w, w));
+    }
+
+    let denom = 1.0 / (va + vb + vc);
+    let v = vb * denom;
+    let w = vc * denom;
+    (a + ab * v + ac * w, DVec3::new(1.0 - v - w, v, w))
+}
+
+/// Separating axis test between a triangle and an axis aligned box
+/// ("Fast 3D Triangle-Box Overlap Testing", Akenine-Möller)
+pub fn intersects_box(bounds: &Box3, a: &DVec3, b: &DVec3, c: &DVec3) -> bool {
+    let center = bounds.center();
+    let half = bounds.size() / 2.0;
+    let v = [a - center, b - center, c - center];
+    let edges = [v[1] - v[0], v[2] - v[1], v[0] - v[2]];
+
+    let separated = |axis: &DVec3| -> bool {
+        let p0 = v[0].dot(axis);
+        let p1 = v[1].dot(axis);
+        let p2 = v[2].dot(axis);
+        let r = half.x * axis.x.abs() + half.y * axis.y.abs() + half.z * axis.z.abs();
+        p0.min(p1).min(p2) > r || p0.max(p1).max(p2) < -r
+    };
+
+    // Box face normals
+    for axis in 0..3 {
+        let min = v[0][axis].min(v[1][axis]).min(v[2][axis]);
+        let max = v[0][axis].max(v[1][axis]).max(v[2][axis]);
+        if min > half[axis] || max < -half[axis] {
+            return false;
+        }
+    }
+
+    // Triangle normal
+    if separated(&edges[0].cross(&edges[1])) {
+        return false;
+    }
+
+    // Cross products of edges
+    let units = [DVec3::x(), DVec3::y(), DVec3::z()];
+    for edge in &edges {
+        for unit in &units {
+            if separated(&unit.cross(edge)) {
+                return false;
+            }
+        }
+    }
+
+    true
+}
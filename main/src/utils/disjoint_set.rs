@@ -0,0 +1,54 @@
+/// Union-find with path halving and union by size
+pub struct DisjointSet {
+    parents: Vec<u32>,
+    sizes: Vec<u32>,
+}
+
+impl DisjointSet {
+    pub fn new(len: usize) -> Self {
+        DisjointSet {
+            parents: (0..len as u32).collect(),
+            sizes: vec![1; len],
+        }
+    }
+
+    pub fn find(&mut self, mut x: u32) -> u32 {
+        while self.parents[x as usize] != x {
+            let grandparent = self.parents[self.parents[x as usize] as usize];
+            self.parents[x as usize] = grandparent;
+            x = grandparent;
+        }
+        x
+    }
+
+    /// Merges the sets of a and b. Returns false if they were already in the same set.
+    pub fn union(&mut self, a: u32, b: u32) -> bool {
+        let mut a = self.find(a);
+        let mut b = self.find(b);
+        if a == b {
+            return false;
+        }
+        if self.sizes[a as usize] < self.sizes[b as usize] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parents[b as usize] = a;
+        self.sizes[a as usize] += self.sizes[b as usize];
+        true
+    }
+
+    /// Relabels sets with consecutive ids, in order of first appearance. Returns the labels and the number of sets.
+    pub fn labels(&mut self) -> (Vec<u32>, u32) {
+        let mut root_label = vec![u32::MAX; self.parents.len()];
+        let mut labels = Vec::with_capacity(self.parents.len());
+        let mut count = 0;
+        for i in 0..self.parents.len() as u32 {
+            let root = self.find(i) as usize;
+            if root_label[root] == u32::MAX {
+                root_label[root] = count;
+                count += 1;
+            }
+            labels.push(root_label[root]);
+        }
+        (labels, count)
+    }
+}
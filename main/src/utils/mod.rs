@@ -3,3 +3,6 @@ pub mod r#unsafe;
 
 pub mod simple_hasher;
 pub use simple_hasher::SimpleHasher as SimpleHasher;
+
+pub mod disjoint_set;
+pub use disjoint_set::DisjointSet as DisjointSet;
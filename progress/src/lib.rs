@@ -0,0 +1,74 @@
+// Progress reporting and cancellation of long operations (decimation, STEP parsing and triangulation, readers)
+
+use std::fmt::{self, Display, Formatter};
+
+/// Receives the progress of a long operation, and may cancel it
+pub trait Progress {
+    /// Called from time to time with the completed fraction of the operation, from 0 to 1.
+    /// Returning false cancels the operation.
+    fn report(&mut self, progress: f32) -> bool;
+}
+
+impl<F: FnMut(f32) -> bool> Progress for F {
+    fn report(&mut self, progress: f32) -> bool {
+        self(progress)
+    }
+}
+
+/// Ignores progress and never cancels
+#[derive(Debug, Copy, Clone, Default)]
+pub struct NoProgress;
+
+impl Progress for NoProgress {
+    fn report(&mut self, _progress: f32) -> bool {
+        true
+    }
+}
+
+/// Maps the progress of one step of a longer operation onto the range `start..end` of the whole operation
+pub struct ProgressRange<'a> {
+    progress: &'a mut dyn Progress,
+    start: f32,
+    end: f32,
+}
+
+impl<'a> ProgressRange<'a> {
+    pub fn new(progress: &'a mut dyn Progress, start: f32, end: f32) -> Self {
+        ProgressRange { progress, start, end }
+    }
+}
+
+impl Progress for ProgressRange<'_> {
+    fn report(&mut self, progress: f32) -> bool {
+        self.progress.report(self.start + (self.end - self.start) * progress.clamp(0.0, 1.0))
+    }
+}
+
+/// Error returned by operations cancelled through their `Progress`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl Display for Cancelled {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "operation cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+#[cfg(test)]
+mod progress_tests {
+    use super::*;
+
+    #[test]
+    fn ranges() {
+        let mut reported = Vec::new();
+        let mut progress = |p: f32| { reported.push(p); p < 0.7 };
+        let mut range = ProgressRange::new(&mut progress, 0.5, 0.75);
+        assert!(range.report(0.0));
+        assert!(range.report(0.5));
+        assert!(!range.report(2.0));
+        assert_eq!(reported, vec![0.5, 0.625, 0.75]);
+        assert!(NoProgress.report(1.0));
+    }
+}
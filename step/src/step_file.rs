@@ -4,19 +4,32 @@ use log::warn;
 #[cfg(feature = "rayon")]
 use rayon::prelude::*;
 
+use nanomesh_progress::{Cancelled, NoProgress, Progress};
+
 use crate::{
     ap214::Entity,
     id::Id,
     parse::{parse_entity_decl, parse_entity_fallback},
 };
 
+/// Number of blocks parsed between progress reports
+const PROGRESS_CHUNK: usize = 4096;
+
 #[derive(Debug)]
 pub struct StepFile<'a>(pub Vec<Entity<'a>>);
 impl<'a> StepFile<'a> {
     /// Parses a STEP file from a raw array of bytes
     /// `data` must be preprocessed by [`strip_flatten`] first
     pub fn parse(data: &'a [u8]) -> Self {
+        Self::parse_with_progress(data, &mut NoProgress)
+            .expect("parsing without progress can't be cancelled")
+    }
 
+    /// Parses a STEP file as [`parse`] does, reporting the fraction of
+    /// entities parsed so far
+    pub fn parse_with_progress(data: &'a [u8], progress: &mut dyn Progress)
+        -> Result<Self, Cancelled>
+    {
         let blocks = Self::into_blocks(&data);
         let data_start = blocks.iter()
             .position(|b| b == b"DATA;")
@@ -28,26 +41,38 @@ impl<'a> StepFile<'a> {
 
         // Parse every block, accumulating a Vec of Results.  We parse in
         // single-threaded mode in WASM builds, because there's no thread
-        // pool.
-        let block_iter = {
-            let block_slice = &blocks[data_start..data_end];
-            #[cfg(feature = "rayon")]
-            { block_slice.par_iter() }
-            #[cfg(not(feature = "rayon"))]
-            { block_slice.iter() }
-        };
-
-        let parsed: Vec<(usize, Entity)> = block_iter
-            .filter_map(|b| parse_entity_decl(*b)
-                .or_else(|e| {
-                    warn!("Failed to parse {}: {:?}",
-                        std::str::from_utf8(b).unwrap_or("[INVALID UTF-8]"),
-                              e);
-                    parse_entity_fallback(*b)
-                })
-                .ok())
-            .map(|b| b.1)
-            .collect();
+        // pool.  Blocks are parsed in chunks, so that progress is reported
+        // (and cancellation checked) between them.
+        let block_slice = &blocks[data_start..data_end];
+        let mut parsed: Vec<(usize, Entity)> = Vec::new();
+        for (i, chunk) in block_slice.chunks(PROGRESS_CHUNK).enumerate() {
+            let block_iter = {
+                #[cfg(feature = "rayon")]
+                { chunk.par_iter() }
+                #[cfg(not(feature = "rayon"))]
+                { chunk.iter() }
+            };
+
+            let chunk_parsed: Vec<(usize, Entity)> = block_iter
+                .filter_map(|b| parse_entity_decl(*b)
+                    .or_else(|e| {
+                        warn!("Failed to parse {}: {:?}",
+                            std::str::from_utf8(b).unwrap_or("[INVALID UTF-8]"),
+                                  e);
+                        parse_entity_fallback(*b)
+                    })
+                    .ok())
+                .map(|b| b.1)
+                .collect();
+            parsed.extend(chunk_parsed);
+
+            let done = (i + 1) * PROGRESS_CHUNK;
+            if !progress.report(done.min(block_slice.len()) as f32
+                                / block_slice.len() as f32)
+            {
+                return Err(Cancelled);
+            }
+        }
 
         // Awkward construction because `Entity` is not `Clone`
         let max_id = parsed.iter().map(|b| b.0).max().unwrap_or(0);
@@ -59,7 +84,7 @@ impl<'a> StepFile<'a> {
             out[p.0] = p.1;
         }
 
-        Self(out)
+        Ok(Self(out))
     }
 
     /// Flattens a STEP file, removing comments and whitespace
@@ -7,6 +7,8 @@ use log::{info, warn, error};
 
 #[cfg(feature = "rayon")]
 use rayon::prelude::*;
+#[cfg(feature = "rayon")]
+use std::sync::{atomic::{AtomicBool, Ordering}, mpsc};
 
 use step::{
     ap214, ap214::*, step_file::{FromEntity, StepFile}, id::Id, ap214::Entity,
@@ -20,6 +22,7 @@ use crate::{
 use nurbs::{BSplineSurface, SampledCurve, SampledSurface, NURBSSurface, KnotVector};
 
 use nanomesh::mesh::SharedMesh;
+use nanomesh::progress::{Cancelled, NoProgress, Progress};
 
 const SAVE_DEBUG_SVGS: bool = false;
 const SAVE_PANIC_SVGS: bool = false;
@@ -65,6 +68,19 @@ fn transform_stack_roots<'a>(transform_stack: &TransformStack<'a>) -> Vec<Repres
 }
 
 pub fn triangulate(s: &StepFile) -> (SharedMesh, Stats) {
+    triangulate_with_progress(s, &mut NoProgress)
+        .expect("triangulation without progress can't be cancelled")
+}
+
+/// Triangulates the file as [`triangulate`] does, reporting the fraction of
+/// solids triangulated so far.  With the `rayon` feature, solids are
+/// triangulated in parallel, and those still running when cancelled are
+/// finished before returning.  Called from a rayon worker thread, solids are
+/// triangulated one after the other on that thread, since waiting there for
+/// the pool to report progress could deadlock it.
+pub fn triangulate_with_progress(s: &StepFile, progress: &mut dyn Progress)
+    -> Result<(SharedMesh, Stats), Cancelled>
+{
     let styled_items: Vec<_> = s.0.iter()
         .filter_map(|e| MechanicalDesignGeometricPresentationRepresentation_::try_from_entity(e))
         .flat_map(|m| m.items.iter())
@@ -158,19 +174,10 @@ pub fn triangulate(s: &StepFile) -> (SharedMesh, Stats) {
             .for_each(|i| to_mesh.entry(i).or_default().push(DMat4::identity()));
     }
 
-    let (to_mesh_iter, empty) = {
-        #[cfg(feature = "rayon")]
-        { (to_mesh.par_iter(), || (SharedMesh::default(), Stats::default())) }
-        #[cfg(not(feature = "rayon"))]
-        { (to_mesh.iter(), (SharedMesh::default(), Stats::default())) }
-    };
-    let mesh_fold = to_mesh_iter
-        .fold(
-            // Empty constructor
-            empty,
-
-            // Fold operation
-            |(mut mesh, mut stats), (id, mats)| {
+    // Fold operation, triangulating one solid once for each of its transforms
+    let triangulate_solid =
+            |(mut mesh, mut stats): (SharedMesh, Stats),
+             (id, mats): (&Id<_>, &Vec<DMat4>)| {
                 let v_start = mesh.positions.len();
                 let t_start = mesh.triangles.len();
                 match &s[*id] {
@@ -236,23 +243,82 @@ pub fn triangulate(s: &StepFile) -> (SharedMesh, Stats) {
                     mesh.colors.as_mut().unwrap()[v] = color;
                 }
                 (mesh, stats)
-            });
+            };
+
+    let triangulate_serially = |progress: &mut dyn Progress| {
+        let mut result = (SharedMesh::default(), Stats::default());
+        for (i, solid) in to_mesh.iter().enumerate() {
+            result = triangulate_solid(result, solid);
+            // The last one is left to the final report, which can't cancel
+            if i + 1 < to_mesh.len() && !progress.report((i + 1) as f32 / to_mesh.len() as f32) {
+                return Err(Cancelled);
+            }
+        }
+        Ok(result)
+    };
 
+    if !progress.report(0.0) {
+        return Err(Cancelled);
+    }
     let (mesh, stats) = {
         #[cfg(feature = "rayon")]
-        { mesh_fold.reduce(empty,
-                |a, b| (Mesh::combine(a.0, b.0), Stats::combine(a.1, b.1))) }
-        #[cfg(not(feature = "rayon"))]
-        {
-            mesh_fold
+        if rayon::current_thread_index().is_some() {
+            triangulate_serially(progress)?
+        } else {
+            // Solids are triangulated on the thread pool while this thread
+            // reports progress, as each of them is done.  Once cancelled,
+            // solids that aren't started yet are skipped.
+            let (cancelled, skipped) = (AtomicBool::new(false), AtomicBool::new(false));
+            let (done_sender, done) = mpsc::channel();
+            let mut result = None;
+            rayon::in_place_scope(|scope| {
+                let (cancelled, skipped, result) = (&cancelled, &skipped, &mut result);
+                let (to_mesh, triangulate_solid) = (&to_mesh, &triangulate_solid);
+                scope.spawn(move |_| {
+                    let empty = || (SharedMesh::default(), Stats::default());
+                    *result = Some(to_mesh.par_iter()
+                        .map_with(done_sender, |done_sender, solid| {
+                            if cancelled.load(Ordering::Relaxed) {
+                                skipped.store(true, Ordering::Relaxed);
+                                return empty();
+                            }
+                            let triangulated = triangulate_solid(empty(), solid);
+                            let _ = done_sender.send(());
+                            triangulated
+                        })
+                        .reduce(empty,
+                            |a, b| (SharedMesh::combine(a.0, b.0), Stats::combine(a.1, b.1))));
+                });
+
+                // Ends early if every sender is dropped, when all solids are done.
+                // The last one is left to the final report, which can't cancel.
+                for i in 1..to_mesh.len() {
+                    if done.recv().is_err() {
+                        break;
+                    }
+                    if !progress.report(i as f32 / to_mesh.len() as f32) {
+                        cancelled.store(true, Ordering::Relaxed);
+                        break;
+                    }
+                }
+            });
+            // Solids may all be done by the time it is cancelled
+            if skipped.into_inner() {
+                return Err(Cancelled);
+            }
+            result.expect("solids are triangulated within the scope")
         }
+        #[cfg(not(feature = "rayon"))]
+        triangulate_serially(progress)?
     };
+    // Triangulation is done, so cancelling no longer has any effect
+    progress.report(1.0);
 
     info!("num_shells: {}", stats.num_shells);
     info!("num_faces: {}", stats.num_faces);
     info!("num_errors: {}", stats.num_errors);
     info!("num_panics: {}", stats.num_panics);
-    (mesh, stats)
+    Ok((mesh, stats))
 }
 
 fn item_defined_transformation(s: &StepFile, t: Id<ItemDefinedTransformation_>) -> DMat4 {
@@ -44,14 +44,18 @@ pub fn read_obj(parameters: &Parameters, bytes: &[u8]) -> Vec<u8> {
   //let mut reader = BufReader::new(slice);
 
   use step::step_file::StepFile;
-  use triangulate::triangulate::triangulate; // lol
+  use triangulate::triangulate::triangulate_with_progress; // lol
+
+  // Nothing can cancel from the JS side while we run, so progress is only reported
+  let mut parsing = |p: f32| { set_progress(0.25 + 0.25 * p, "Parsing..."); true };
+  let mut tesselating = |p: f32| { set_progress(0.5 + 0.25 * p, "Tesselating..."); true };
 
   set_progress(0.25, "Parsing...");
   let flat = StepFile::strip_flatten(bytes);
-  let step = StepFile::parse(&flat);
+  let step = StepFile::parse_with_progress(&flat, &mut parsing).unwrap();
 
   set_progress(0.5, "Tesselating...");
-  let (mesh, _stats) = triangulate(&step);
+  let (mesh, _stats) = triangulate_with_progress(&step, &mut tesselating).unwrap();
 
   set_progress(0.75, "Writing...");
   let mut result = Vec::new();
@@ -62,4 +66,45 @@ pub fn read_obj(parameters: &Parameters, bytes: &[u8]) -> Vec<u8> {
 
   set_progress(1., "Done!");
   return result;
-}
\ No newline at end of file
+}
+
+/// Spatial queries (picking, snapping) on a mesh read from OBJ bytes
+#[wasm_bindgen]
+pub struct MeshQuery {
+  bvh: nanomesh::spatial::Bvh,
+}
+
+#[wasm_bindgen]
+impl MeshQuery {
+  #[wasm_bindgen(constructor)]
+  pub fn new(obj_bytes: &[u8]) -> MeshQuery {
+    let mut reader = std::io::BufReader::new(obj_bytes);
+    let mesh = nanomesh::io::obj::read(&mut reader);
+    MeshQuery { bvh: nanomesh::spatial::Bvh::new(&mesh) }
+  }
+
+  /// Distance to the first hit along the ray, if any
+  pub fn raycast(&self, ox: f64, oy: f64, oz: f64, dx: f64, dy: f64, dz: f64) -> Option<f64> {
+    use nanomesh::base::Ray;
+    use nalgebra_glm::DVec3;
+    let ray = Ray::new(DVec3::new(ox, oy, oz), DVec3::new(dx, dy, dz));
+    self.bvh.raycast(&ray, f64::MAX).map(|hit| hit.distance)
+  }
+
+  /// Index of the first triangle hit along the ray, or -1
+  pub fn pick(&self, ox: f64, oy: f64, oz: f64, dx: f64, dy: f64, dz: f64) -> i32 {
+    use nanomesh::base::Ray;
+    use nalgebra_glm::DVec3;
+    let ray = Ray::new(DVec3::new(ox, oy, oz), DVec3::new(dx, dy, dz));
+    self.bvh.raycast(&ray, f64::MAX).map(|hit| hit.triangle as i32).unwrap_or(-1)
+  }
+
+  /// Closest point on the mesh as [x, y, z, distance], or an empty array if the mesh is empty
+  pub fn closest_point(&self, x: f64, y: f64, z: f64) -> Vec<f64> {
+    use nalgebra_glm::DVec3;
+    match self.bvh.closest_point(&DVec3::new(x, y, z), f64::MAX) {
+      Some(closest) => vec![closest.point.x, closest.point.y, closest.point.z, closest.distance],
+      None => Vec::new(),
+    }
+  }
+}